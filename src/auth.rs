@@ -1,6 +1,7 @@
-use std::io;
+use std::{env, io};
 
 use anyhow::Result;
+use base64::prelude::{Engine, BASE64_STANDARD};
 use regex_lite::Regex;
 use reqwest::blocking::{Request, Response};
 use reqwest::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
@@ -9,6 +10,7 @@ use reqwest::StatusCode;
 use crate::cli::AuthType;
 use crate::middleware::{Context, Middleware};
 use crate::netrc;
+use crate::ntlm;
 use crate::utils::clone_request;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -16,6 +18,9 @@ pub enum Auth {
     Bearer(String),
     Basic(String, Option<String>),
     Digest(String, String),
+    AwsSigv4(String, String),
+    Ntlm(String, String),
+    OAuth2(String, String),
 }
 
 impl Auth {
@@ -33,6 +38,38 @@ impl Auth {
                 ))
             }
             AuthType::Bearer => Ok(Auth::Bearer(auth.into())),
+            AuthType::Ntlm => {
+                let (username, password) = parse_auth(auth, host)?;
+                Ok(Auth::Ntlm(username, password.unwrap_or_else(|| "".into())))
+            }
+            AuthType::OAuth2 => {
+                let (client_id, client_secret) = parse_auth(auth, host)?;
+                Ok(Auth::OAuth2(
+                    client_id,
+                    client_secret.unwrap_or_else(|| "".into()),
+                ))
+            }
+            AuthType::AwsSigv4 => {
+                let (access_key, secret_key) = parse_auth(auth, host)?;
+                Ok(Auth::AwsSigv4(
+                    access_key,
+                    secret_key.unwrap_or_else(|| "".into()),
+                ))
+            }
+        }
+    }
+
+    pub fn from_env(auth_type: AuthType) -> Option<Auth> {
+        match auth_type {
+            AuthType::AwsSigv4 => Some(Auth::AwsSigv4(
+                env::var("AWS_ACCESS_KEY_ID").ok()?,
+                env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            )),
+            AuthType::Basic
+            | AuthType::Bearer
+            | AuthType::Digest
+            | AuthType::Ntlm
+            | AuthType::OAuth2 => None,
         }
     }
 
@@ -41,6 +78,9 @@ impl Auth {
             AuthType::Basic => Some(Auth::Basic(entry.login?, Some(entry.password))),
             AuthType::Bearer => Some(Auth::Bearer(entry.password)),
             AuthType::Digest => Some(Auth::Digest(entry.login?, entry.password)),
+            AuthType::AwsSigv4 => Some(Auth::AwsSigv4(entry.login?, entry.password)),
+            AuthType::Ntlm => Some(Auth::Ntlm(entry.login?, entry.password)),
+            AuthType::OAuth2 => Some(Auth::OAuth2(entry.login?, entry.password)),
         }
     }
 }
@@ -97,6 +137,58 @@ impl<'a> Middleware for DigestAuthMiddleware<'a> {
     }
 }
 
+pub struct NtlmAuthMiddleware<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+impl<'a> NtlmAuthMiddleware<'a> {
+    pub fn new(username: &'a str, password: &'a str) -> Self {
+        NtlmAuthMiddleware { username, password }
+    }
+}
+
+impl<'a> Middleware for NtlmAuthMiddleware<'a> {
+    fn handle(&mut self, mut ctx: Context, mut request: Request) -> Result<Response> {
+        // NTLM authenticates the connection rather than the request, so all
+        // of this relies on the connection being kept alive in between
+        let mut negotiate_request = clone_request(&mut request)?;
+        negotiate_request.headers_mut().insert(
+            AUTHORIZATION,
+            ntlm_header_value(&ntlm::negotiate_message())?,
+        );
+        let mut response = self.next(&mut ctx, negotiate_request)?;
+        let challenge = response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok()?.strip_prefix("NTLM "))
+            .next()
+            .map(|challenge| BASE64_STANDARD.decode(challenge.trim()));
+        match challenge {
+            Some(challenge) if response.status() == StatusCode::UNAUTHORIZED => {
+                let challenge = ntlm::parse_challenge(&challenge?)?;
+                let answer = ntlm::authenticate_message(&challenge, self.username, self.password);
+                request
+                    .headers_mut()
+                    .insert(AUTHORIZATION, ntlm_header_value(&answer)?);
+                self.print(&mut ctx, &mut response, &mut request)?;
+                // Drain the body so that the connection can be reused
+                response.copy_to(&mut io::sink())?;
+                Ok(self.next(&mut ctx, request)?)
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+fn ntlm_header_value(message: &[u8]) -> Result<HeaderValue> {
+    Ok(HeaderValue::from_str(&format!(
+        "NTLM {}",
+        BASE64_STANDARD.encode(message)
+    ))?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
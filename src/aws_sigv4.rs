@@ -0,0 +1,393 @@
+//! AWS Signature Version 4 request signing.
+//!
+//! See https://docs.aws.amazon.com/IAM/latest/UserGuide/create-signed-request.html
+
+use std::env::var;
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use reqwest::blocking::Request;
+use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::cli::AwsSigv4Scope;
+use crate::utils::{percent_decode, test_mode};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+pub struct Credentials<'a> {
+    pub access_key: &'a str,
+    pub secret_key: &'a str,
+    pub session_token: Option<&'a str>,
+}
+
+/// Sign a request in place by adding the X-Amz-* and Authorization headers.
+///
+/// Unless `scope` gives them, the region and service are derived from the
+/// hostname, e.g. `sqs.eu-west-1.amazonaws.com`. If neither contains a region
+/// then `AWS_REGION` or `AWS_DEFAULT_REGION` is used.
+pub fn sign_request(
+    request: &mut Request,
+    credentials: &Credentials,
+    scope: Option<&AwsSigv4Scope>,
+) -> Result<()> {
+    let (service, region) = service_and_region(request.url(), scope)?;
+    let now = match var("XH_TEST_AWS_SIGV4_DATE") {
+        Ok(timestamp) if test_mode() => OffsetDateTime::from_unix_timestamp(timestamp.parse()?)?,
+        _ => OffsetDateTime::now_utc(),
+    };
+    let amz_date = format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    );
+
+    // Streamed bodies like files are read into memory so they can be hashed
+    let payload_hash = match request.body_mut() {
+        None => hex_sha256(b""),
+        Some(body) => hex_sha256(body.buffer()?),
+    };
+
+    let headers = request.headers_mut();
+    headers.insert("x-amz-date", HeaderValue::from_str(&amz_date)?);
+    headers.insert(
+        "x-amz-content-sha256",
+        HeaderValue::from_str(&payload_hash)?,
+    );
+    if let Some(token) = credentials.session_token {
+        headers.insert("x-amz-security-token", HeaderValue::from_str(token)?);
+    }
+
+    let mut signed_headers = vec![("host".to_string(), host_header(request.url()))];
+    for (name, value) in request.headers() {
+        if name.as_str().starts_with("x-amz-") || name == CONTENT_TYPE {
+            signed_headers.push((name.as_str().to_string(), value.to_str()?.to_string()));
+        }
+    }
+
+    let authorization = authorization(
+        request.method().as_str(),
+        request.url(),
+        &signed_headers,
+        &payload_hash,
+        &amz_date,
+        &region,
+        &service,
+        credentials,
+    );
+    request
+        .headers_mut()
+        .insert(AUTHORIZATION, HeaderValue::from_str(&authorization)?);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn authorization(
+    method: &str,
+    url: &Url,
+    headers: &[(String, String)],
+    payload_hash: &str,
+    amz_date: &str,
+    region: &str,
+    service: &str,
+    credentials: &Credentials,
+) -> String {
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), normalize_header_value(value)))
+        .collect();
+    // Multiple values for the same header are joined with commas, in the
+    // order they were given, so this has to be a stable sort by name only
+    headers.sort_by(|(a, _), (b, _)| a.cmp(b));
+    headers.dedup_by(|next, prev| {
+        if next.0 == prev.0 {
+            prev.1.push(',');
+            prev.1.push_str(&next.1);
+            true
+        } else {
+            false
+        }
+    });
+
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = [
+        method,
+        &canonical_uri(url, service),
+        &canonical_query(url),
+        &canonical_headers,
+        &signed_headers,
+        payload_hash,
+    ]
+    .join("\n");
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = [
+        ALGORITHM,
+        amz_date,
+        &scope,
+        &hex_sha256(canonical_request.as_bytes()),
+    ]
+    .join("\n");
+
+    let secret = format!("AWS4{}", credentials.secret_key);
+    let key = hmac_sha256(secret.as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    let key = hmac_sha256(&key, b"aws4_request");
+    let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+    format!(
+        "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+        ALGORITHM, credentials.access_key, scope, signed_headers, signature
+    )
+}
+
+/// Guess the service and region from hostnames like `service.region.amazonaws.com`
+/// or `bucket.s3.region.amazonaws.com`, unless they're given explicitly.
+fn service_and_region(url: &Url, scope: Option<&AwsSigv4Scope>) -> Result<(String, String)> {
+    let region = scope.and_then(|scope| scope.region.clone());
+    let service = scope.and_then(|scope| scope.service.clone());
+    let host = url.host_str().unwrap_or("");
+    let labels: Vec<&str> = host
+        .strip_suffix(".amazonaws.com")
+        .or_else(|| host.strip_suffix(".amazonaws.com.cn"))
+        .map(|prefix| prefix.split('.').collect())
+        .unwrap_or_default();
+
+    let default_region = || {
+        var("AWS_REGION")
+            .or_else(|_| var("AWS_DEFAULT_REGION"))
+            .ok()
+    };
+    let (guessed_service, guessed_region) = match labels.as_slice() {
+        [.., service, region] if is_region(region) => (Some(*service), Some(*region)),
+        [.., service] => (Some(*service), None),
+        [] => (None, None),
+    };
+    let service = service
+        .or_else(|| guessed_service.map(String::from))
+        .ok_or_else(|| {
+            anyhow!(
+                "Unable to determine the AWS service from {:?}, expected a hostname like \
+                SERVICE.REGION.amazonaws.com or --aws-sigv4=aws:amz:REGION:SERVICE",
+                host
+            )
+        })?;
+    let region = region
+        .or_else(|| guessed_region.map(String::from))
+        .or_else(default_region)
+        .unwrap_or_else(|| "us-east-1".to_string());
+    Ok((service, region))
+}
+
+fn is_region(label: &str) -> bool {
+    label.contains('-') && label.ends_with(|c: char| c.is_ascii_digit())
+}
+
+fn host_header(url: &Url) -> String {
+    let host = url.host_str().unwrap_or("");
+    match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+fn canonical_uri(url: &Url, service: &str) -> String {
+    let path = url.path();
+    if path.is_empty() {
+        return "/".to_string();
+    }
+    // Url already percent-encoded the path once. S3 expects exactly that,
+    // all other services expect every segment to be encoded twice.
+    path.split('/')
+        .map(|segment| {
+            let segment = uri_encode(&String::from_utf8_lossy(&percent_decode(segment)));
+            if service == "s3" {
+                segment
+            } else {
+                uri_encode(&segment)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (uri_encode(&key), uri_encode(&value)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn normalize_header_value(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Percent-encode everything except the RFC 3986 unreserved characters.
+fn uri_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CREDENTIALS: Credentials = Credentials {
+        access_key: "AKIDEXAMPLE",
+        secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        session_token: None,
+    };
+
+    // From the get-vanilla case of the AWS Signature Version 4 test suite
+    #[test]
+    fn get_vanilla() {
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let headers = [
+            ("Host".to_string(), "example.amazonaws.com".to_string()),
+            ("X-Amz-Date".to_string(), "20150830T123600Z".to_string()),
+        ];
+        assert_eq!(
+            authorization(
+                "GET",
+                &url,
+                &headers,
+                &hex_sha256(b""),
+                "20150830T123600Z",
+                "us-east-1",
+                "service",
+                &CREDENTIALS,
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+            SignedHeaders=host;x-amz-date, \
+            Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    // From the get-header-value-order case
+    #[test]
+    fn get_header_value_order() {
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let headers = [
+            ("Host".to_string(), "example.amazonaws.com".to_string()),
+            ("My-Header1".to_string(), "value4".to_string()),
+            ("My-Header1".to_string(), "value1".to_string()),
+            ("My-Header1".to_string(), "value3".to_string()),
+            ("My-Header1".to_string(), "value2".to_string()),
+            ("X-Amz-Date".to_string(), "20150830T123600Z".to_string()),
+        ];
+        assert_eq!(
+            authorization(
+                "GET",
+                &url,
+                &headers,
+                &hex_sha256(b""),
+                "20150830T123600Z",
+                "us-east-1",
+                "service",
+                &CREDENTIALS,
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+            SignedHeaders=host;my-header1;x-amz-date, \
+            Signature=08c7e5a9acfcfeb3ab6b2185e75ce8b1deb5e634ec47601a50643f830c755c01"
+        );
+    }
+
+    #[test]
+    fn guess_service_and_region() {
+        let expected = [
+            ("https://sqs.eu-west-1.amazonaws.com/", ("sqs", "eu-west-1")),
+            (
+                "https://bucket.s3.us-west-2.amazonaws.com/",
+                ("s3", "us-west-2"),
+            ),
+            (
+                "https://abc.execute-api.ap-south-1.amazonaws.com/",
+                ("execute-api", "ap-south-1"),
+            ),
+        ];
+        for (url, (service, region)) in expected {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(
+                service_and_region(&url, None).unwrap(),
+                (service.to_string(), region.to_string())
+            );
+        }
+        assert!(service_and_region(&Url::parse("https://example.org/").unwrap(), None).is_err());
+    }
+
+    #[test]
+    fn explicit_service_and_region() {
+        let url = Url::parse("https://storage.example.org/bucket").unwrap();
+        let scope: AwsSigv4Scope = "aws:amz:eu-central-1:s3".parse().unwrap();
+        assert_eq!(
+            service_and_region(&url, Some(&scope)).unwrap(),
+            ("s3".to_string(), "eu-central-1".to_string())
+        );
+
+        // An explicit region wins over the one in the hostname
+        let url = Url::parse("https://sqs.us-east-1.amazonaws.com/").unwrap();
+        let scope: AwsSigv4Scope = "aws:amz:us-west-2".parse().unwrap();
+        assert_eq!(
+            service_and_region(&url, Some(&scope)).unwrap(),
+            ("sqs".to_string(), "us-west-2".to_string())
+        );
+    }
+
+    #[test]
+    fn canonical_paths() {
+        let url = Url::parse("https://example.amazonaws.com/a b/c%2Fd").unwrap();
+        assert_eq!(canonical_uri(&url, "s3"), "/a%20b/c%2Fd");
+        assert_eq!(canonical_uri(&url, "sqs"), "/a%2520b/c%252Fd");
+    }
+
+    #[test]
+    fn canonical_query_is_sorted() {
+        let url = Url::parse("https://example.amazonaws.com/?b=2&a=x y&a=1").unwrap();
+        assert_eq!(canonical_query(&url), "a=1&a=x%20y&b=2");
+    }
+}
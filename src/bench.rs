@@ -0,0 +1,196 @@
+//! Sending a request many times and summarizing the results, for --bench.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use reqwest::blocking::{Client, Request};
+
+use crate::utils::clone_request;
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// The time until each successful response was completely read.
+    durations: Vec<Duration>,
+    statuses: BTreeMap<u16, usize>,
+    failures: usize,
+    first_error: Option<String>,
+    bytes: u64,
+    total: Duration,
+}
+
+/// Send `request` `count` times, spread over `concurrency` threads that each
+/// send one request after another. The client keeps connections alive, so
+/// they're reused.
+///
+/// Requests that fail count as failures rather than stopping the benchmark.
+pub fn run(client: &Client, request: &mut Request, count: u32, concurrency: u32) -> Result<Report> {
+    let requests = (0..concurrency.min(count))
+        .map(|_| clone_request(request))
+        .collect::<Result<Vec<_>>>()?;
+    let remaining = &AtomicU32::new(count);
+    let started = Instant::now();
+    let mut report = thread::scope(|scope| {
+        let workers: Vec<_> = requests
+            .into_iter()
+            .map(|request| scope.spawn(move || work(client, request, remaining)))
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap())
+            .fold(Report::default(), Report::merge)
+    });
+    report.total = started.elapsed();
+    Ok(report)
+}
+
+/// Keep sending requests until there are none left.
+fn work(client: &Client, request: Request, remaining: &AtomicU32) -> Report {
+    let mut report = Report::default();
+    while remaining
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+        .is_ok()
+    {
+        // The body was buffered by clone_request()
+        let request = request.try_clone().unwrap();
+        let sent = Instant::now();
+        let result = client.execute(request).and_then(|mut response| {
+            let status = response.status().as_u16();
+            let bytes = response.copy_to(&mut io::sink())?;
+            Ok((status, bytes))
+        });
+        match result {
+            Ok((status, bytes)) => {
+                report.durations.push(sent.elapsed());
+                *report.statuses.entry(status).or_default() += 1;
+                report.bytes += bytes;
+            }
+            Err(err) => {
+                report.failures += 1;
+                report
+                    .first_error
+                    .get_or_insert_with(|| format!("{:#}", anyhow::Error::from(err)));
+            }
+        }
+    }
+    report
+}
+
+impl Report {
+    fn merge(mut self, other: Report) -> Report {
+        self.durations.extend(other.durations);
+        for (status, count) in other.statuses {
+            *self.statuses.entry(status).or_default() += count;
+        }
+        self.failures += other.failures;
+        self.first_error = self.first_error.or(other.first_error);
+        self.bytes += other.bytes;
+        self
+    }
+}
+
+/// The latency that `percent` percent of responses didn't exceed.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.durations.len() + self.failures;
+        writeln!(f, "Requests:     {} ({} failed)", count, self.failures)?;
+        writeln!(f, "Total time:   {:.3} s", self.total.as_secs_f64())?;
+        if !self.total.is_zero() {
+            writeln!(
+                f,
+                "Throughput:   {:.2} requests/s",
+                count as f64 / self.total.as_secs_f64()
+            )?;
+        }
+        writeln!(f, "Transferred:  {} bytes", self.bytes)?;
+
+        let mut sorted = self.durations.clone();
+        sorted.sort();
+        if let (Some(min), Some(max)) = (sorted.first(), sorted.last()) {
+            let avg = sorted.iter().sum::<Duration>() / sorted.len() as u32;
+            writeln!(
+                f,
+                "Latency:      min {}, avg {}, max {}",
+                millis(*min),
+                millis(avg),
+                millis(*max)
+            )?;
+            writeln!(
+                f,
+                "Percentiles:  50% {}, 90% {}, 99% {}",
+                millis(percentile(&sorted, 50)),
+                millis(percentile(&sorted, 90)),
+                millis(percentile(&sorted, 99))
+            )?;
+        }
+        if !self.statuses.is_empty() {
+            let statuses: Vec<_> = self
+                .statuses
+                .iter()
+                .map(|(status, count)| format!("{} × {}", status, count))
+                .collect();
+            writeln!(f, "Status codes: {}", statuses.join(", "))?;
+        }
+        if let Some(error) = &self.first_error {
+            writeln!(f, "First error:  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let report = Report {
+            durations: (1..=10).rev().map(Duration::from_millis).collect(),
+            statuses: BTreeMap::from([(200, 9), (503, 1)]),
+            failures: 2,
+            first_error: None,
+            bytes: 1234,
+            total: Duration::from_secs(2),
+        };
+        assert_eq!(
+            report.to_string(),
+            "\
+Requests:     12 (2 failed)
+Total time:   2.000 s
+Throughput:   6.00 requests/s
+Transferred:  1234 bytes
+Latency:      min 1.00 ms, avg 5.50 ms, max 10.00 ms
+Percentiles:  50% 5.00 ms, 90% 9.00 ms, 99% 10.00 ms
+Status codes: 200 × 9, 503 × 1
+"
+        );
+
+        let report = Report {
+            failures: 3,
+            first_error: Some("error sending request".to_string()),
+            ..Report::default()
+        };
+        assert_eq!(
+            report.to_string(),
+            "\
+Requests:     3 (3 failed)
+Total time:   0.000 s
+Transferred:  0 bytes
+First error:  error sending request
+"
+        );
+    }
+}
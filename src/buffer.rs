@@ -32,6 +32,8 @@ mod imp {
 
     use termcolor::{Ansi, WriteColor};
 
+    use crate::pager::PagerInput;
+
     pub struct Buffer {
         inner: Ansi<BufWriter<Inner>>,
         terminal: bool,
@@ -42,6 +44,7 @@ mod imp {
         File(std::fs::File),
         Stdout(std::io::Stdout),
         Stderr(std::io::Stderr),
+        Pager(PagerInput),
     }
 
     impl Buffer {
@@ -77,6 +80,14 @@ mod imp {
             }
         }
 
+        pub fn pager(input: PagerInput) -> Self {
+            Self {
+                inner: Ansi::new(BufWriter::new(Inner::Pager(input))),
+                terminal: true,
+                redirect: false,
+            }
+        }
+
         pub fn is_terminal(&self) -> bool {
             self.terminal
         }
@@ -107,6 +118,7 @@ mod imp {
                 Inner::File(w) => w.write(buf),
                 Inner::Stdout(w) => w.write(buf),
                 Inner::Stderr(w) => w.write(buf),
+                Inner::Pager(w) => w.write(buf),
             }
         }
 
@@ -115,6 +127,7 @@ mod imp {
                 Inner::File(w) => w.write_all(buf),
                 Inner::Stdout(w) => w.write_all(buf),
                 Inner::Stderr(w) => w.write_all(buf),
+                Inner::Pager(w) => w.write_all(buf),
             }
         }
 
@@ -123,6 +136,7 @@ mod imp {
                 Inner::File(w) => w.flush(),
                 Inner::Stdout(w) => w.flush(),
                 Inner::Stderr(w) => w.flush(),
+                Inner::Pager(w) => w.flush(),
             }
         }
     }
@@ -164,6 +178,7 @@ mod imp {
 
     use termcolor::{Ansi, BufferedStandardStream, ColorChoice, WriteColor};
 
+    use crate::pager::PagerInput;
     use crate::utils::test_default_color;
 
     pub enum Buffer {
@@ -173,6 +188,7 @@ mod imp {
         Redirect(Ansi<BufWriter<std::io::Stdout>>),
         Stdout(BufferedStandardStream),
         Stderr(BufferedStandardStream),
+        Pager(Ansi<BufWriter<PagerInput>>),
     }
 
     impl Buffer {
@@ -200,8 +216,15 @@ mod imp {
             Buffer::File(Ansi::new(BufWriter::new(file)))
         }
 
+        pub fn pager(input: PagerInput) -> Self {
+            Buffer::Pager(Ansi::new(BufWriter::new(input)))
+        }
+
         pub fn is_terminal(&self) -> bool {
-            matches!(self, Buffer::Stdout(_) | Buffer::Stderr(_))
+            matches!(
+                self,
+                Buffer::Stdout(_) | Buffer::Stderr(_) | Buffer::Pager(_)
+            )
         }
 
         pub fn is_redirect(&self) -> bool {
@@ -229,6 +252,7 @@ mod imp {
             match self {
                 Buffer::File(w) => w.write(buf),
                 Buffer::Redirect(w) => w.write(buf),
+                Buffer::Pager(w) => w.write(buf),
                 Buffer::Stdout(w) | Buffer::Stderr(w) => w.write(buf),
             }
         }
@@ -237,6 +261,7 @@ mod imp {
             match self {
                 Buffer::File(w) => w.get_mut().write_all(buf),
                 Buffer::Redirect(w) => w.get_mut().write_all(buf),
+                Buffer::Pager(w) => w.get_mut().write_all(buf),
                 Buffer::Stdout(w) | Buffer::Stderr(w) => w.write_all(buf),
             }
         }
@@ -245,6 +270,7 @@ mod imp {
             match self {
                 Buffer::File(w) => w.flush(),
                 Buffer::Redirect(w) => w.flush(),
+                Buffer::Pager(w) => w.flush(),
                 Buffer::Stdout(w) | Buffer::Stderr(w) => w.flush(),
             }
         }
@@ -255,6 +281,7 @@ mod imp {
             match self {
                 Buffer::File(w) => w.supports_color(),
                 Buffer::Redirect(w) => w.supports_color(),
+                Buffer::Pager(w) => w.supports_color(),
                 Buffer::Stdout(w) | Buffer::Stderr(w) => w.supports_color(),
             }
         }
@@ -263,6 +290,7 @@ mod imp {
             match self {
                 Buffer::File(w) => w.set_color(spec),
                 Buffer::Redirect(w) => w.set_color(spec),
+                Buffer::Pager(w) => w.set_color(spec),
                 Buffer::Stdout(w) | Buffer::Stderr(w) => w.set_color(spec),
             }
         }
@@ -271,6 +299,7 @@ mod imp {
             match self {
                 Buffer::File(w) => w.reset(),
                 Buffer::Redirect(w) => w.reset(),
+                Buffer::Pager(w) => w.reset(),
                 Buffer::Stdout(w) | Buffer::Stderr(w) => w.reset(),
             }
         }
@@ -279,6 +308,7 @@ mod imp {
             match self {
                 Buffer::File(w) => w.is_synchronous(),
                 Buffer::Redirect(w) => w.is_synchronous(),
+                Buffer::Pager(w) => w.is_synchronous(),
                 Buffer::Stdout(w) | Buffer::Stderr(w) => w.is_synchronous(),
             }
         }
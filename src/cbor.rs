@@ -0,0 +1,292 @@
+//! Decoding CBOR bodies to JSON, so they can be formatted and filtered.
+//!
+//! This mostly follows https://www.rfc-editor.org/rfc/rfc8949#section-6.1:
+//! tags are dropped except for bignums, undefined becomes null and other simple
+//! values are shown as `"simple(N)"`. Byte strings are shown as base64 or hex,
+//! and map keys that aren't strings are turned into JSON text.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde_json::{Map, Value};
+
+use crate::utils::{json_f32, json_float};
+
+/// Values that are nested deeper than this are assumed to be malformed.
+const MAX_DEPTH: usize = 100;
+
+const TAG_POSITIVE_BIGNUM: u64 = 2;
+const TAG_NEGATIVE_BIGNUM: u64 = 3;
+
+/// The "break" stop code that ends indefinite-length items.
+const BREAK: u8 = 0xff;
+
+/// How to show byte strings.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ByteStrings {
+    #[default]
+    Base64,
+    Hex,
+}
+
+impl FromStr for ByteStrings {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<ByteStrings> {
+        match s {
+            "base64" => Ok(ByteStrings::Base64),
+            "hex" => Ok(ByteStrings::Hex),
+            _ => Err(anyhow!("expected base64 or hex")),
+        }
+    }
+}
+
+/// Decode a single CBOR data item.
+pub fn decode(data: &[u8], byte_strings: ByteStrings) -> Result<Value> {
+    let mut reader = Reader { data, byte_strings };
+    let value = reader
+        .value(0)
+        .map_err(|err| anyhow!("Invalid CBOR body: {}", err))?;
+    if !reader.data.is_empty() {
+        bail!(
+            "Invalid CBOR body: {} bytes of trailing data",
+            reader.data.len()
+        );
+    }
+    Ok(value)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    byte_strings: ByteStrings,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.data.len() {
+            bail!("unexpected end of data");
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn uint(&mut self, len: usize) -> Result<u64> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | u64::from(byte)))
+    }
+
+    /// Whether the next byte ends an indefinite-length item, which is consumed if so.
+    fn at_break(&mut self) -> Result<bool> {
+        match self.data.first() {
+            Some(&BREAK) => {
+                self.data = &self.data[1..];
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => bail!("unexpected end of data"),
+        }
+    }
+
+    /// Read the initial byte of a data item: its major type, the additional
+    /// information and the argument. The argument is None for indefinite lengths.
+    fn head(&mut self) -> Result<(u8, u8, Option<u64>)> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let argument = match info {
+            0..=23 => Some(u64::from(info)),
+            24 => Some(self.uint(1)?),
+            25 => Some(self.uint(2)?),
+            26 => Some(self.uint(4)?),
+            27 => Some(self.uint(8)?),
+            31 if matches!(major, 2..=5 | 7) => None,
+            _ => bail!("invalid initial byte 0x{:02x}", initial),
+        };
+        Ok((major, info, argument))
+    }
+
+    fn len(argument: u64) -> usize {
+        argument.try_into().unwrap_or(usize::MAX)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("data is nested too deeply");
+        }
+        let (major, info, argument) = self.head()?;
+        Ok(match (major, argument) {
+            (0, Some(value)) => Value::from(value),
+            (1, Some(value)) => match i64::try_from(value) {
+                Ok(value) => Value::from(-1 - value),
+                Err(_) => json_float(-1.0 - value as f64),
+            },
+            (2, _) => {
+                let bytes = self.string(major, argument)?;
+                self.byte_string(&bytes)
+            }
+            (3, _) => Value::String(String::from_utf8_lossy(&self.string(major, argument)?).into()),
+            (4, Some(len)) => {
+                // Every element takes at least one byte, so don't trust the length further than that
+                let mut elements = Vec::with_capacity(Self::len(len).min(self.data.len()));
+                for _ in 0..len {
+                    elements.push(self.value(depth + 1)?);
+                }
+                Value::Array(elements)
+            }
+            (4, None) => {
+                let mut elements = Vec::new();
+                while !self.at_break()? {
+                    elements.push(self.value(depth + 1)?);
+                }
+                Value::Array(elements)
+            }
+            (5, Some(len)) => {
+                let mut map = Map::new();
+                for _ in 0..len {
+                    self.entry(&mut map, depth)?;
+                }
+                Value::Object(map)
+            }
+            (5, None) => {
+                let mut map = Map::new();
+                while !self.at_break()? {
+                    self.entry(&mut map, depth)?;
+                }
+                Value::Object(map)
+            }
+            (6, Some(tag)) => self.tagged(tag, depth)?,
+            (7, Some(value)) => match info {
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 | 23 => Value::Null,
+                25 => json_float(f16_to_f64(value as u16)),
+                26 => json_f32(f32::from_bits(value as u32)),
+                27 => json_float(f64::from_bits(value)),
+                _ => Value::String(format!("simple({})", value)),
+            },
+            _ => bail!("unexpected break"),
+        })
+    }
+
+    /// Read the contents of a byte or text string, which may be split into chunks.
+    fn string(&mut self, major: u8, argument: Option<u64>) -> Result<Vec<u8>> {
+        match argument {
+            Some(len) => Ok(self.take(Self::len(len))?.to_vec()),
+            None => {
+                let mut contents = Vec::new();
+                while !self.at_break()? {
+                    match self.head()? {
+                        (chunk_major, _, Some(len)) if chunk_major == major => {
+                            contents.extend_from_slice(self.take(Self::len(len))?);
+                        }
+                        _ => bail!("invalid chunk in indefinite-length string"),
+                    }
+                }
+                Ok(contents)
+            }
+        }
+    }
+
+    fn entry(&mut self, map: &mut Map<String, Value>, depth: usize) -> Result<()> {
+        let key = match self.value(depth + 1)? {
+            Value::String(key) => key,
+            key => key.to_string(),
+        };
+        map.insert(key, self.value(depth + 1)?);
+        Ok(())
+    }
+
+    fn tagged(&mut self, tag: u64, depth: usize) -> Result<Value> {
+        let is_bignum = matches!(tag, TAG_POSITIVE_BIGNUM | TAG_NEGATIVE_BIGNUM);
+        if is_bignum && self.data.first().is_some_and(|&initial| initial >> 5 == 2) {
+            let (major, _, argument) = self.head()?;
+            let bytes = self.string(major, argument)?;
+            return Ok(bignum(tag, &bytes).unwrap_or_else(|| self.byte_string(&bytes)));
+        }
+        // Other tags only add meaning that JSON can't express
+        self.value(depth + 1)
+    }
+
+    fn byte_string(&self, bytes: &[u8]) -> Value {
+        Value::String(match self.byte_strings {
+            ByteStrings::Base64 => BASE64_STANDARD.encode(bytes),
+            ByteStrings::Hex => bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        })
+    }
+}
+
+/// Show a bignum as a decimal string, if it fits in 128 bits.
+fn bignum(tag: u64, bytes: &[u8]) -> Option<Value> {
+    if bytes.len() > 16 {
+        return None;
+    }
+    let number = bytes
+        .iter()
+        .fold(0, |number, &byte| (number << 8) | u128::from(byte));
+    if tag == TAG_POSITIVE_BIGNUM {
+        Some(Value::String(number.to_string()))
+    } else {
+        // Negative bignums encode -1 - n
+        Some(Value::String(format!("-{}", number.checked_add(1)?)))
+    }
+}
+
+/// Convert a half-precision float, see
+/// https://www.rfc-editor.org/rfc/rfc8949#name-half-precision.
+fn f16_to_f64(half: u16) -> f64 {
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = f64::from(half & 0x3ff);
+    let value = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.0) * 2f64.powi(i32::from(exponent) - 25),
+    };
+    if half & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn decode_values() {
+        // {"name": "xh", "id": -500, "tags": ["cli", undefined], 1: h'ff00', "ratio": 1.5}
+        let data = b"\xa5\x64name\x62xh\x62id\x39\x01\xf3\x64tags\x82\x63cli\xf7\x01\x42\xff\x00\x65ratio\xf9\x3e\x00";
+        assert_eq!(
+            decode(data, ByteStrings::Base64).unwrap(),
+            json!({
+                "name": "xh",
+                "id": -500,
+                "tags": ["cli", null],
+                "1": "/wA=",
+                "ratio": 1.5,
+            })
+        );
+        assert_eq!(decode(data, ByteStrings::Hex).unwrap()["1"], json!("ff00"));
+
+        // Indefinite lengths, tags, bignums and simple values
+        assert_eq!(
+            decode(
+                b"\x9f\x7f\x62ab\x61c\xff\xbf\x61k\xf5\xff\xc1\x1a\x65\x53\xf1\x00\xc2\x49\x01\x00\x00\x00\x00\x00\x00\x00\x00\xc3\x41\x00\xf0\xfa\x7f\xc0\x00\x00\xff",
+                ByteStrings::Base64
+            )
+            .unwrap(),
+            json!(["abc", {"k": true}, 1700000000, "18446744073709551616", "-1", "simple(16)", "NaN"])
+        );
+
+        assert!(decode(b"\x82\x01", ByteStrings::Base64).is_err());
+        assert!(decode(b"\x01\x02", ByteStrings::Base64).is_err());
+        assert!(decode(b"\xff", ByteStrings::Base64).is_err());
+        assert!(decode(b"\x1c", ByteStrings::Base64).is_err());
+        assert!(decode(&[0x81; 200], ByteStrings::Base64).is_err());
+    }
+}
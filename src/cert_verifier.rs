@@ -0,0 +1,193 @@
+//! Verifying server certificates ourselves, for --insecure-host and --pinnedpubkey.
+//!
+//! reqwest can only turn off verification for the whole client, which would
+//! also cover redirects to other hosts, proxies and the requests for OAuth 2.0
+//! tokens, and it only shows us the certificate after the request was sent.
+//! So when this is needed we build the rustls configuration that reqwest would
+//! otherwise build, with a verifier that decides per server name and checks
+//! pinned keys during the handshake.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::tls;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, DigitallySignedStruct, ProtocolVersion, RootCertStore, SignatureScheme,
+};
+
+use crate::insecure_hosts::is_insecure_host_name;
+use crate::pinning::PinnedKeys;
+
+/// The TLS settings that would otherwise be given to reqwest.
+pub struct TlsOptions {
+    /// Whether to verify certificates at all, i.e. not --verify=no.
+    pub verify: bool,
+    pub insecure_hosts: Vec<String>,
+    pub pins: Option<PinnedKeys>,
+    /// DER certificates from a custom CA bundle.
+    pub root_certs: Vec<Vec<u8>>,
+    /// Whether to trust the built-in and system certificates as well.
+    pub built_in_roots: bool,
+    /// A client certificate and its private key, in PEM format.
+    pub identity: Option<Vec<u8>>,
+    pub min_version: Option<tls::Version>,
+    pub max_version: Option<tls::Version>,
+    pub alpn_protocols: Vec<Vec<u8>>,
+}
+
+pub fn client_config(options: TlsOptions) -> Result<ClientConfig> {
+    let provider = CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(rustls::crypto::ring::default_provider()));
+
+    let webpki = if options.verify {
+        let mut roots = RootCertStore::empty();
+        for cert in options.root_certs {
+            roots
+                .add(CertificateDer::from(cert))
+                .context("Failed to load the custom CA bundle")?;
+        }
+        if options.built_in_roots {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            // Like reqwest, skip system certificates that can't be used
+            if let Ok(certs) = rustls_native_certs::load_native_certs() {
+                roots.add_parsable_certificates(certs);
+            }
+        }
+        let webpki = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()
+            .context("Failed to set up certificate verification")?;
+        Some(webpki)
+    } else {
+        None
+    };
+
+    let versions: Vec<_> = rustls::ALL_VERSIONS
+        .iter()
+        .copied()
+        .filter(|supported| {
+            let version = match supported.version {
+                ProtocolVersion::TLSv1_2 => tls::Version::TLS_1_2,
+                ProtocolVersion::TLSv1_3 => tls::Version::TLS_1_3,
+                _ => return false,
+            };
+            options.min_version.map_or(true, |min| version >= min)
+                && options.max_version.map_or(true, |max| version <= max)
+        })
+        .collect();
+    if versions.is_empty() {
+        bail!("rustls does not support the requested TLS versions");
+    }
+
+    let verifier = Verifier {
+        webpki,
+        insecure_hosts: options.insecure_hosts,
+        pins: options.pins,
+        provider: provider.clone(),
+    };
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&versions)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    let mut config = match options.identity {
+        Some(pem) => {
+            let (certs, key) = parse_identity(&pem)?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .context("Failed to load the cert/cert key files")?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = options.alpn_protocols;
+    Ok(config)
+}
+
+fn parse_identity(pem: &[u8]) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let mut certs = Vec::new();
+    let mut key = None;
+    for item in pem::parse_many(pem).context("Failed to load the cert/cert key files")? {
+        match item.tag() {
+            "CERTIFICATE" => certs.push(CertificateDer::from(item.into_contents())),
+            "PRIVATE KEY" => key = Some(PrivateKeyDer::Pkcs8(item.into_contents().into())),
+            "RSA PRIVATE KEY" => key = Some(PrivateKeyDer::Pkcs1(item.into_contents().into())),
+            "EC PRIVATE KEY" => key = Some(PrivateKeyDer::Sec1(item.into_contents().into())),
+            _ => {}
+        }
+    }
+    let key = key.ok_or_else(|| anyhow!("The cert key file doesn't contain a PEM private key"))?;
+    Ok((certs, key))
+}
+
+#[derive(Debug)]
+struct Verifier {
+    /// The regular verifier, unless verification is turned off.
+    webpki: Option<Arc<WebPkiServerVerifier>>,
+    insecure_hosts: Vec<String>,
+    pins: Option<PinnedKeys>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(pins) = &self.pins {
+            pins.check(end_entity)
+                .map_err(|err| rustls::Error::General(err.to_string()))?;
+        }
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_string(),
+            ServerName::IpAddress(ip) => IpAddr::from(*ip).to_string(),
+            _ => String::new(),
+        };
+        match &self.webpki {
+            Some(webpki) if !is_insecure_host_name(&host, &self.insecure_hosts) => webpki
+                .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now),
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
@@ -0,0 +1,237 @@
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use reqwest::header::{HeaderMap, HeaderName};
+use sha2::digest::DynDigest;
+
+use crate::utils::BUFFER_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Md5,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn hasher(self) -> Box<dyn DynDigest> {
+        match self {
+            Algorithm::Md5 => Box::<md5::Md5>::default(),
+            Algorithm::Sha256 => Box::<sha2::Sha256>::default(),
+            Algorithm::Sha512 => Box::<sha2::Sha512>::default(),
+        }
+    }
+
+    /// Parse the algorithm names used by --checksum, Content-Digest and Digest.
+    fn from_name(name: &str) -> Option<Algorithm> {
+        match name.to_ascii_lowercase().as_str() {
+            "md5" => Some(Algorithm::Md5),
+            "sha256" | "sha-256" => Some(Algorithm::Sha256),
+            "sha512" | "sha-512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    algorithm: Algorithm,
+    expected: Vec<u8>,
+}
+
+impl FromStr for Checksum {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Checksum> {
+        let (name, hex) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Expected ALGORITHM:HEX, e.g. sha256:2c26b46b…"))?;
+        let algorithm = Algorithm::from_name(name).ok_or_else(|| {
+            anyhow!(
+                "Unsupported checksum algorithm {:?}, expected md5, sha256 or sha512",
+                name
+            )
+        })?;
+        Ok(Checksum {
+            algorithm,
+            expected: decode_hex(hex).ok_or_else(|| anyhow!("Invalid hex digest {:?}", hex))?,
+        })
+    }
+}
+
+impl Checksum {
+    /// Find a digest of the full response body in the `Content-Digest`
+    /// (RFC 9530) or `Digest` (RFC 3230) response headers.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Checksum> {
+        let content_digest = headers
+            .get_all(HeaderName::from_static("content-digest"))
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|item| {
+                // sha-256=:base64:
+                let (name, value) = item.trim().split_once('=')?;
+                let value = value.strip_prefix(':')?.strip_suffix(':')?;
+                Checksum::from_base64(name, value)
+            });
+        let digest = headers
+            .get_all(HeaderName::from_static("digest"))
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|item| {
+                // SHA-256=base64
+                let (name, value) = item.trim().split_once('=')?;
+                Checksum::from_base64(name, value)
+            });
+        content_digest.chain(digest).next()
+    }
+
+    fn from_base64(name: &str, value: &str) -> Option<Checksum> {
+        Some(Checksum {
+            algorithm: Algorithm::from_name(name)?,
+            expected: BASE64_STANDARD.decode(value).ok()?,
+        })
+    }
+}
+
+/// A writer that hashes everything that passes through it, if there's
+/// a checksum to verify.
+pub struct ChecksumWriter<W> {
+    inner: W,
+    checksum: Option<(Checksum, Box<dyn DynDigest>)>,
+}
+
+impl<W> ChecksumWriter<W> {
+    pub fn new(inner: W, checksum: Option<Checksum>) -> Self {
+        ChecksumWriter {
+            inner,
+            checksum: checksum.map(|checksum| {
+                let hasher = checksum.algorithm.hasher();
+                (checksum, hasher)
+            }),
+        }
+    }
+
+    /// Hash data that was written earlier, e.g. when resuming a download.
+    pub fn hash_existing(&mut self, mut reader: impl Read) -> io::Result<()> {
+        let hasher = match &mut self.checksum {
+            Some((_, hasher)) => hasher,
+            None => return Ok(()),
+        };
+        let mut buf = vec![0; BUFFER_SIZE];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => hasher.update(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn is_checking(&self) -> bool {
+        self.checksum.is_some()
+    }
+
+    pub fn verify(self) -> Result<()> {
+        let (checksum, hasher) = match self.checksum {
+            Some(checksum) => checksum,
+            None => return Ok(()),
+        };
+        let actual = hasher.finalize();
+        if *actual == *checksum.expected {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Checksum mismatch: expected {}, got {}",
+                encode_hex(&checksum.expected),
+                encode_hex(&actual)
+            ))
+        }
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some((_, hasher)) = &mut self.checksum {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn verify(checksum: &Checksum, data: &[u8]) -> Result<()> {
+        let mut writer = ChecksumWriter::new(io::sink(), Some(checksum.clone()));
+        writer.write_all(data).unwrap();
+        writer.verify()
+    }
+
+    #[test]
+    fn parse_and_verify() {
+        let checksum: Checksum = format!("sha256:{}", HELLO_SHA256).parse().unwrap();
+        assert!(verify(&checksum, b"hello").is_ok());
+        assert!(verify(&checksum, b"hello!").is_err());
+
+        assert!("sha1:abcd".parse::<Checksum>().is_err());
+        assert!("sha256:xyz".parse::<Checksum>().is_err());
+        assert!("sha256".parse::<Checksum>().is_err());
+    }
+
+    #[test]
+    fn digest_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "content-digest",
+            HeaderValue::from_static(
+                "unknown=:AAAA:, sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:",
+            ),
+        );
+        let checksum = Checksum::from_headers(&headers).unwrap();
+        assert_eq!(
+            checksum,
+            format!("sha256:{}", HELLO_SHA256).parse().unwrap()
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "digest",
+            HeaderValue::from_static("SHA-256=LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ="),
+        );
+        let checksum = Checksum::from_headers(&headers).unwrap();
+        assert_eq!(
+            checksum,
+            format!("sha256:{}", HELLO_SHA256).parse().unwrap()
+        );
+
+        assert_eq!(Checksum::from_headers(&HeaderMap::new()), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! Cookie jar files for --cookie-jar and --cookie @FILE.
+//!
+//! Files are in the Netscape format used by curl and browsers' export tools,
+//! unless they have a .json extension, in which case they hold a list of
+//! cookies like those in session files.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use cookie_store::{CookieDomain, RawCookie};
+use url::Url;
+
+use crate::session;
+
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// Read the cookies in a jar. A jar that doesn't exist yet is empty.
+///
+/// `url` is used for JSON cookies that don't have a domain.
+pub fn load(path: &Path, url: &Url) -> Result<Vec<cookie_store::Cookie<'static>>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).context("Failed to read cookie jar"),
+    };
+    parse(path, &text, url)
+}
+
+/// Parse the contents of a jar. The format depends on the extension of `path`.
+pub fn parse(path: &Path, text: &str, url: &Url) -> Result<Vec<cookie_store::Cookie<'static>>> {
+    if is_json(path) {
+        let cookies: Vec<session::Cookie> =
+            serde_json::from_str(text).context("Failed to parse cookie jar")?;
+        cookies.iter().map(|cookie| cookie.to_cookie(url)).collect()
+    } else {
+        parse_netscape(text)
+    }
+}
+
+/// Write cookies to a jar, replacing its contents.
+pub fn save<'a>(
+    path: &Path,
+    cookies: impl Iterator<Item = &'a cookie_store::Cookie<'static>>,
+) -> Result<()> {
+    let text = if is_json(path) {
+        let cookies: Vec<_> = cookies.map(session::Cookie::from_cookie).collect();
+        let mut text = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+        let mut ser = serde_json::Serializer::with_formatter(&mut text, formatter);
+        serde::Serialize::serialize(&cookies, &mut ser)?;
+        text.push(b'\n');
+        text
+    } else {
+        format_netscape(cookies).into_bytes()
+    };
+    fs::write(path, text).context("Failed to write cookie jar")
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+fn parse_netscape(text: &str) -> Result<Vec<cookie_store::Cookie<'static>>> {
+    let mut cookies = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || anyhow!("Invalid cookie on line {} of cookie jar", number + 1);
+        let [domain, include_subdomains, path, secure, expires, name, value] =
+            <[&str; 7]>::try_from(line.split('\t').collect::<Vec<_>>()).map_err(|_| invalid())?;
+
+        let domain = domain.trim_start_matches('.');
+        let mut builder = RawCookie::build(name.to_string(), value.to_string())
+            .path(path.to_string())
+            .secure(secure.eq_ignore_ascii_case("TRUE"))
+            .http_only(http_only);
+        match expires.parse::<i64>().map_err(|_| invalid())? {
+            // Session cookies
+            0 => {}
+            expires => {
+                builder = builder.expires(time::OffsetDateTime::from_unix_timestamp(expires)?);
+            }
+        }
+        let url: Url = format!("https://{}/", domain)
+            .parse()
+            .map_err(|_| invalid())?;
+        // The cookie's domain attribute cannot be an IP address
+        if include_subdomains.eq_ignore_ascii_case("TRUE") {
+            if let Some(url::Host::Domain(_)) = url.host() {
+                builder = builder.domain(domain.to_string());
+            }
+        }
+        cookies.push(cookie_store::Cookie::try_from_raw_cookie(
+            &builder.finish(),
+            &url,
+        )?);
+    }
+    Ok(cookies)
+}
+
+fn format_netscape<'a>(cookies: impl Iterator<Item = &'a cookie_store::Cookie<'static>>) -> String {
+    let mut text =
+        String::from("# Netscape HTTP Cookie File\n# This file was generated by xh.\n\n");
+    for cookie in cookies {
+        let (domain, include_subdomains) = match &cookie.domain {
+            CookieDomain::HostOnly(domain) => (domain.clone(), "FALSE"),
+            CookieDomain::Suffix(domain) => (format!(".{}", domain), "TRUE"),
+            CookieDomain::NotPresent | CookieDomain::Empty => continue,
+        };
+        let expires = cookie
+            .expires()
+            .and_then(|expires| expires.datetime())
+            .map_or(0, |expires| expires.unix_timestamp());
+        writeln!(
+            text,
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            if cookie.http_only() == Some(true) {
+                HTTP_ONLY_PREFIX
+            } else {
+                ""
+            },
+            domain,
+            include_subdomains,
+            cookie.path().unwrap_or("/"),
+            if cookie.secure() == Some(true) {
+                "TRUE"
+            } else {
+                "FALSE"
+            },
+            expires,
+            cookie.name(),
+            cookie.value()
+        )
+        .unwrap();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netscape_format() {
+        let text = "\
+# Netscape HTTP Cookie File
+
+example.com\tFALSE\t/\tFALSE\t0\tsession\t1
+.example.com\tTRUE\t/api\tTRUE\t4102444800\tpersistent\t2
+#HttpOnly_127.0.0.1\tFALSE\t/\tFALSE\t0\thidden\t3
+";
+        let cookies = parse_netscape(text).unwrap();
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies[1].name(), "persistent");
+        assert_eq!(cookies[1].path(), Some("/api"));
+        assert_eq!(cookies[1].secure(), Some(true));
+        assert_eq!(cookies[2].http_only(), Some(true));
+
+        assert_eq!(
+            format_netscape(cookies.iter()),
+            "\
+# Netscape HTTP Cookie File
+# This file was generated by xh.
+
+example.com\tFALSE\t/\tFALSE\t0\tsession\t1
+.example.com\tTRUE\t/api\tTRUE\t4102444800\tpersistent\t2
+#HttpOnly_127.0.0.1\tFALSE\t/\tFALSE\t0\thidden\t3
+"
+        );
+
+        assert!(parse_netscape("example.com\tFALSE\t/\n").is_err());
+        assert!(parse_netscape("example.com\tFALSE\t/\tFALSE\tsoon\ta\tb\n").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io::{self, Read};
 use std::str::FromStr;
 
@@ -27,6 +28,17 @@ impl FromStr for CompressionType {
     }
 }
 
+impl fmt::Display for CompressionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CompressionType::Gzip => "gzip",
+            CompressionType::Deflate => "deflate",
+            CompressionType::Brotli => "br",
+            CompressionType::Zstd => "zstd",
+        })
+    }
+}
+
 // See https://github.com/seanmonstar/reqwest/blob/9bd4e90ec3401c2c5bc435c58954f3d52ab53e99/src/async_impl/decoder.rs#L150
 pub fn get_compression_type(headers: &HeaderMap) -> Option<CompressionType> {
     let mut compression_type = headers
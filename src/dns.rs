@@ -0,0 +1,425 @@
+//! A minimal DNS client for --dns-server.
+//!
+//! Only A and AAAA lookups are supported. Queries are sent over UDP, or over
+//! HTTPS as described in RFC 8484 if the server is given as a URL.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{self, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use url::Url;
+
+use crate::logging::debug;
+
+const DNS_MESSAGE: &str = "application/dns-message";
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsServer {
+    Udp(SocketAddr),
+    Https(Url),
+}
+
+impl FromStr for DnsServer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<DnsServer> {
+        if s.starts_with("https://") {
+            return Ok(DnsServer::Https(s.parse()?));
+        }
+        if let Ok(addr) = s.parse() {
+            return Ok(DnsServer::Udp(addr));
+        }
+        let ip = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s)
+            .parse::<IpAddr>()
+            .with_context(|| {
+                format!(
+                    "Invalid DNS server {:?}, expected an IP address or an https:// URL",
+                    s
+                )
+            })?;
+        Ok(DnsServer::Udp(SocketAddr::new(ip, 53)))
+    }
+}
+
+impl std::fmt::Display for DnsServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsServer::Udp(addr) => write!(f, "{}", addr),
+            DnsServer::Https(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+type Reply = Box<dyn FnOnce(Result<Vec<IpAddr>>) + Send>;
+
+/// Resolves every host through --dns-server, including the hosts of
+/// redirects and proxies.
+///
+/// Lookups are blocking, so they run on a thread of their own. That thread
+/// also owns the DNS over HTTPS client, which can't be used or dropped inside
+/// the runtime of the client that asks for the lookups.
+pub struct Resolver {
+    server: DnsServer,
+    lookups: mpsc::Sender<(String, Reply)>,
+    /// Addresses that were found so far, to show them in the metadata.
+    cache: Arc<Mutex<HashMap<String, Vec<IpAddr>>>>,
+}
+
+impl Resolver {
+    /// `https_client` is only built if the server is a DNS over HTTPS URL.
+    /// Lookups are logged if `bin_name` is given.
+    pub fn new(
+        server: DnsServer,
+        ipv4: bool,
+        ipv6: bool,
+        https_client: ClientBuilder,
+        bin_name: Option<String>,
+    ) -> Result<Resolver> {
+        let https_client = match server {
+            DnsServer::Udp(_) => None,
+            DnsServer::Https(_) => Some(https_client.build()?),
+        };
+        let cache = Arc::new(Mutex::new(HashMap::new()));
+        let (sender, receiver) = mpsc::channel::<(String, Reply)>();
+        thread::spawn({
+            let server = server.clone();
+            let cache = Arc::clone(&cache);
+            move || {
+                for (host, reply) in receiver {
+                    let cached = cache.lock().unwrap().get(&host).cloned();
+                    if let Some(addrs) = cached {
+                        reply(Ok(addrs));
+                        continue;
+                    }
+                    let result = lookup(&server, &host, ipv4, ipv6, https_client.as_ref());
+                    if let Ok(addrs) = &result {
+                        if let Some(bin_name) = &bin_name {
+                            debug(
+                                bin_name,
+                                format_args!(
+                                    "resolved {} to {} using {}",
+                                    host,
+                                    join_addrs(addrs),
+                                    server
+                                ),
+                            );
+                        }
+                        cache.lock().unwrap().insert(host, addrs.clone());
+                    }
+                    reply(result);
+                }
+            }
+        });
+        Ok(Resolver {
+            server,
+            lookups: sender,
+            cache,
+        })
+    }
+
+    fn start_lookup(&self, host: String, reply: Reply) {
+        if let Err(mpsc::SendError((_, reply))) = self.lookups.send((host, reply)) {
+            reply(Err(anyhow!("The DNS lookup thread has stopped")));
+        }
+    }
+
+    /// Look up a host right away, outside of a request.
+    pub fn lookup(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let (sender, receiver) = mpsc::channel();
+        self.start_lookup(
+            host.to_string(),
+            Box::new(move |result| {
+                let _ = sender.send(result);
+            }),
+        );
+        receiver.recv()?
+    }
+
+    /// How a host was resolved, if it was.
+    pub fn describe(&self, host: &str) -> Option<String> {
+        let cache = self.cache.lock().unwrap();
+        let addrs = cache.get(host.trim_end_matches('.'))?;
+        Some(format!("{} using {}", join_addrs(addrs), self.server))
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let pending = Arc::new(Mutex::new(PendingLookup::default()));
+        self.start_lookup(
+            name.as_str().to_string(),
+            Box::new({
+                let pending = Arc::clone(&pending);
+                move |result| {
+                    let mut pending = pending.lock().unwrap();
+                    pending.result = Some(result);
+                    if let Some(waker) = pending.waker.take() {
+                        waker.wake();
+                    }
+                }
+            }),
+        );
+        Box::pin(LookupFuture(pending))
+    }
+}
+
+#[derive(Default)]
+struct PendingLookup {
+    result: Option<Result<Vec<IpAddr>>>,
+    waker: Option<Waker>,
+}
+
+struct LookupFuture(Arc<Mutex<PendingLookup>>);
+
+impl Future for LookupFuture {
+    type Output = Result<Addrs, Box<dyn std::error::Error + Send + Sync>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let mut pending = self.0.lock().unwrap();
+        match pending.result.take() {
+            Some(Ok(addrs)) => Poll::Ready(Ok(Box::new(
+                addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)),
+            ))),
+            Some(Err(err)) => Poll::Ready(Err(err.into())),
+            None => {
+                pending.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn join_addrs(addrs: &[IpAddr]) -> String {
+    addrs
+        .iter()
+        .map(IpAddr::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Look up the addresses of a host. IPv4 addresses come first.
+///
+/// `https_client` is only used if the server is a DNS over HTTPS URL.
+fn lookup(
+    server: &DnsServer,
+    host: &str,
+    ipv4: bool,
+    ipv6: bool,
+    https_client: Option<&Client>,
+) -> Result<Vec<IpAddr>> {
+    let mut types = Vec::new();
+    if ipv4 || !ipv6 {
+        types.push(TYPE_A);
+    }
+    if ipv6 || !ipv4 {
+        types.push(TYPE_AAAA);
+    }
+
+    let mut addrs = Vec::new();
+    for qtype in types {
+        // DoH recommends an ID of 0 so that responses can be cached
+        let id = match server {
+            DnsServer::Udp(_) => rand::random(),
+            DnsServer::Https(_) => 0,
+        };
+        let query = encode_query(id, host, qtype)?;
+        let response = match (server, https_client) {
+            (DnsServer::Udp(addr), _) => query_udp(*addr, &query),
+            (DnsServer::Https(url), Some(client)) => query_https(client, url, query),
+            (DnsServer::Https(_), None) => unreachable!(),
+        }
+        .with_context(|| format!("Failed to look up {:?} using {}", host, server))?;
+        addrs.extend(parse_response(&response, id)?);
+    }
+
+    if addrs.is_empty() {
+        return Err(anyhow!("{} has no addresses for {:?}", server, host));
+    }
+    Ok(addrs)
+}
+
+fn query_udp(server: SocketAddr, query: &[u8]) -> Result<Vec<u8>> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.connect(server)?;
+    socket.send(query)?;
+    let mut buf = vec![0; 4096];
+    let len = socket.recv(&mut buf)?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+fn query_https(client: &Client, url: &Url, query: Vec<u8>) -> Result<Vec<u8>> {
+    let response = client
+        .post(url.clone())
+        .header(CONTENT_TYPE, DNS_MESSAGE)
+        .header(ACCEPT, DNS_MESSAGE)
+        .body(query)
+        .send()?
+        .error_for_status()?;
+    Ok(response.bytes()?.to_vec())
+}
+
+fn encode_query(id: u16, host: &str, qtype: u16) -> Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(host.len() + 18);
+    query.extend(id.to_be_bytes());
+    // Recursion desired, one question
+    query.extend([0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(anyhow!("Invalid hostname {:?}", host));
+        }
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+    query.extend(qtype.to_be_bytes());
+    query.extend(CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+fn parse_response(response: &[u8], id: u16) -> Result<Vec<IpAddr>> {
+    let invalid = || anyhow!("Invalid DNS response");
+    let read_u16 = |pos: usize| -> Result<u16> {
+        let bytes = response.get(pos..pos + 2).ok_or_else(invalid)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    if read_u16(0)? != id {
+        return Err(anyhow!("DNS response does not match the query"));
+    }
+    let flags = read_u16(2)?;
+    if flags & 0x0200 != 0 {
+        return Err(anyhow!("DNS response was truncated"));
+    }
+    match flags & 0x000f {
+        // NXDOMAIN just means there are no addresses
+        0 | 3 => {}
+        rcode => return Err(anyhow!("DNS server returned error code {}", rcode)),
+    }
+    let questions = read_u16(4)?;
+    let answers = read_u16(6)?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(response, pos).ok_or_else(invalid)? + 4;
+    }
+
+    let mut addrs = Vec::new();
+    for _ in 0..answers {
+        pos = skip_name(response, pos).ok_or_else(invalid)?;
+        let rtype = read_u16(pos)?;
+        let class = read_u16(pos + 2)?;
+        let len = read_u16(pos + 8)? as usize;
+        pos += 10;
+        let data = response.get(pos..pos + len).ok_or_else(invalid)?;
+        pos += len;
+        // Answers may also include CNAME records, those are skipped
+        match (rtype, class, data.len()) {
+            (TYPE_A, CLASS_IN, 4) => {
+                addrs.push(IpAddr::from(<[u8; 4]>::try_from(data).unwrap()));
+            }
+            (TYPE_AAAA, CLASS_IN, 16) => {
+                addrs.push(IpAddr::from(<[u8; 16]>::try_from(data).unwrap()));
+            }
+            _ => {}
+        }
+    }
+    Ok(addrs)
+}
+
+/// Return the position right after a (possibly compressed) domain name.
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            // A pointer to a name elsewhere in the message ends the name
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len => pos += 1 + len as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_dns_server() {
+        assert_eq!(
+            "1.1.1.1".parse::<DnsServer>().unwrap(),
+            DnsServer::Udp("1.1.1.1:53".parse().unwrap())
+        );
+        assert_eq!(
+            "[::1]:5353".parse::<DnsServer>().unwrap(),
+            DnsServer::Udp("[::1]:5353".parse().unwrap())
+        );
+        assert_eq!(
+            "2606:4700::1111".parse::<DnsServer>().unwrap(),
+            DnsServer::Udp("[2606:4700::1111]:53".parse().unwrap())
+        );
+        assert!(matches!(
+            "https://dns.example/dns-query"
+                .parse::<DnsServer>()
+                .unwrap(),
+            DnsServer::Https(_)
+        ));
+        assert!("dns.example".parse::<DnsServer>().is_err());
+    }
+
+    #[test]
+    fn dns_messages() {
+        let query = encode_query(0x1234, "example.com.", TYPE_A).unwrap();
+        assert_eq!(
+            query,
+            b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+              \x07example\x03com\x00\x00\x01\x00\x01"
+        );
+        assert!(encode_query(0, "example..com", TYPE_A).is_err());
+
+        // The question, followed by a CNAME and an A record that refer back
+        // to earlier names
+        let mut response = query.clone();
+        response[2..4].copy_from_slice(&[0x81, 0x80]);
+        response[6..8].copy_from_slice(&[0x00, 0x02]);
+        response.extend(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x00\x3c\x00\x06\x03www\xc0\x0c");
+        response.extend(b"\xc0\x29\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\x7f\x00\x00\x01");
+        assert_eq!(
+            parse_response(&response, 0x1234).unwrap(),
+            vec![IpAddr::from([127, 0, 0, 1])]
+        );
+        assert!(parse_response(&response, 0x4321).is_err());
+        assert!(parse_response(&response[..response.len() - 1], 0x1234).is_err());
+
+        // NXDOMAIN
+        let mut response = query;
+        response[2..4].copy_from_slice(&[0x81, 0x83]);
+        assert_eq!(
+            parse_response(&response, 0x1234).unwrap(),
+            Vec::<IpAddr>::new()
+        );
+    }
+}
@@ -9,12 +9,19 @@ use mime2ext::mime2ext;
 use regex_lite::Regex;
 use reqwest::{
     blocking::Response,
-    header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE},
+    header::{
+        HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_ENCODING, CONTENT_LENGTH,
+        CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED,
+    },
     StatusCode,
 };
 
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::{Checksum, ChecksumWriter};
 use crate::decoder::{decompress, get_compression_type};
-use crate::utils::{copy_largebuf, test_pretend_term};
+use crate::rate_limit::RateLimit;
+use crate::utils::{copy_largebuf, percent_decode, test_pretend_term, SizeLimit};
 
 fn get_content_length(headers: &HeaderMap) -> Option<u64> {
     headers
@@ -27,15 +34,9 @@ fn get_content_length(headers: &HeaderMap) -> Option<u64> {
 // of PathBufs
 fn get_file_name(response: &Response, orig_url: &reqwest::Url) -> String {
     fn from_header(response: &Response) -> Option<String> {
-        let quoted = Regex::new("filename=\"([^\"]*)\"").unwrap();
-        // Against the spec, but used by e.g. Github's zip downloads
-        let unquoted = Regex::new("filename=([^;=\"]*)").unwrap();
-
-        let header = response.headers().get(CONTENT_DISPOSITION)?.to_str().ok()?;
-        let caps = quoted
-            .captures(header)
-            .or_else(|| unquoted.captures(header))?;
-        Some(caps[1].to_string())
+        // Some servers send raw UTF-8, which to_str() would reject
+        let header = response.headers().get(CONTENT_DISPOSITION)?.as_bytes();
+        filename_from_content_disposition(std::str::from_utf8(header).ok()?)
     }
 
     fn from_url(url: &reqwest::Url) -> Option<String> {
@@ -43,7 +44,7 @@ fn get_file_name(response: &Response, orig_url: &reqwest::Url) -> String {
             .path_segments()?
             .rev()
             .find(|segment| !segment.is_empty())?;
-        Some(last_seg.to_string())
+        Some(String::from_utf8_lossy(&percent_decode(last_seg)).into_owned())
     }
 
     fn guess_extension(response: &Response) -> Option<&'static str> {
@@ -51,12 +52,14 @@ fn get_file_name(response: &Response, orig_url: &reqwest::Url) -> String {
         mime2ext(mimetype)
     }
 
+    let sanitize = |filename: String| sanitize_file_name(&filename, cfg!(windows));
     let mut filename = from_header(response)
-        .or_else(|| from_url(orig_url))
+        .map(sanitize)
+        .filter(|filename| !filename.is_empty())
+        .or_else(|| from_url(orig_url).map(sanitize))
+        .filter(|filename| !filename.is_empty())
         .unwrap_or_else(|| "index".to_string());
 
-    filename = filename.trim().trim_start_matches('.').to_string();
-
     if !filename.contains('.') {
         if let Some(extension) = guess_extension(response) {
             filename.push('.');
@@ -67,6 +70,120 @@ fn get_file_name(response: &Response, orig_url: &reqwest::Url) -> String {
     filename
 }
 
+/// Get the file name from a Content-Disposition header, as described in
+/// RFC 6266. An RFC 5987 `filename*` parameter takes precedence over `filename`.
+fn filename_from_content_disposition(header: &str) -> Option<String> {
+    let mut filename = None;
+    // Skip the disposition type, which some servers leave out
+    let mut rest = match header.split_once(';') {
+        Some((kind, rest)) if !kind.contains('=') => rest,
+        _ => header,
+    };
+    while !rest.is_empty() {
+        let (name, after_name) = match rest.find(['=', ';']) {
+            Some(i) if rest.as_bytes()[i] == b'=' => (&rest[..i], rest[i + 1..].trim_start()),
+            // A parameter without a value
+            Some(i) => {
+                rest = &rest[i + 1..];
+                continue;
+            }
+            None => break,
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let (value, next) = match after_name.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, after_quote) = unquote(quoted);
+                let rest = after_quote.split_once(';').map_or("", |(_, rest)| rest);
+                (value, rest)
+            }
+            None => {
+                // Unquoted values with spaces are against the spec, but used
+                // by e.g. Github's zip downloads
+                let (value, rest) = after_name.split_once(';').unwrap_or((after_name, ""));
+                (value.trim().to_string(), rest)
+            }
+        };
+        rest = next;
+        match name.as_str() {
+            "filename*" => {
+                if let Some(value) = decode_ext_value(&value) {
+                    return Some(value);
+                }
+            }
+            "filename" => filename = filename.or(Some(value)),
+            _ => {}
+        }
+    }
+    filename
+}
+
+/// Read a quoted string up to its closing quote, which has already been
+/// opened. Returns the unescaped contents and whatever follows.
+fn unquote(text: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => return (value, &text[i + 1..]),
+            '\\' => value.extend(chars.next().map(|(_, ch)| ch)),
+            ch => value.push(ch),
+        }
+    }
+    (value, "")
+}
+
+/// Decode an RFC 5987 value like `UTF-8'en'%e2%82%ac%20rates.txt`.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (parts.next()?, parts.next()?, parts.next()?);
+    let bytes = percent_decode(encoded);
+    if charset.eq_ignore_ascii_case("UTF-8") {
+        String::from_utf8(bytes).ok()
+    } else {
+        let encoding = encoding_rs::Encoding::for_label(charset.as_bytes())?;
+        let (decoded, had_errors) = encoding.decode_without_bom_handling(&bytes);
+        (!had_errors).then(|| decoded.into_owned())
+    }
+}
+
+/// Make a suggested file name safe to create in the current directory.
+///
+/// Directories and control characters are removed, as well as leading dots so
+/// the file isn't hidden. On Windows, characters that aren't allowed in file
+/// names are replaced and reserved device names like "CON" are avoided.
+fn sanitize_file_name(filename: &str, windows: bool) -> String {
+    let filename = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let mut filename: String = filename.chars().filter(|ch| !ch.is_control()).collect();
+    filename = filename.trim().trim_start_matches('.').to_string();
+    if windows {
+        filename = filename.replace(['<', '>', ':', '"', '|', '?', '*'], "_");
+        filename.truncate(filename.trim_end_matches(['.', ' ']).len());
+        let stem = filename.split('.').next().unwrap_or_default().trim_end();
+        let reserved = ["CON", "PRN", "AUX", "NUL"].contains(&&*stem.to_ascii_uppercase())
+            || (stem.len() == 4
+                && ["COM", "LPT"].iter().any(|prefix| {
+                    stem.get(..3)
+                        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+                        && matches!(stem.as_bytes()[3], b'1'..=b'9')
+                }));
+        if reserved {
+            filename.insert(0, '_');
+        }
+    }
+    filename
+}
+
+/// Turn "file.ext" into "file (1).ext".
+fn numbered_file_name(file_name: &Path, number: u32) -> PathBuf {
+    let mut name = file_name.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(" ({})", number));
+    if let Some(extension) = file_name.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    file_name.with_file_name(name)
+}
+
 pub fn get_file_size(path: Option<&Path>) -> Option<u64> {
     Some(fs::metadata(path?).ok()?.len())
 }
@@ -87,12 +204,8 @@ fn open_new_file(file_name: PathBuf) -> io::Result<(PathBuf, File)> {
     if let Some(file) = try_open_new(&file_name)? {
         return Ok((file_name, file));
     }
-    for suffix in 1..u32::MAX {
-        let candidate = {
-            let mut candidate = file_name.clone().into_os_string();
-            candidate.push(format!("-{}", suffix));
-            PathBuf::from(candidate)
-        };
+    for number in 1..u32::MAX {
+        let candidate = numbered_file_name(&file_name, number);
         if let Some(file) = try_open_new(&candidate)? {
             return Ok((candidate, file));
         }
@@ -100,6 +213,62 @@ fn open_new_file(file_name: PathBuf) -> io::Result<(PathBuf, File)> {
     panic!("Could not create file after unreasonable number of attempts");
 }
 
+/// The validators of a download, which are kept next to the file until it's
+/// complete so that --continue can tell whether the remote file changed.
+#[derive(Serialize, Deserialize)]
+struct Validators {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+fn validators_path(file_name: &Path) -> PathBuf {
+    let mut path = file_name.as_os_str().to_owned();
+    path.push(".xh-resume");
+    path.into()
+}
+
+/// The If-Range header for resuming a download into `file_name`, if the
+/// validators of the partial file were kept.
+pub fn if_range(file_name: &Path) -> Option<HeaderValue> {
+    let text = fs::read_to_string(validators_path(file_name)).ok()?;
+    let validators: Validators = serde_json::from_str(&text).ok()?;
+    // Weak ETags can't be used with If-Range
+    let validator = validators
+        .etag
+        .filter(|etag| !etag.starts_with("W/"))
+        .or(validators.last_modified)?;
+    HeaderValue::from_str(&validator).ok()
+}
+
+fn save_validators(file_name: &Path, headers: &HeaderMap) -> io::Result<()> {
+    let get = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(String::from)
+    };
+    let validators = Validators {
+        etag: get(ETAG),
+        last_modified: get(LAST_MODIFIED),
+    };
+    if validators.etag.is_none() && validators.last_modified.is_none() {
+        return remove_validators(file_name);
+    }
+    fs::write(
+        validators_path(file_name),
+        serde_json::to_string(&validators)?,
+    )
+}
+
+fn remove_validators(file_name: &Path) -> io::Result<()> {
+    match fs::remove_file(validators_path(file_name)) {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
 // https://github.com/httpie/httpie/blob/84c7327057/httpie/downloads.py#L44
 // https://tools.ietf.org/html/rfc7233#section-4.2
 fn total_for_content_range(header: &str, expected_start: u64) -> Result<u64> {
@@ -158,14 +327,22 @@ const UNCOLORED_BAR_TEMPLATE: &str =
 const SPINNER_TEMPLATE: &str = "{spinner:.green} {bytes} {bytes_per_sec} {wide_msg}";
 const UNCOLORED_SPINNER_TEMPLATE: &str = "{spinner} {bytes} {bytes_per_sec} {wide_msg}";
 
+#[allow(clippy::too_many_arguments)]
 pub fn download_file(
     mut response: Response,
     file_name: Option<PathBuf>,
+    // The directory for a file name of our own choosing
+    dir: Option<&Path>,
+    // Whether to pick a new name if `file_name` exists, like we do for our own names
+    no_clobber: bool,
     // If we fall back on taking the filename from the URL it has to be the
     // original URL, before redirects. That's less surprising and matches
     // HTTPie. Hence this argument.
     orig_url: &reqwest::Url,
     mut resume: Option<u64>,
+    checksum: Option<Checksum>,
+    max_size: Option<u64>,
+    rate_limit: Option<u64>,
     color: bool,
     quiet: bool,
 ) -> Result<()> {
@@ -173,9 +350,25 @@ pub fn download_file(
         resume = None;
     }
 
-    let mut buffer: Box<dyn io::Write>;
+    let buffer: Box<dyn io::Write>;
     let dest_name: PathBuf;
 
+    // A name for a new file, which mustn't replace an existing one
+    let mut file_name = file_name;
+    let new_file_name = if no_clobber && file_name.is_some() {
+        file_name.take()
+    } else if file_name.is_some() {
+        None
+    } else if let Some(dir) = dir {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("couldn't create directory {}", dir.display()))?;
+        Some(dir.join(get_file_name(&response, orig_url)))
+    } else if test_pretend_term() || io::stdout().is_terminal() {
+        Some(get_file_name(&response, orig_url).into())
+    } else {
+        None
+    };
+
     if let Some(file_name) = file_name {
         let mut open_opts = OpenOptions::new();
         open_opts.write(true).create(true);
@@ -187,14 +380,39 @@ pub fn download_file(
 
         dest_name = file_name;
         buffer = Box::new(open_opts.open(&dest_name)?);
-    } else if test_pretend_term() || io::stdout().is_terminal() {
-        let (new_name, handle) = open_new_file(get_file_name(&response, orig_url).into())?;
+    } else if let Some(file_name) = new_file_name {
+        let (new_name, handle) = open_new_file(file_name.clone())?;
+        if new_name != file_name && !quiet {
+            eprintln!(
+                "{:?} already exists, saving to {:?} instead",
+                file_name, new_name
+            );
+        }
         dest_name = new_name;
         buffer = Box::new(handle);
     } else {
         dest_name = "<stdout>".into();
         buffer = Box::new(io::stdout());
     }
+    let to_file = dest_name.as_os_str() != "<stdout>";
+    if to_file {
+        save_validators(&dest_name, response.headers())
+            .context("Failed to save the validators for --continue")?;
+    }
+
+    // Digest headers describe the body of this response, which isn't the
+    // whole file if we're resuming, and isn't what we write if it's compressed
+    let checksum = checksum.or_else(|| {
+        if resume.is_none() && !response.headers().contains_key(CONTENT_ENCODING) {
+            Checksum::from_headers(response.headers())
+        } else {
+            None
+        }
+    });
+    let mut buffer = ChecksumWriter::new(buffer, checksum);
+    if resume.is_some() && buffer.is_checking() {
+        buffer.hash_existing(File::open(&dest_name)?)?;
+    }
 
     let starting_length: u64;
     let total_length: Option<u64>;
@@ -248,7 +466,13 @@ pub fn download_file(
         Some(ref pb) => {
             let compression_type = get_compression_type(response.headers());
             copy_largebuf(
-                &mut decompress(&mut pb.wrap_read(response), compression_type),
+                &mut SizeLimit::new(
+                    decompress(
+                        &mut pb.wrap_read(RateLimit::new(&mut response, rate_limit)),
+                        compression_type,
+                    ),
+                    max_size,
+                ),
                 &mut buffer,
                 false,
             )?;
@@ -269,13 +493,31 @@ pub fn download_file(
         None => {
             let compression_type = get_compression_type(response.headers());
             copy_largebuf(
-                &mut decompress(&mut response, compression_type),
+                &mut SizeLimit::new(
+                    decompress(
+                        &mut RateLimit::new(&mut response, rate_limit),
+                        compression_type,
+                    ),
+                    max_size,
+                ),
                 &mut buffer,
                 false,
             )?;
         }
     }
 
+    if let Err(err) = buffer.verify() {
+        if to_file {
+            fs::remove_file(&dest_name)?;
+            remove_validators(&dest_name)?;
+            return Err(anyhow!("{}, removed {:?}", err, dest_name));
+        }
+        return Err(err);
+    }
+    if to_file {
+        remove_validators(&dest_name)?;
+    }
+
     Ok(())
 }
 
@@ -283,6 +525,84 @@ pub fn download_file(
 mod tests {
     use super::*;
 
+    #[test]
+    fn content_disposition_filenames() {
+        let expected = [
+            (r#"attachment; filename="foo.bar""#, Some("foo.bar")),
+            ("attachment; filename=foo bar baz", Some("foo bar baz")),
+            (
+                r#"attachment; filename="a \"b\";c.txt"; size=3"#,
+                Some(r#"a "b";c.txt"#),
+            ),
+            (r#"attachment; FILENAME = "x.txt""#, Some("x.txt")),
+            (
+                r#"attachment; filename="euro.txt"; filename*=UTF-8''%e2%82%ac%20rates.txt"#,
+                Some("€ rates.txt"),
+            ),
+            (
+                r#"attachment; filename*=UTF-8''%e2%82%ac.txt; filename="euro.txt""#,
+                Some("€.txt"),
+            ),
+            (
+                "attachment; filename*=iso-8859-1'en'%A3%20rates.txt",
+                Some("£ rates.txt"),
+            ),
+            (
+                r#"attachment; filename*=UTF-8''%ff.txt; filename="fallback.txt""#,
+                Some("fallback.txt"),
+            ),
+            ("attachment; filename*=nope''x.txt", None),
+            ("attachment", None),
+            (r#"filename="no-type.txt""#, Some("no-type.txt")),
+            ("inline; name=x", None),
+        ];
+        for (header, filename) in expected {
+            assert_eq!(
+                filename_from_content_disposition(header).as_deref(),
+                filename,
+                "{}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn sanitized_file_names() {
+        let expected = [
+            ("../../etc/passwd", "passwd", "passwd"),
+            ("C:\\Windows\\evil.exe", "evil.exe", "evil.exe"),
+            ("..hidden", "hidden", "hidden"),
+            (" a\tb\u{1b}[31mc.txt ", "ab[31mc.txt", "ab[31mc.txt"),
+            ("what?.txt", "what?.txt", "what_.txt"),
+            ("trailing. ", "trailing.", "trailing"),
+            ("con.txt", "con.txt", "_con.txt"),
+            ("LPT1", "LPT1", "_LPT1"),
+            ("COM10.txt", "COM10.txt", "COM10.txt"),
+            ("console.txt", "console.txt", "console.txt"),
+            ("/", "", ""),
+        ];
+        for (filename, unix, windows) in expected {
+            assert_eq!(sanitize_file_name(filename, false), unix);
+            assert_eq!(sanitize_file_name(filename, true), windows);
+        }
+    }
+
+    #[test]
+    fn numbered_file_names() {
+        let expected = [
+            ("file.txt", "file (1).txt"),
+            ("archive.tar.gz", "archive.tar (1).gz"),
+            ("index", "index (1)"),
+            ("dir/file.txt", "dir/file (1).txt"),
+        ];
+        for (file_name, numbered) in expected {
+            assert_eq!(
+                numbered_file_name(Path::new(file_name), 1),
+                Path::new(numbered)
+            );
+        }
+    }
+
     #[test]
     fn content_range_parsing() {
         let expected = vec![
@@ -0,0 +1,64 @@
+//! Saving response headers to a file, for --dump-header.
+//!
+//! Like curl, the status line and headers of every response are written,
+//! including redirects and auth challenges, each followed by a blank line.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use reqwest::blocking::{Request, Response};
+use reqwest::Version;
+
+use crate::middleware::{Context, Middleware};
+use crate::utils::title_case_header;
+
+pub struct HeaderDumper {
+    file: File,
+}
+
+impl HeaderDumper {
+    /// Open the file, truncating it unless `append` is set.
+    pub fn open(path: &Path, append: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        Ok(HeaderDumper { file })
+    }
+}
+
+impl Middleware for HeaderDumper {
+    fn handle(&mut self, mut ctx: Context, request: Request) -> Result<Response> {
+        let response = self.next(&mut ctx, request)?;
+        self.file
+            .write_all(&dump(&response))
+            .context("Failed to write response headers")?;
+        Ok(response)
+    }
+}
+
+fn dump(response: &Response) -> Vec<u8> {
+    let version = response.version();
+    let as_titlecase = matches!(
+        version,
+        Version::HTTP_09 | Version::HTTP_10 | Version::HTTP_11
+    );
+    let mut text = format!("{:?} {}\r\n", version, response.status()).into_bytes();
+    for (name, value) in response.headers() {
+        if as_titlecase {
+            text.extend_from_slice(title_case_header(name.as_str()).as_bytes());
+        } else {
+            text.extend_from_slice(name.as_str().as_bytes());
+        }
+        text.extend_from_slice(b": ");
+        text.extend_from_slice(value.as_bytes());
+        text.extend_from_slice(b"\r\n");
+    }
+    text.extend_from_slice(b"\r\n");
+    text
+}
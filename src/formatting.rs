@@ -1,8 +1,14 @@
+use std::collections::HashMap;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
+use anyhow::Context;
+
+use serde_json::Value;
 use syntect::dumps::from_binary;
 use syntect::easy::HighlightLines;
-use syntect::highlighting::ThemeSet;
+use syntect::highlighting::{Theme as SyntectTheme, ThemeSet};
 use syntect::parsing::SyntaxSet;
 use syntect::util::LinesWithEndings;
 use termcolor::WriteColor;
@@ -30,12 +36,270 @@ pub fn serde_json_format(indent_level: usize, text: &str, write: impl Write) ->
     Ok(())
 }
 
+/// Sort the keys of all objects in a JSON document, or return `None` if it isn't valid JSON.
+pub fn sort_json_keys(text: &str) -> Option<String> {
+    fn sort(value: Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let mut entries: Vec<(String, Value)> = map
+                    .into_iter()
+                    .map(|(key, value)| (key, sort(value)))
+                    .collect();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                Value::Object(entries.into_iter().collect())
+            }
+            Value::Array(values) => Value::Array(values.into_iter().map(sort).collect()),
+            value => value,
+        }
+    }
+    let value = serde_json::from_str(text).ok()?;
+    Some(sort(value).to_string())
+}
+
+/// Format XML text, or return `None` if it doesn't look like XML.
+pub fn format_xml(text: &str, indent_level: usize) -> Option<String> {
+    if !text.trim_start().starts_with('<') {
+        return None;
+    }
+    let mut formatter = XmlFormatter::new(indent_level);
+    let mut out = String::new();
+    formatter.format(text, &mut out);
+    if formatter.pending.trim_start().starts_with('<') {
+        // Unterminated tag or comment
+        return None;
+    }
+    formatter.finish(&mut out);
+    Some(out)
+}
+
+/// Re-indents XML, putting every element on its own line.
+///
+/// The input can be fed in chunks of any size so that this also works on
+/// streams. Whitespace between elements is normalized, so the formatting of
+/// elements with mixed content (text and elements) may change.
+pub struct XmlFormatter {
+    indent: String,
+    depth: usize,
+    last: XmlToken,
+    started: bool,
+    /// An incomplete token from the end of the previous chunk.
+    pending: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum XmlToken {
+    Open,
+    Close,
+    /// Text directly inside an opening tag, which stays on the same line.
+    InlineText,
+    Other,
+}
+
+impl XmlFormatter {
+    pub fn new(indent_level: usize) -> Self {
+        XmlFormatter {
+            indent: " ".repeat(indent_level),
+            depth: 0,
+            last: XmlToken::Other,
+            started: false,
+            pending: String::new(),
+        }
+    }
+
+    /// Format a chunk of XML, appending the output to `out`.
+    ///
+    /// Tokens that aren't complete yet are held back until the next call.
+    pub fn format(&mut self, chunk: &str, out: &mut String) {
+        let text = std::mem::take(&mut self.pending) + chunk;
+        let mut rest = text.as_str();
+        while !rest.is_empty() {
+            let end = if rest.starts_with('<') {
+                find_tag_end(rest)
+            } else {
+                rest.find('<')
+            };
+            match end {
+                Some(end) => {
+                    self.write_token(&rest[..end], out);
+                    rest = &rest[end..];
+                }
+                None => break,
+            }
+        }
+        self.pending = rest.to_string();
+    }
+
+    /// Write whatever is left and end the output with a newline.
+    pub fn finish(&mut self, out: &mut String) {
+        let rest = std::mem::take(&mut self.pending);
+        self.write_token(&rest, out);
+        if self.started {
+            out.push('\n');
+        }
+    }
+
+    fn write_token(&mut self, token: &str, out: &mut String) {
+        if token.starts_with("</") {
+            self.depth = self.depth.saturating_sub(1);
+            if !matches!(self.last, XmlToken::Open | XmlToken::InlineText) {
+                self.newline(out);
+            }
+            self.last = XmlToken::Close;
+        } else if token.starts_with("<![CDATA[") && self.last == XmlToken::Open {
+            self.last = XmlToken::InlineText;
+        } else if token.starts_with("<?") || token.starts_with("<!") || token.ends_with("/>") {
+            self.newline(out);
+            self.last = XmlToken::Other;
+        } else if token.starts_with('<') {
+            self.newline(out);
+            self.depth += 1;
+            self.last = XmlToken::Open;
+        } else {
+            let token = token.trim();
+            if token.is_empty() {
+                return;
+            }
+            if self.last == XmlToken::Open {
+                self.last = XmlToken::InlineText;
+            } else {
+                self.newline(out);
+                self.last = XmlToken::Other;
+            }
+            out.push_str(token);
+            return;
+        }
+        out.push_str(token);
+    }
+
+    fn newline(&mut self, out: &mut String) {
+        if self.started {
+            out.push('\n');
+        }
+        self.started = true;
+        for _ in 0..self.depth {
+            out.push_str(&self.indent);
+        }
+    }
+}
+
+/// Find the end of the tag, comment, etc. at the start of `text`.
+fn find_tag_end(text: &str) -> Option<usize> {
+    let end_after = |terminator: &str| text.find(terminator).map(|i| i + terminator.len());
+    if text.starts_with("<!--") {
+        end_after("-->")
+    } else if text.starts_with("<![CDATA[") {
+        end_after("]]>")
+    } else if text.starts_with("<?") {
+        end_after("?>")
+    } else {
+        // Look for the closing > while skipping over attribute values and
+        // the internal subset of a DOCTYPE
+        let mut quote = None;
+        let mut brackets = 0;
+        for (i, c) in text.char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => (),
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '[') => brackets += 1,
+                (None, ']') => brackets -= 1,
+                (None, '>') if brackets <= 0 => return Some(i + 1),
+                _ => (),
+            }
+        }
+        None
+    }
+}
+
+/// Write `data` in the style of `xxd`: an offset, sixteen bytes in hex and
+/// the same bytes as ASCII, with non-printable bytes shown as dots.
+///
+/// `offset` is the position of the first byte, for dumping data in chunks.
+pub fn write_hexdump(
+    out: &mut impl WriteColor,
+    data: &[u8],
+    offset: usize,
+    color: bool,
+) -> io::Result<()> {
+    let mut offset_color = termcolor::ColorSpec::new();
+    offset_color.set_fg(Some(termcolor::Color::Blue));
+    let mut nonprintable_color = termcolor::ColorSpec::new();
+    nonprintable_color.set_dimmed(true);
+
+    let set_color =
+        |out: &mut dyn WriteColor, spec: Option<&termcolor::ColorSpec>| -> io::Result<()> {
+            match spec {
+                Some(spec) if color => out.set_color(spec),
+                None if color => out.reset(),
+                _ => Ok(()),
+            }
+        };
+
+    for (i, line) in data.chunks(16).enumerate() {
+        set_color(out, Some(&offset_color))?;
+        write!(out, "{:08x}:", offset + i * 16)?;
+        set_color(out, None)?;
+        for (j, byte) in line.iter().enumerate() {
+            if j % 2 == 0 {
+                write!(out, " ")?;
+            }
+            write!(out, "{:02x}", byte)?;
+        }
+        // Pad short lines so the ASCII column lines up
+        let missing = 16 - line.len();
+        write!(out, "{:width$}  ", "", width = missing * 2 + missing / 2)?;
+        for &byte in line {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                write!(out, "{}", byte as char)?;
+            } else {
+                set_color(out, Some(&nonprintable_color))?;
+                write!(out, ".")?;
+                set_color(out, None)?;
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
 static TS: once_cell::sync::Lazy<ThemeSet> = once_cell::sync::Lazy::new(|| {
     from_binary(include_bytes!(concat!(
         env!("OUT_DIR"),
         "/themepack.themedump"
     )))
 });
+static CUSTOM_THEMES: once_cell::sync::Lazy<Mutex<HashMap<PathBuf, &'static SyntectTheme>>> =
+    once_cell::sync::Lazy::new(Default::default);
+
+/// Load a theme from a .tmTheme file.
+///
+/// Themes are kept around for the rest of the program so that the
+/// arguments can be parsed more than once without reading the file again.
+pub fn load_theme(path: &Path) -> anyhow::Result<&'static SyntectTheme> {
+    let mut themes = CUSTOM_THEMES.lock().unwrap();
+    if let Some(theme) = themes.get(path) {
+        return Ok(*theme);
+    }
+    let theme = ThemeSet::get_theme(path)
+        .with_context(|| format!("Failed to load theme {}", path.display()))?;
+    let theme = Box::leak(Box::new(theme));
+    themes.insert(path.to_path_buf(), theme);
+    Ok(theme)
+}
+
+fn get_theme(theme: Theme) -> &'static SyntectTheme {
+    let name = match theme {
+        Theme::Auto => "ansi",
+        Theme::Solarized => "solarized",
+        Theme::Monokai => "monokai",
+        Theme::Fruity => "fruity",
+        Theme::Gruvbox => "gruvbox",
+        Theme::OneDark => "one-dark",
+        Theme::Custom(theme) => return theme,
+    };
+    &TS.themes[name]
+}
+
 static PS_BASIC: once_cell::sync::Lazy<SyntaxSet> = once_cell::sync::Lazy::new(|| {
     from_binary(include_bytes!(concat!(env!("OUT_DIR"), "/basic.packdump")))
 });
@@ -60,7 +324,7 @@ impl<'a> Highlighter<'a> {
             .find_syntax_by_extension(syntax)
             .expect("syntax not found");
         Self {
-            highlighter: HighlightLines::new(syntax, &TS.themes[theme.as_str()]),
+            highlighter: HighlightLines::new(syntax, get_theme(theme)),
             syntax_set,
             out,
         }
@@ -102,11 +366,196 @@ impl Drop for Highlighter<'_> {
 fn convert_style(style: syntect::highlighting::Style) -> termcolor::ColorSpec {
     use syntect::highlighting::FontStyle;
     let mut spec = termcolor::ColorSpec::new();
-    spec.set_fg(convert_color(style.foreground))
-        .set_underline(style.font_style.contains(FontStyle::UNDERLINE));
+    if let Some(color) = convert_color(style.foreground) {
+        let (color, bold) = fit_color(color, *COLOR_DEPTH);
+        spec.set_fg(Some(color)).set_bold(bold);
+    }
+    spec.set_underline(style.font_style.contains(FontStyle::UNDERLINE));
     spec
 }
 
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorDepth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+static COLOR_DEPTH: once_cell::sync::Lazy<ColorDepth> = once_cell::sync::Lazy::new(|| {
+    color_depth(
+        std::env::var("COLORTERM").ok().as_deref(),
+        std::env::var("TERM").ok().as_deref(),
+        std::env::var("TERM_PROGRAM").ok().as_deref(),
+    )
+});
+
+/// There's no reliable way to ask a terminal which colors it supports, so
+/// this follows the same conventions as other tools. Colors are only
+/// approximated for terminals that are known not to support them, so that
+/// themes look the same as before everywhere else.
+fn color_depth(
+    colorterm: Option<&str>,
+    term: Option<&str>,
+    term_program: Option<&str>,
+) -> ColorDepth {
+    if matches!(colorterm, Some("truecolor" | "24bit")) {
+        return ColorDepth::TrueColor;
+    }
+    match term {
+        Some("linux" | "vt100" | "vt220" | "ansi" | "cons25") => return ColorDepth::Ansi16,
+        Some(term) if term.ends_with("-16color") => return ColorDepth::Ansi16,
+        _ => (),
+    }
+    match term_program {
+        // macOS's Terminal.app shows 24-bit colors wrongly
+        Some("Apple_Terminal") => ColorDepth::Ansi256,
+        _ => ColorDepth::TrueColor,
+    }
+}
+
+/// Approximate a color if the terminal can't show it. The second value is
+/// whether the text should be bold, which is how 16-color terminals show the
+/// bright variants of the 8 basic colors.
+fn fit_color(color: termcolor::Color, depth: ColorDepth) -> (termcolor::Color, bool) {
+    use termcolor::Color;
+
+    const BASIC_COLORS: [Color; 8] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::White,
+    ];
+
+    match (color, depth) {
+        (_, ColorDepth::TrueColor) => (color, false),
+        (Color::Rgb(r, g, b), ColorDepth::Ansi256) => {
+            (Color::Ansi256(rgb_to_ansi256(r, g, b)), false)
+        }
+        (Color::Rgb(r, g, b), ColorDepth::Ansi16) => {
+            let n = rgb_to_ansi16(r, g, b);
+            (BASIC_COLORS[n as usize % 8], n >= 8)
+        }
+        (Color::Ansi256(n), ColorDepth::Ansi16) => {
+            let n = if n < 16 {
+                n
+            } else {
+                let (r, g, b) = ansi256_to_rgb(n);
+                rgb_to_ansi16(r, g, b)
+            };
+            (BASIC_COLORS[n as usize % 8], n >= 8)
+        }
+        _ => (color, false),
+    }
+}
+
+/// The levels of each channel in the 6x6x6 color cube of the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    fn nearest_level(value: u8) -> u8 {
+        (0..6)
+            .min_by_key(|&i| (CUBE_LEVELS[i as usize] as i32 - value as i32).abs())
+            .unwrap()
+    }
+    let cube = 16 + 36 * nearest_level(r) + 6 * nearest_level(g) + nearest_level(b);
+    // Colors 232 to 255 are a grayscale ramp from 8 to 238 in steps of 10
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray = 232 + ((average.saturating_sub(3) / 10).min(23)) as u8;
+    [cube, gray]
+        .into_iter()
+        .min_by_key(|&n| color_distance((r, g, b), ansi256_to_rgb(n)))
+        .unwrap()
+}
+
+fn ansi256_to_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => ANSI16_COLORS[n as usize],
+        16..=231 => {
+            let n = n - 16;
+            (
+                CUBE_LEVELS[(n / 36) as usize],
+                CUBE_LEVELS[(n / 6 % 6) as usize],
+                CUBE_LEVELS[(n % 6) as usize],
+            )
+        }
+        232..=255 => {
+            let level = 8 + 10 * (n - 232);
+            (level, level, level)
+        }
+    }
+}
+
+/// The 16 basic colors, as xterm shows them by default.
+const ANSI16_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Pick one of the 16 basic colors by hue. Going by the distance between
+/// colors would turn most of the muted colors that themes use into gray.
+fn rgb_to_ansi16(r: u8, g: u8, b: u8) -> u8 {
+    let max = r.max(g).max(b) as i32;
+    let min = r.min(g).min(b) as i32;
+    let lightness = (max + min) / 2;
+    let chroma = max - min;
+
+    if chroma < 32 {
+        return match lightness {
+            0..=63 => 0,
+            64..=159 => 8,
+            160..=223 => 7,
+            _ => 15,
+        };
+    }
+
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let hue = if max == r {
+        60 * (g - b) / chroma
+    } else if max == g {
+        120 + 60 * (b - r) / chroma
+    } else {
+        240 + 60 * (r - g) / chroma
+    };
+    // Red, yellow, green, cyan, blue and magenta are 60 degrees apart
+    let color = match (hue + 360 + 30) % 360 / 60 {
+        0 => 1,
+        1 => 3,
+        2 => 2,
+        3 => 6,
+        4 => 4,
+        _ => 5,
+    };
+    if lightness > 160 {
+        color + 8
+    } else {
+        color
+    }
+}
+
+fn color_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let diff = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    diff(a.0, b.0) + diff(a.1, b.1) + diff(a.2, b.2)
+}
+
 // https://github.com/sharkdp/bat/blob/3a85fd767bd1f03debd0a60ac5bc08548f95bc9d/src/terminal.rs
 fn convert_color(color: syntect::highlighting::Color) -> Option<termcolor::Color> {
     use termcolor::Color;
@@ -142,3 +591,114 @@ fn convert_color(color: syntect::highlighting::Color) -> Option<termcolor::Color
         Some(Color::Rgb(color.r, color.g, color.b))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use termcolor::Color;
+
+    #[test]
+    fn xml_formatting() {
+        let xml = r#"<?xml version="1.0"?><!-- list --><list a=">"><item>one</item><item><![CDATA[<two>]]></item><empty/><mixed>a<b>c</b>d</mixed><x></x></list>"#;
+        assert_eq!(
+            format_xml(xml, 2).unwrap(),
+            indoc! {r#"
+                <?xml version="1.0"?>
+                <!-- list -->
+                <list a=">">
+                  <item>one</item>
+                  <item><![CDATA[<two>]]></item>
+                  <empty/>
+                  <mixed>a
+                    <b>c</b>
+                    d
+                  </mixed>
+                  <x></x>
+                </list>
+            "#}
+        );
+
+        // Chunks can be split anywhere
+        let mut formatter = XmlFormatter::new(2);
+        let mut out = String::new();
+        for chunk in xml.as_bytes().chunks(5) {
+            formatter.format(std::str::from_utf8(chunk).unwrap(), &mut out);
+        }
+        formatter.finish(&mut out);
+        assert_eq!(out, format_xml(xml, 2).unwrap());
+
+        assert_eq!(format_xml("not xml", 2), None);
+        assert_eq!(format_xml("<a><b", 2), None);
+    }
+
+    #[test]
+    fn detect_color_depth() {
+        assert_eq!(
+            color_depth(Some("truecolor"), Some("xterm-256color"), None),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            color_depth(None, Some("xterm-256color"), None),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(color_depth(None, None, None), ColorDepth::TrueColor);
+        assert_eq!(
+            color_depth(None, Some("xterm-256color"), Some("Apple_Terminal")),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(
+            color_depth(Some("truecolor"), None, Some("Apple_Terminal")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(color_depth(None, Some("linux"), None), ColorDepth::Ansi16);
+        assert_eq!(
+            color_depth(Some(""), Some("rxvt-16color"), None),
+            ColorDepth::Ansi16
+        );
+    }
+
+    #[test]
+    fn approximate_colors() {
+        let orange = Color::Rgb(0xD1, 0x9A, 0x66);
+        assert_eq!(fit_color(orange, ColorDepth::TrueColor), (orange, false));
+        assert_eq!(
+            fit_color(orange, ColorDepth::Ansi256),
+            (Color::Ansi256(173), false)
+        );
+
+        let gray = Color::Rgb(0x92, 0x83, 0x74);
+        assert_eq!(
+            fit_color(gray, ColorDepth::Ansi256),
+            (Color::Ansi256(244), false)
+        );
+        assert_eq!(fit_color(gray, ColorDepth::Ansi16), (Color::Black, true));
+
+        let yellow = Color::Rgb(0xFA, 0xBD, 0x2F);
+        assert_eq!(
+            fit_color(yellow, ColorDepth::Ansi16),
+            (Color::Yellow, false)
+        );
+        let green = Color::Rgb(0x98, 0xC3, 0x79);
+        assert_eq!(fit_color(green, ColorDepth::Ansi16), (Color::Green, false));
+        let red = Color::Rgb(0xE0, 0x6C, 0x75);
+        assert_eq!(fit_color(red, ColorDepth::Ansi16), (Color::Red, true));
+        assert_eq!(
+            fit_color(Color::Ansi256(173), ColorDepth::Ansi16),
+            fit_color(Color::Rgb(215, 135, 95), ColorDepth::Ansi16)
+        );
+
+        assert_eq!(
+            fit_color(Color::Ansi256(9), ColorDepth::Ansi16),
+            (Color::Red, true)
+        );
+        assert_eq!(
+            fit_color(Color::Ansi256(9), ColorDepth::Ansi256),
+            (Color::Ansi256(9), false)
+        );
+        assert_eq!(
+            fit_color(Color::Blue, ColorDepth::Ansi16),
+            (Color::Blue, false)
+        );
+    }
+}
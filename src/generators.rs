@@ -0,0 +1,135 @@
+//! Values that are generated when the request is built, like `id:={uuid}`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use rand::{distributions::Alphanumeric, Rng};
+use serde_json::Value;
+
+const DEFAULT_RAND_LENGTH: usize = 16;
+const MAX_RAND_LENGTH: usize = 4096;
+
+/// Expand a request item value of the form `{name}` or `{name:arg}`.
+///
+/// Returns `None` if the value doesn't name a generator, in which case it
+/// should be used as-is. Numbers are returned as JSON numbers so that they
+/// keep their type in JSON fields.
+///
+/// A leading backslash, as in `\{uuid}`, gives the generator's name literally.
+pub fn generate(value: &str) -> Result<Option<Value>> {
+    if let Some(literal) = value
+        .strip_prefix('\\')
+        .filter(|rest| rest.starts_with('{'))
+    {
+        return Ok(match generate(literal) {
+            Ok(None) => None,
+            Ok(Some(_)) | Err(_) => Some(Value::String(literal.to_string())),
+        });
+    }
+    let Some(expr) = value.strip_prefix('{').and_then(|v| v.strip_suffix('}')) else {
+        return Ok(None);
+    };
+    let (name, arg) = match expr.split_once(':') {
+        Some((name, arg)) => (name, Some(arg)),
+        None => (expr, None),
+    };
+    let value = match (name, arg) {
+        ("uuid", None) => Value::String(uuid_v4(rand::random())),
+        ("now", None) => Value::from(since_epoch().as_secs()),
+        ("now", Some("ms")) => Value::from(since_epoch().as_millis() as u64),
+        ("rand", arg) => {
+            let len = match arg {
+                Some(arg) => arg
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid length for {{rand}}: {:?}", arg))?,
+                None => DEFAULT_RAND_LENGTH,
+            };
+            if len > MAX_RAND_LENGTH {
+                return Err(anyhow!(
+                    "Length for {{rand}} can't be more than {}: {}",
+                    MAX_RAND_LENGTH,
+                    len
+                ));
+            }
+            let text = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(len)
+                .map(char::from)
+                .collect();
+            Value::String(text)
+        }
+        ("uuid" | "now", Some(arg)) => {
+            return Err(anyhow!("Invalid argument for {{{}}}: {:?}", name, arg));
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+/// Like [`generate`], but for places that need text.
+pub fn generate_string(value: &str) -> Result<Option<String>> {
+    Ok(generate(value)?.map(|value| match value {
+        Value::String(text) => text,
+        value => value.to_string(),
+    }))
+}
+
+fn since_epoch() -> std::time::Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Format random bytes as a version 4 UUID, as described in RFC 9562.
+fn uuid_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generators() {
+        assert_eq!(uuid_v4([0; 16]), "00000000-0000-4000-8000-000000000000");
+        assert_eq!(uuid_v4([0xff; 16]), "ffffffff-ffff-4fff-bfff-ffffffffffff");
+
+        let uuid = generate("{uuid}").unwrap().unwrap();
+        assert_eq!(uuid.as_str().unwrap().len(), 36);
+        assert!(generate("{now}").unwrap().unwrap().is_u64());
+        assert!(generate("{now:ms}").unwrap().unwrap().as_u64().unwrap() > 1_000_000_000_000);
+        let text = generate_string("{rand:32}").unwrap().unwrap();
+        assert_eq!(text.len(), 32);
+        assert!(text.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(generate_string("{rand}").unwrap().unwrap().len(), 16);
+
+        assert_eq!(generate("{nope}").unwrap(), None);
+        assert_eq!(generate("uuid").unwrap(), None);
+        assert_eq!(generate("{uuid").unwrap(), None);
+        generate("{rand:x}").unwrap_err();
+        generate("{uuid:4}").unwrap_err();
+        assert_eq!(generate_string("{rand:4096}").unwrap().unwrap().len(), 4096);
+        generate("{rand:4097}").unwrap_err();
+        generate("{rand:99999999999}").unwrap_err();
+
+        assert_eq!(
+            generate_string("\\{uuid}").unwrap().as_deref(),
+            Some("{uuid}")
+        );
+        assert_eq!(
+            generate_string("\\{rand:x}").unwrap().as_deref(),
+            Some("{rand:x}")
+        );
+        assert_eq!(generate("\\{nope}").unwrap(), None);
+    }
+}
@@ -0,0 +1,93 @@
+//! GraphQL requests for --graphql.
+//!
+//! Queries are sent as a JSON object with a "query" member and, if there are
+//! any, a "variables" member. See https://graphql.org/learn/serving-over-http/.
+
+use std::fs;
+
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+
+use crate::utils::expand_tilde;
+
+/// Build the JSON body for a query. A query starting with "@" is read from a file.
+pub fn request_body(query: &str, variables: &[(String, Value)]) -> Result<String> {
+    let query = match query.strip_prefix('@') {
+        Some(path) => fs::read_to_string(expand_tilde(path))
+            .with_context(|| format!("couldn't read GraphQL query from {:?}", path))?,
+        None => query.to_string(),
+    };
+    let mut body = Map::new();
+    body.insert("query".to_string(), Value::String(query));
+    if !variables.is_empty() {
+        let variables = variables.iter().cloned().collect();
+        body.insert("variables".to_string(), Value::Object(variables));
+    }
+    Ok(Value::Object(body).to_string())
+}
+
+/// Take a body built by `request_body` apart again, for printing.
+pub fn split_body(body: &str) -> Option<(String, Option<Value>)> {
+    let mut body: Map<String, Value> = serde_json::from_str(body).ok()?;
+    let query = match body.remove("query")? {
+        Value::String(query) => query,
+        _ => return None,
+    };
+    Some((query, body.remove("variables")))
+}
+
+/// Parse a --graphql-var, which is NAME=VALUE for a string or NAME:=JSON.
+pub fn parse_variable(var: &str) -> Result<(String, Value), String> {
+    let (name, value) = var
+        .split_once('=')
+        .ok_or_else(|| "expected NAME=VALUE or NAME:=JSON".to_string())?;
+    let (name, value) = match name.strip_suffix(':') {
+        Some(name) => {
+            let value =
+                serde_json::from_str(value).map_err(|err| format!("invalid JSON: {}", err))?;
+            (name, value)
+        }
+        None => (name, Value::String(value.to_string())),
+    };
+    if name.is_empty() {
+        return Err("expected NAME=VALUE or NAME:=JSON".to_string());
+    }
+    Ok((name.to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn body() {
+        let variables = [
+            parse_variable("id:=42").unwrap(),
+            parse_variable("name=a=b").unwrap(),
+        ];
+        let body = request_body("query { user(id: $id) { name } }", &variables).unwrap();
+        assert_eq!(
+            body,
+            r#"{"query":"query { user(id: $id) { name } }","variables":{"id":42,"name":"a=b"}}"#
+        );
+        assert_eq!(
+            split_body(&body).unwrap(),
+            (
+                "query { user(id: $id) { name } }".to_string(),
+                Some(json!({"id": 42, "name": "a=b"}))
+            )
+        );
+
+        assert_eq!(
+            request_body("{ me }", &[]).unwrap(),
+            r#"{"query":"{ me }"}"#
+        );
+        assert_eq!(split_body(r#"{"query": 1}"#), None);
+
+        assert!(parse_variable("id").is_err());
+        assert!(parse_variable("=1").is_err());
+        assert!(parse_variable("id:={").is_err());
+    }
+}
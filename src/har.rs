@@ -0,0 +1,226 @@
+//! Recording of requests and responses into an HTTP Archive.
+//!
+//! See http://www.softwareishard.com/blog/har-12-spec/
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use reqwest::blocking::{Body, Request, Response};
+use reqwest::header::{HeaderMap, CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::middleware::{Context, Middleware, ResponseExt};
+use crate::utils::clone_request;
+
+#[derive(Default)]
+pub struct Har {
+    entries: Vec<Value>,
+}
+
+impl Har {
+    pub fn new() -> Self {
+        Har::default()
+    }
+
+    /// Fill in the body of the most recently recorded response.
+    pub fn set_response_body(&mut self, body: &[u8], download_duration: Option<Duration>) {
+        let entry = match self.entries.last_mut() {
+            Some(entry) => entry,
+            None => return,
+        };
+        let content = &mut entry["response"]["content"];
+        content["size"] = body.len().into();
+        match std::str::from_utf8(body) {
+            Ok(text) => content["text"] = text.into(),
+            Err(_) => {
+                content["text"] = BASE64_STANDARD.encode(body).into();
+                content["encoding"] = "base64".into();
+            }
+        }
+        if let Some(duration) = download_duration {
+            let receive = millis(duration);
+            entry["timings"]["receive"] = receive.into();
+            entry["time"] = (entry["time"].as_f64().unwrap_or(0.0) + receive).into();
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let har = json!({
+            "log": {
+                "version": "1.2",
+                "creator": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+                "entries": self.entries,
+            }
+        });
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut file, &har)?;
+        file.write_all(b"\n")?;
+        file.flush()?;
+        Ok(())
+    }
+
+    fn record(&mut self, started: OffsetDateTime, request: &Request, response: &Response) {
+        let wait = millis(response.meta().request_duration);
+        let mut entry = json!({
+            "startedDateTime": format_date_time(started),
+            "time": wait,
+            "request": {
+                "method": request.method().as_str(),
+                "url": request.url().as_str(),
+                "httpVersion": format!("{:?}", request.version()),
+                "cookies": cookies(request.headers().get_all(COOKIE).iter().flat_map(
+                    |value| value.to_str().unwrap_or("").split(';')
+                )),
+                "headers": headers(request.headers()),
+                "queryString": request
+                    .url()
+                    .query_pairs()
+                    .map(|(name, value)| json!({ "name": name, "value": value }))
+                    .collect::<Vec<_>>(),
+                "headersSize": -1,
+                "bodySize": 0,
+            },
+            "response": {
+                "status": response.status().as_u16(),
+                "statusText": response.status().canonical_reason().unwrap_or(""),
+                "httpVersion": format!("{:?}", response.version()),
+                "cookies": cookies(response.headers().get_all(SET_COOKIE).iter().filter_map(
+                    // Attributes like Path and Expires are not recorded
+                    |value| value.to_str().ok()?.split(';').next()
+                )),
+                "headers": headers(response.headers()),
+                "content": {
+                    "size": 0,
+                    "mimeType": content_type(response.headers()),
+                },
+                "redirectURL": response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .unwrap_or(""),
+                "headersSize": -1,
+                "bodySize": -1,
+            },
+            "cache": {},
+            "timings": {
+                "send": 0,
+                "wait": wait,
+                "receive": 0,
+            },
+        });
+
+        // The body was already buffered by clone_request()
+        if let Some(body) = request.body().and_then(Body::as_bytes) {
+            entry["request"]["bodySize"] = body.len().into();
+            let post_data = &mut entry["request"]["postData"];
+            *post_data = json!({ "mimeType": content_type(request.headers()) });
+            match std::str::from_utf8(body) {
+                Ok(text) => post_data["text"] = text.into(),
+                Err(_) => {
+                    post_data["text"] = BASE64_STANDARD.encode(body).into();
+                    post_data["encoding"] = "base64".into();
+                }
+            }
+        }
+
+        self.entries.push(entry);
+    }
+}
+
+/// Records every request that is sent and the response it gets. This has to
+/// come after the redirect, auth and retry middleware so that it sees every
+/// request they send, and before anything that streams the request body.
+pub struct HarRecorder<'a> {
+    har: &'a mut Har,
+}
+
+impl<'a> HarRecorder<'a> {
+    pub fn new(har: &'a mut Har) -> Self {
+        HarRecorder { har }
+    }
+}
+
+impl<'a> Middleware for HarRecorder<'a> {
+    fn handle(&mut self, mut ctx: Context, mut request: Request) -> Result<Response> {
+        let recorded_request = clone_request(&mut request)?;
+        let started = OffsetDateTime::now_utc();
+        let response = self.next(&mut ctx, request)?;
+        self.har.record(started, &recorded_request, &response);
+        Ok(response)
+    }
+}
+
+fn headers(headers: &HeaderMap) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            json!({
+                "name": name.as_str(),
+                "value": String::from_utf8_lossy(value.as_bytes()),
+            })
+        })
+        .collect()
+}
+
+fn cookies<'a>(pairs: impl Iterator<Item = &'a str>) -> Vec<Value> {
+    pairs
+        .filter_map(|pair| pair.trim().split_once('='))
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect()
+}
+
+fn content_type(headers: &HeaderMap) -> &str {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn format_date_time(date_time: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        date_time.year(),
+        u8::from(date_time.month()),
+        date_time.day(),
+        date_time.hour(),
+        date_time.minute(),
+        date_time.second(),
+        date_time.millisecond()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_time_format() {
+        let date_time =
+            OffsetDateTime::from_unix_timestamp_nanos(1_440_938_160_123_000_000).unwrap();
+        assert_eq!(format_date_time(date_time), "2015-08-30T12:36:00.123Z");
+    }
+
+    #[test]
+    fn binary_bodies_are_base64_encoded() {
+        let mut har = Har {
+            entries: vec![json!({ "response": { "content": { "size": 0 } } })],
+        };
+        har.set_response_body(b"\xff\x00", None);
+        assert_eq!(
+            har.entries[0]["response"]["content"],
+            json!({ "size": 2, "text": "/wA=", "encoding": "base64" })
+        );
+    }
+}
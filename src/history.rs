@@ -0,0 +1,231 @@
+//! A log of past requests, for --history.
+//!
+//! Every exchange is appended as a line of JSON to history.jsonl in the config
+//! directory. `xh history` lists them, and `xh last` sends the most recent
+//! request again using the arguments recorded with it.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context as _, Result};
+use reqwest::blocking::{Request, Response};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::middleware::{Context, Middleware, ResponseExt};
+use crate::utils::config_dir;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+    /// Milliseconds until the response headers arrived.
+    pub duration: u64,
+    /// Size of the request body, unless it was streamed.
+    pub request_size: Option<u64>,
+    /// Size of the response body, if it was read or announced.
+    pub response_size: Option<u64>,
+    /// The command line arguments, for the first exchange of each run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+}
+
+pub struct History {
+    args: Vec<String>,
+    entries: Vec<Entry>,
+}
+
+impl History {
+    pub fn new(args: Vec<String>) -> Self {
+        History {
+            args,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Fill in the body size of the most recently recorded response.
+    pub fn set_response_size(&mut self, size: u64) {
+        if let Some(entry) = self.entries.last_mut() {
+            entry.response_size = Some(size);
+        }
+    }
+
+    /// Append the recorded entries to the history file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(&serde_json::to_string(entry)?);
+            text.push('\n');
+        }
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        // A single write keeps lines from concurrent runs apart
+        options.open(path)?.write_all(text.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Records every request that is sent. Like `HarRecorder` this sees redirects
+/// and auth challenges.
+pub struct HistoryRecorder<'a> {
+    history: &'a mut History,
+}
+
+impl<'a> HistoryRecorder<'a> {
+    pub fn new(history: &'a mut History) -> Self {
+        HistoryRecorder { history }
+    }
+}
+
+impl<'a> Middleware for HistoryRecorder<'a> {
+    fn handle(&mut self, mut ctx: Context, request: Request) -> Result<Response> {
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+        let method = request.method().to_string();
+        let mut url = request.url().clone();
+        // Fails only for URLs that can't have credentials in the first place
+        let _ = url.set_password(None);
+        let url = url.to_string();
+        let request_size = match request.body() {
+            Some(body) => body.as_bytes().map(|body| body.len() as u64),
+            None => Some(0),
+        };
+        let response = self.next(&mut ctx, request)?;
+        self.history.entries.push(Entry {
+            timestamp,
+            method,
+            url,
+            status: response.status().as_u16(),
+            duration: response.meta().request_duration.as_millis() as u64,
+            request_size,
+            response_size: response.content_length(),
+            args: mem::take(&mut self.history.args),
+        });
+        Ok(response)
+    }
+}
+
+pub fn history_path() -> Result<PathBuf> {
+    Ok(config_dir()
+        .ok_or_else(|| anyhow!("couldn't get config directory"))?
+        .join("history.jsonl"))
+}
+
+/// Read the history file. A file that doesn't exist yet is empty.
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).context("Failed to read history"),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid entry on line {} of history", number + 1))
+        })
+        .collect()
+}
+
+/// The arguments of the most recent run, for `xh last`.
+pub fn last_args() -> Result<Option<Vec<String>>> {
+    let path = history_path()?;
+    let entries =
+        read(&path).with_context(|| format!("couldn't read history {}", path.display()))?;
+    Ok(entries
+        .into_iter()
+        .rev()
+        .map(|entry| entry.args)
+        .find(|args| !args.is_empty()))
+}
+
+/// Print the entries that contain `filter`, for `xh history [FILTER]`.
+pub fn print_history(filter: Option<&str>) -> Result<()> {
+    let path = history_path()?;
+    let entries =
+        read(&path).with_context(|| format!("couldn't read history {}", path.display()))?;
+    let mut stdout = io::stdout().lock();
+    for entry in entries {
+        let line = format_entry(&entry);
+        if filter.map_or(true, |filter| line.contains(filter)) {
+            writeln!(stdout, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+fn format_entry(entry: &Entry) -> String {
+    let size = |size: Option<u64>| size.map_or_else(|| "?".to_string(), |size| size.to_string());
+    let time = match OffsetDateTime::from_unix_timestamp(entry.timestamp) {
+        Ok(time) => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            time.year(),
+            u8::from(time.month()),
+            time.day(),
+            time.hour(),
+            time.minute(),
+            time.second()
+        ),
+        Err(_) => entry.timestamp.to_string(),
+    };
+    format!(
+        "{}  {} {} {}  {} ms, {} bytes sent, {} bytes received",
+        time,
+        entry.status,
+        entry.method,
+        entry.url,
+        entry.duration,
+        size(entry.request_size),
+        size(entry.response_size)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xh").join("history.jsonl");
+        let entry = Entry {
+            timestamp: 1700000000,
+            method: "POST".to_string(),
+            url: "http://example.com/".to_string(),
+            status: 201,
+            duration: 42,
+            request_size: Some(10),
+            response_size: None,
+            args: vec!["post".to_string(), "example.com".to_string()],
+        };
+        let history = History {
+            args: Vec::new(),
+            entries: vec![entry.clone()],
+        };
+        history.save(&path).unwrap();
+        history.save(&path).unwrap();
+        assert_eq!(read(&path).unwrap(), vec![entry.clone(), entry.clone()]);
+        assert_eq!(read(&dir.path().join("missing")).unwrap(), vec![]);
+
+        assert_eq!(
+            format_entry(&entry),
+            "2023-11-14 22:13:20  201 POST http://example.com/  42 ms, 10 bytes sent, ? bytes received"
+        );
+
+        fs::write(&path, "{}\n").unwrap();
+        assert!(read(&path).is_err());
+    }
+}
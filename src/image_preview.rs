@@ -0,0 +1,128 @@
+//! Showing images inline on terminals that support it.
+//!
+//! Two protocols are supported: kitty's graphics protocol (also used by
+//! Ghostty) and iTerm2's inline images (also used by WezTerm). Sixel isn't,
+//! because it would mean decoding the image ourselves.
+
+use std::env;
+use std::io::{self, Write};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtocol {
+    Kitty,
+    Iterm,
+}
+
+impl ImageProtocol {
+    /// Guess which protocol the terminal understands, if any.
+    pub fn detect() -> Option<ImageProtocol> {
+        detect_protocol(|name| env::var(name).ok())
+    }
+
+    /// Write the escape sequences that display an image.
+    ///
+    /// Returns `false` without writing anything if the terminal can't
+    /// show this image format.
+    pub fn write_image(self, out: &mut impl Write, image: &[u8]) -> io::Result<bool> {
+        match self {
+            ImageProtocol::Kitty => {
+                // kitty can decode PNG but anything else would have to be
+                // sent as raw pixels
+                if !image.starts_with(PNG_SIGNATURE) {
+                    return Ok(false);
+                }
+                // The data has to be sent in chunks of at most 4096 bytes
+                let encoded = BASE64_STANDARD.encode(image);
+                let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = if i + 1 < chunks.len() { 1 } else { 0 };
+                    if i == 0 {
+                        write!(out, "\x1b_Ga=T,f=100,m={};", more)?;
+                    } else {
+                        write!(out, "\x1b_Gm={};", more)?;
+                    }
+                    out.write_all(chunk)?;
+                    write!(out, "\x1b\\")?;
+                }
+            }
+            ImageProtocol::Iterm => {
+                write!(
+                    out,
+                    "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07",
+                    image.len(),
+                    BASE64_STANDARD.encode(image)
+                )?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+fn detect_protocol(var: impl Fn(&str) -> Option<String>) -> Option<ImageProtocol> {
+    let term = var("TERM").unwrap_or_default();
+    let term_program = var("TERM_PROGRAM").unwrap_or_default();
+    if var("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" || term == "xterm-ghostty" {
+        Some(ImageProtocol::Kitty)
+    } else if term_program == "iTerm.app"
+        || term_program == "WezTerm"
+        || var("LC_TERMINAL").as_deref() == Some("iTerm2")
+    {
+        Some(ImageProtocol::Iterm)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(vars: &[(&str, &str)]) -> Option<ImageProtocol> {
+        detect_protocol(|name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn detect_terminal() {
+        assert_eq!(
+            detect(&[("TERM", "xterm-kitty")]),
+            Some(ImageProtocol::Kitty)
+        );
+        assert_eq!(
+            detect(&[("TERM", "xterm-256color"), ("KITTY_WINDOW_ID", "1")]),
+            Some(ImageProtocol::Kitty)
+        );
+        assert_eq!(
+            detect(&[("TERM_PROGRAM", "iTerm.app")]),
+            Some(ImageProtocol::Iterm)
+        );
+        assert_eq!(detect(&[("TERM", "xterm-256color")]), None);
+        assert_eq!(detect(&[]), None);
+    }
+
+    #[test]
+    fn kitty_chunks() {
+        let mut image = PNG_SIGNATURE.to_vec();
+        image.resize(4000, 0);
+        let mut out = Vec::new();
+        assert!(ImageProtocol::Kitty.write_image(&mut out, &image).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("\x1b_Ga=T,f=100,m=1;iVBORw0KGgo"));
+        assert_eq!(out.matches("\x1b_G").count(), 2);
+        assert!(out.contains("\x1b_Gm=0;"));
+        assert!(out.ends_with("\x1b\\"));
+
+        let mut out = Vec::new();
+        assert!(!ImageProtocol::Kitty
+            .write_image(&mut out, b"GIF89a")
+            .unwrap());
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,49 @@
+//! Skipping certificate verification for some hosts only, for --insecure-host.
+//!
+//! The verification itself happens in [`crate::cert_verifier`], which knows
+//! the name of the server for every connection, including connections for
+//! redirects and to proxies.
+
+use url::Url;
+
+/// Whether a URL points at one of the hosts given with --insecure-host.
+pub fn is_insecure_host(url: &Url, insecure_hosts: &[String]) -> bool {
+    url.host_str()
+        .is_some_and(|host| is_insecure_host_name(host, insecure_hosts))
+}
+
+/// Whether a host name or IP address is one of the hosts given with --insecure-host.
+pub fn is_insecure_host_name(host: &str, insecure_hosts: &[String]) -> bool {
+    let normalize = |host: &str| {
+        host.trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim_end_matches('.')
+            .to_ascii_lowercase()
+    };
+    let host = normalize(host);
+    insecure_hosts
+        .iter()
+        .any(|insecure_host| normalize(insecure_host) == host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_matching() {
+        let hosts = vec![
+            "Internal.example".to_string(),
+            "10.0.0.1".to_string(),
+            "[::1]".to_string(),
+        ];
+        let check = |url: &str| is_insecure_host(&url.parse().unwrap(), &hosts);
+        assert!(check("https://internal.example/"));
+        assert!(check("https://internal.example.:8443/path"));
+        assert!(check("https://10.0.0.1/"));
+        assert!(check("https://[::1]:8443/"));
+        assert!(!check("https://example/"));
+        assert!(!check("https://sub.internal.example/"));
+        assert!(is_insecure_host_name("::1", &hosts));
+    }
+}
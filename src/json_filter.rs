@@ -0,0 +1,198 @@
+//! A small subset of jq (and JSONPath) for picking values out of JSON bodies.
+//!
+//! Supported: `.`, `.key`, `."quoted key"`, `["key"]`, `[0]`, `[-1]` and `[]`,
+//! chained together like `.items[0].id` or `.items[].name`. A leading `$` and
+//! `[*]` are accepted as their JSONPath spellings.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Key(String),
+    Index(i64),
+    Iterate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonFilter {
+    source: String,
+    steps: Vec<Step>,
+}
+
+impl FromStr for JsonFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<JsonFilter> {
+        let error = || {
+            anyhow!(
+                "Invalid filter {:?}, expected something like .items[0].id",
+                s
+            )
+        };
+        let mut rest = s.trim();
+        rest = rest.strip_prefix('$').unwrap_or(rest);
+        if rest.is_empty() {
+            return Err(error());
+        }
+
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                if let Some(quoted) = after_dot.strip_prefix('"') {
+                    let end = quoted.find('"').ok_or_else(error)?;
+                    steps.push(Step::Key(quoted[..end].to_string()));
+                    rest = &quoted[end + 1..];
+                } else {
+                    let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+                    if end > 0 {
+                        steps.push(Step::Key(after_dot[..end].to_string()));
+                    }
+                    rest = &after_dot[end..];
+                }
+            } else if let Some(bracketed) = rest.strip_prefix('[') {
+                let end = bracketed.find(']').ok_or_else(error)?;
+                let inner = bracketed[..end].trim();
+                let step = if inner.is_empty() || inner == "*" {
+                    Step::Iterate
+                } else if let Some(key) = inner
+                    .strip_prefix('"')
+                    .and_then(|key| key.strip_suffix('"'))
+                {
+                    Step::Key(key.to_string())
+                } else {
+                    Step::Index(inner.parse().map_err(|_| error())?)
+                };
+                steps.push(step);
+                rest = &bracketed[end + 1..];
+            } else {
+                return Err(error());
+            }
+        }
+
+        Ok(JsonFilter {
+            source: s.to_string(),
+            steps,
+        })
+    }
+}
+
+impl JsonFilter {
+    /// Apply the filter, returning every value it selects.
+    pub fn apply(&self, value: Value) -> Result<Vec<Value>> {
+        let mut values = vec![value];
+        for step in &self.steps {
+            let mut next = Vec::new();
+            for value in values {
+                match (step, value) {
+                    (Step::Key(key), Value::Object(mut object)) => {
+                        next.push(object.remove(key).unwrap_or(Value::Null))
+                    }
+                    (Step::Index(index), Value::Array(mut array)) => {
+                        let index = if *index < 0 {
+                            array.len() as i64 + index
+                        } else {
+                            *index
+                        };
+                        if index >= 0 && (index as usize) < array.len() {
+                            next.push(array.swap_remove(index as usize));
+                        } else {
+                            next.push(Value::Null);
+                        }
+                    }
+                    (Step::Iterate, Value::Array(array)) => next.extend(array),
+                    (Step::Iterate, Value::Object(object)) => {
+                        next.extend(object.into_iter().map(|(_, value)| value))
+                    }
+                    // Like jq, looking into null gives null
+                    (Step::Key(_) | Step::Index(_), Value::Null) => next.push(Value::Null),
+                    (_, value) => {
+                        return Err(anyhow!(
+                            "--filter {:?}: can't apply {} to {}",
+                            self.source,
+                            step.describe(),
+                            type_name(&value)
+                        ))
+                    }
+                }
+            }
+            values = next;
+        }
+        Ok(values)
+    }
+}
+
+impl Step {
+    fn describe(&self) -> String {
+        match self {
+            Step::Key(key) => format!("{:?}", key),
+            Step::Index(index) => format!("[{}]", index),
+            Step::Iterate => "[]".to_string(),
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(expr: &str, value: Value) -> Vec<Value> {
+        expr.parse::<JsonFilter>().unwrap().apply(value).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            ".items[0].id".parse::<JsonFilter>().unwrap().steps,
+            [
+                Step::Key("items".to_string()),
+                Step::Index(0),
+                Step::Key("id".to_string())
+            ]
+        );
+        assert_eq!(
+            "$.items[*][\"a b\"]".parse::<JsonFilter>().unwrap().steps,
+            [
+                Step::Key("items".to_string()),
+                Step::Iterate,
+                Step::Key("a b".to_string())
+            ]
+        );
+        assert!(".".parse::<JsonFilter>().unwrap().steps.is_empty());
+        assert!("items".parse::<JsonFilter>().is_err());
+        assert!(".items[x]".parse::<JsonFilter>().is_err());
+        assert!(".items[0".parse::<JsonFilter>().is_err());
+    }
+
+    #[test]
+    fn apply() {
+        let value = json!({"items": [{"id": 1}, {"id": 2}], "a.b": true});
+        assert_eq!(filter(".items[0].id", value.clone()), [json!(1)]);
+        assert_eq!(filter(".items[-1].id", value.clone()), [json!(2)]);
+        assert_eq!(filter(".items[].id", value.clone()), [json!(1), json!(2)]);
+        assert_eq!(filter(".\"a.b\"", value.clone()), [json!(true)]);
+        assert_eq!(filter(".missing.key", value.clone()), [Value::Null]);
+        assert_eq!(filter(".", value.clone()), std::slice::from_ref(&value));
+
+        let err = ".items.id"
+            .parse::<JsonFilter>()
+            .unwrap()
+            .apply(value)
+            .unwrap_err();
+        assert!(err.to_string().contains("can't apply \"id\" to an array"));
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use reqwest::blocking::{Request, Response};
+
+use crate::middleware::{Context, Middleware, ResponseExt};
+
+/// Write a line of debug information to stderr. This is used by -vv.
+pub fn debug(bin_name: &str, msg: impl std::fmt::Display) {
+    eprintln!("{}: debug: {}", bin_name, msg);
+}
+
+/// Write a warning to stderr. Callers are responsible for respecting -qq.
+pub fn warn(bin_name: &str, msg: impl std::fmt::Display) {
+    eprintln!("{}: warning: {}", bin_name, msg);
+}
+
+/// Logs every request that is actually sent, including the ones made while
+/// following redirects or answering authentication challenges, together with
+/// where it went and how long it took.
+///
+/// This should be the innermost middleware.
+pub struct Logger<'a> {
+    bin_name: &'a str,
+    request_count: usize,
+}
+
+impl<'a> Logger<'a> {
+    pub fn new(bin_name: &'a str) -> Self {
+        Logger {
+            bin_name,
+            request_count: 0,
+        }
+    }
+}
+
+impl<'a> Middleware for Logger<'a> {
+    fn handle(&mut self, mut ctx: Context, request: Request) -> Result<Response> {
+        self.request_count += 1;
+        debug(
+            self.bin_name,
+            format_args!(
+                "request #{}: {} {}",
+                self.request_count,
+                request.method(),
+                request.url()
+            ),
+        );
+
+        let response = match self.next(&mut ctx, request) {
+            Ok(response) => response,
+            Err(err) => {
+                debug(
+                    self.bin_name,
+                    format_args!("request #{} failed: {}", self.request_count, err),
+                );
+                return Err(err);
+            }
+        };
+
+        if let Some(remote_addr) = response.remote_addr() {
+            debug(
+                self.bin_name,
+                format_args!(
+                    "request #{}: connected to {} using {:?}",
+                    self.request_count,
+                    remote_addr,
+                    response.version()
+                ),
+            );
+        }
+        debug(
+            self.bin_name,
+            format_args!(
+                "request #{}: received {} after {:.5}s",
+                self.request_count,
+                response.status(),
+                response.meta().request_duration.as_secs_f64()
+            ),
+        );
+        Ok(response)
+    }
+}
@@ -1,49 +1,94 @@
 #![allow(clippy::bool_assert_comparison)]
 mod auth;
+mod aws_sigv4;
+mod bench;
 mod buffer;
+mod cbor;
+#[cfg(feature = "rustls")]
+mod cert_verifier;
+mod checksum;
 mod cli;
+mod cookie_jar;
 mod decoder;
+mod dns;
 mod download;
+mod dump_header;
 mod formatting;
+mod generators;
+mod graphql;
+mod har;
+mod history;
+mod image_preview;
+mod insecure_hosts;
+mod json_filter;
+mod logging;
 mod middleware;
+mod msgpack;
 mod nested_json;
 mod netrc;
+mod ntlm;
+mod oauth2;
+mod pager;
+mod pinning;
 mod printer;
+mod protobuf;
+mod rate_limit;
 mod redirect;
 mod request_items;
+mod retry;
 mod session;
+mod table;
+mod template;
 mod to_curl;
 mod utils;
 mod vendored;
+mod websocket;
+mod x509;
+mod yaml;
 
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, IsTerminal, Read};
 use std::net::{IpAddr, SocketAddr};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 use cookie_store::{CookieStore, RawCookie};
 use redirect::RedirectFollower;
 use reqwest::blocking::Client;
 use reqwest::header::{
-    HeaderValue, ACCEPT, ACCEPT_ENCODING, CONNECTION, CONTENT_TYPE, COOKIE, RANGE, USER_AGENT,
+    HeaderValue, ACCEPT, ACCEPT_ENCODING, CONNECTION, CONTENT_TYPE, COOKIE, IF_RANGE, RANGE,
+    USER_AGENT,
 };
-use reqwest::tls;
+use reqwest::{tls, StatusCode};
 use url::Host;
 
-use crate::auth::{Auth, DigestAuthMiddleware};
+use crate::auth::{parse_auth, Auth, DigestAuthMiddleware, NtlmAuthMiddleware};
 use crate::buffer::Buffer;
-use crate::cli::{Cli, FormatOptions, HttpVersion, Print, Proxy, Verify};
-use crate::download::{download_file, get_file_size};
-use crate::middleware::ClientWithMiddleware;
+use crate::cli::{AuthType, Cli, FormatOptions, HttpVersion, Print, Proxy, Verify};
+use crate::decoder::{decompress, get_compression_type};
+use crate::download::{download_file, get_file_size, if_range};
+use crate::dump_header::HeaderDumper;
+use crate::har::{Har, HarRecorder};
+use crate::history::{history_path, History, HistoryRecorder};
+use crate::logging::Logger;
+use crate::middleware::{ClientWithMiddleware, ResponseExt};
+use crate::pager::Pager;
 use crate::printer::Printer;
-use crate::request_items::{Body, FORM_CONTENT_TYPE, JSON_ACCEPT, JSON_CONTENT_TYPE};
+use crate::rate_limit::{ChunkedUpload, RateLimit, UploadRateLimiter};
+use crate::request_items::{
+    multipart_content_type, Body, FORM_CONTENT_TYPE, JSON_ACCEPT, JSON_CONTENT_TYPE,
+};
+use crate::retry::Retrier;
 use crate::session::Session;
-use crate::utils::{test_mode, test_pretend_term, url_with_query};
+use crate::utils::{
+    compress_request, read_ca_bundle, response_too_large, stdin_body, test_mode, test_pretend_term,
+    url_with_query, SizeLimit,
+};
 use crate::vendored::reqwest_cookie_store;
 
 #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
@@ -62,17 +107,20 @@ fn main() {
     let args = Cli::parse();
     let bin_name = args.bin_name.clone();
     let native_tls = args.native_tls;
+    let quiet = args.quiet;
 
     match run(args) {
         Ok(exit_code) => {
             process::exit(exit_code);
         }
         Err(err) => {
-            eprintln!("{}: error: {:?}", bin_name, err);
             let msg = err.root_cause().to_string();
-            if native_tls && msg == "invalid minimum TLS version for backend" {
-                eprintln!();
-                eprintln!("Try running without the --native-tls flag.");
+            if quiet < 2 {
+                eprintln!("{}: error: {:?}", bin_name, err);
+                if native_tls && msg == "invalid minimum TLS version for backend" {
+                    eprintln!();
+                    eprintln!("Try running without the --native-tls flag.");
+                }
             }
             if let Some(err) = err.downcast_ref::<reqwest::Error>() {
                 if err.is_timeout() {
@@ -95,11 +143,20 @@ fn run(args: Cli) -> Result<i32> {
 
     let warn = {
         let bin_name = &args.bin_name;
-        move |msg| eprintln!("{}: warning: {}", bin_name, msg)
+        let quiet = args.quiet;
+        move |msg: &str| {
+            if quiet < 2 {
+                logging::warn(bin_name, msg)
+            }
+        }
     };
 
     let (mut headers, headers_to_unset) = args.request_items.headers()?;
-    let url = url_with_query(args.url, &args.request_items.query()?);
+    let url = url_with_query(args.url.clone(), &args.request_items.query()?);
+
+    if websocket::is_websocket_url(&url) {
+        return websocket::run(&args, url, headers);
+    }
 
     let use_stdin = !(args.ignore_stdin || io::stdin().is_terminal() || test_pretend_term());
 
@@ -108,48 +165,115 @@ fn run(args: Cli) -> Result<i32> {
             if args.multipart {
                 // Multipart bodies are never "empty", so we can get here without request items
                 return Err(anyhow!("Cannot build a multipart request body from stdin"));
-            } else {
+            } else if !args.request_items.can_merge_into_json() {
                 return Err(anyhow!(
                     "Request body (from stdin) and request data (key=value) cannot be mixed. \
                     Pass --ignore-stdin to ignore standard input."
                 ));
             }
         }
+        if args.graphql.is_some() {
+            return Err(anyhow!(
+                "Request body from stdin and --graphql cannot be mixed. \
+                Pass --ignore-stdin to ignore standard input."
+            ));
+        }
         if args.raw.is_some() {
             return Err(anyhow!(
                 "Request body from stdin and --raw cannot be mixed. \
                 Pass --ignore-stdin to ignore standard input."
             ));
         }
-        let mut buffer = Vec::new();
-        io::stdin().read_to_end(&mut buffer)?;
-        Body::Raw(buffer)
+        if args.request_items.is_body_empty() {
+            Body::Stdin
+        } else {
+            // Data fields tweak a JSON document from stdin
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .context("Failed to read request body from stdin")?;
+            let base = if args.yaml {
+                yaml::yaml_to_json(&text)
+            } else {
+                serde_json::from_str(&text).map_err(anyhow::Error::from)
+            }
+            .context(
+                "Request body (from stdin) and request data (key=value) cannot be mixed \
+                unless the body is JSON. Pass --ignore-stdin to ignore standard input.",
+            )?;
+            args.request_items.body_merged_into(base)?
+        }
     } else if let Some(raw) = args.raw {
         Body::Raw(raw.into_bytes())
     } else {
         args.request_items.body()?
     };
+    let body = if args.yaml {
+        yaml::convert_body(body)?
+    } else {
+        body
+    };
 
     let method = args.method.unwrap_or_else(|| body.pick_method());
 
+    let is_http10 = matches!(args.http_version, Some(HttpVersion::Http10));
+    if is_http10 && args.chunked {
+        return Err(anyhow!("--chunked can't be used with --http-version=1.0"));
+    }
+    // Signing hashes the payload and challenge-response auth sends it twice
+    let needs_buffered_body = matches!(
+        args.auth_type,
+        Some(AuthType::AwsSigv4 | AuthType::Digest | AuthType::Ntlm)
+    );
+
+    // reqwest's blocking timeout limits how long we wait for the response and
+    // for each read of the body, so it's what --read-timeout needs as well
+    let read_timeout = [&args.timeout, &args.read_timeout]
+        .into_iter()
+        .filter_map(|t| t.as_ref().and_then(|t| t.as_duration()))
+        .min();
+
+    let connect_timeout = args.connect_timeout.as_ref().and_then(|t| t.as_duration());
+
     let mut client = Client::builder()
         .http1_title_case_headers()
         .http2_adaptive_window(true)
         .redirect(reqwest::redirect::Policy::none())
-        .timeout(args.timeout.and_then(|t| t.as_duration()))
+        .timeout(read_timeout)
+        .connect_timeout(connect_timeout)
         .no_gzip()
         .no_deflate()
         .no_brotli();
+    // DNS over HTTPS for --dns-server connects with the same settings
+    let mut doh_client = Client::builder()
+        .timeout(read_timeout.or(Some(dns::TIMEOUT)))
+        .connect_timeout(connect_timeout);
 
     #[cfg(feature = "rustls")]
     if !args.native_tls {
         client = client.use_rustls_tls();
     }
+    // For the certificate details in the metadata
+    client = client.tls_info(true);
 
-    if let Some(tls_version) = args.ssl.and_then(Into::into) {
-        client = client
-            .min_tls_version(tls_version)
-            .max_tls_version(tls_version);
+    let (tls_min, tls_max): (Option<tls::Version>, Option<tls::Version>) = match args.ssl {
+        Some(tls_version) => (tls_version.into(), tls_version.into()),
+        None => (
+            args.tls_min.and_then(Into::into),
+            args.tls_max.and_then(Into::into),
+        ),
+    };
+    if let (Some(tls_min), Some(tls_max)) = (tls_min, tls_max) {
+        if tls_min > tls_max {
+            return Err(anyhow!("--tls-min can't be higher than --tls-max"));
+        }
+    }
+    if let Some(tls_min) = tls_min {
+        client = client.min_tls_version(tls_min);
+    }
+    // Only the maximum matters here, rustls can still satisfy a low minimum
+    if let Some(tls_version) = tls_max {
+        client = client.max_tls_version(tls_version);
 
         #[cfg(feature = "native-tls")]
         if !args.native_tls && tls_version < tls::Version::TLS_1_2 {
@@ -162,6 +286,9 @@ fn run(args: Cli) -> Result<i32> {
             warn("rustls does not support older TLS versions. Consider building with the `native-tls` feature enabled.");
         }
     }
+    let native_tls = args.native_tls
+        || cfg!(feature = "native-tls")
+            && tls_max.is_some_and(|tls_version| tls_version < tls::Version::TLS_1_2);
 
     #[cfg(feature = "native-tls")]
     if args.native_tls {
@@ -190,7 +317,11 @@ fn run(args: Cli) -> Result<i32> {
             Verify::Yes
         }
     });
-    client = match verify {
+    // Only needed when rustls is configured by hand, see cert_verifier.rs
+    let mut root_certs: Vec<Vec<u8>> = Vec::new();
+    #[cfg_attr(not(feature = "rustls"), allow(unused_mut, unused_variables))]
+    let mut identity_pem: Option<Vec<u8>> = None;
+    client = match &verify {
         Verify::Yes => client,
         Verify::No => client.danger_accept_invalid_certs(true),
         Verify::CustomCaBundle(path) => {
@@ -200,16 +331,15 @@ fn run(args: Cli) -> Result<i32> {
                 warn("Custom CA bundles with native-tls are broken");
             }
 
-            let mut buffer = Vec::new();
-            let mut file = File::open(&path).with_context(|| {
-                format!("Failed to open the custom CA bundle: {}", path.display())
-            })?;
-            file.read_to_end(&mut buffer).with_context(|| {
+            let buffer = read_ca_bundle(path).with_context(|| {
                 format!("Failed to read the custom CA bundle: {}", path.display())
             })?;
 
-            client = client.tls_built_in_root_certs(false);
+            if !args.ca_merge {
+                client = client.tls_built_in_root_certs(false);
+            }
             for pem in pem::parse_many(buffer)? {
+                root_certs.push(pem.contents().to_vec());
                 let certificate = reqwest::Certificate::from_pem(pem::encode(&pem).as_bytes())
                     .with_context(|| {
                         format!("Failed to load the custom CA bundle: {}", path.display())
@@ -219,6 +349,16 @@ fn run(args: Cli) -> Result<i32> {
             client
         }
     };
+    doh_client = match &verify {
+        Verify::Yes => doh_client,
+        Verify::No => doh_client.danger_accept_invalid_certs(true),
+        Verify::CustomCaBundle(_) => {
+            for cert in &root_certs {
+                doh_client = doh_client.add_root_certificate(reqwest::Certificate::from_der(cert)?);
+            }
+            doh_client.tls_built_in_root_certs(args.ca_merge)
+        }
+    };
 
     #[cfg(feature = "rustls")]
     if let Some(cert) = args.cert {
@@ -234,7 +374,7 @@ fn run(args: Cli) -> Result<i32> {
         file.read_to_end(&mut buffer)
             .with_context(|| format!("Failed to read the cert file: {}", cert.display()))?;
 
-        if let Some(cert_key) = args.cert_key {
+        if let Some(cert_key) = &args.cert_key {
             buffer.push(b'\n');
 
             let mut file = File::open(&cert_key).with_context(|| {
@@ -245,10 +385,18 @@ fn run(args: Cli) -> Result<i32> {
             })?;
         }
 
+        if !String::from_utf8_lossy(&buffer).contains("PRIVATE KEY-----") {
+            return Err(anyhow!(match &args.cert_key {
+                Some(_) => "The cert key file doesn't contain a PEM private key",
+                None => "The cert file doesn't contain a private key, pass it with --cert-key",
+            }));
+        }
+
         // We may fail here if we can't parse it but also if we don't have the key
         let identity = reqwest::Identity::from_pem(&buffer)
             .context("Failed to load the cert/cert key files")?;
         client = client.identity(identity);
+        identity_pem = Some(buffer);
     }
     #[cfg(not(feature = "rustls"))]
     if args.cert.is_some() {
@@ -257,12 +405,27 @@ fn run(args: Cli) -> Result<i32> {
         warn("Client certificates are not supported for native-tls and this binary was built without rustls support");
     }
 
+    if args.ignore_env_proxy {
+        // This also clears proxies that were added before, so it has to come first
+        client = client.no_proxy();
+        doh_client = doh_client.no_proxy();
+    }
+    let proxy_auth = match &args.proxy_auth {
+        Some(proxy_auth) => Some(parse_auth(proxy_auth, "proxy")?),
+        None => None,
+    };
     for proxy in args.proxy.into_iter().rev() {
-        client = client.proxy(match proxy {
-            Proxy::Http(url) => reqwest::Proxy::http(url),
-            Proxy::Https(url) => reqwest::Proxy::https(url),
-            Proxy::All(url) => reqwest::Proxy::all(url),
-        }?);
+        let (url, mut proxy) = match proxy {
+            Proxy::Http(url) => (url.clone(), reqwest::Proxy::http(url)?),
+            Proxy::Https(url) => (url.clone(), reqwest::Proxy::https(url)?),
+            Proxy::All(url) => (url.clone(), reqwest::Proxy::all(url)?),
+        };
+        // Credentials in the URL take precedence
+        if let (Some((username, password)), "") = (&proxy_auth, url.username()) {
+            proxy = proxy.basic_auth(username, password.as_deref().unwrap_or(""));
+        }
+        doh_client = doh_client.proxy(proxy.clone());
+        client = client.proxy(proxy);
     }
 
     if matches!(
@@ -276,14 +439,63 @@ fn run(args: Cli) -> Result<i32> {
         client = client.http2_prior_knowledge();
     }
 
+    if matches!(args.http_version, Some(HttpVersion::Http3)) {
+        #[cfg(not(feature = "http3"))]
+        return Err(anyhow!(
+            "This binary was built without support for HTTP/3. Enable the `http3` feature."
+        ));
+
+        #[cfg(feature = "http3")]
+        {
+            client = client.http3_prior_knowledge();
+        }
+    }
+
+    if !args.insecure_host.is_empty() || args.pinnedpubkey.is_some() {
+        if native_tls {
+            let option = if args.pinnedpubkey.is_some() {
+                "--pinnedpubkey"
+            } else {
+                "--insecure-host"
+            };
+            return Err(anyhow!("{} is not supported with native-tls", option));
+        }
+        #[cfg(feature = "rustls")]
+        {
+            let alpn_protocols = match args.http_version {
+                Some(HttpVersion::Http10 | HttpVersion::Http11) => vec![b"http/1.1".to_vec()],
+                Some(HttpVersion::Http2PriorKnowledge) => vec![b"h2".to_vec()],
+                Some(HttpVersion::Http3) => vec![b"h3".to_vec()],
+                _ => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            };
+            let config = cert_verifier::client_config(cert_verifier::TlsOptions {
+                verify: verify != Verify::No,
+                insecure_hosts: args.insecure_host.clone(),
+                pins: args.pinnedpubkey.clone(),
+                built_in_roots: !matches!(verify, Verify::CustomCaBundle(_)) || args.ca_merge,
+                root_certs,
+                identity: identity_pem,
+                min_version: tls_min,
+                max_version: tls_max,
+                alpn_protocols,
+            })?;
+            doh_client = doh_client.use_preconfigured_tls(config.clone());
+            client = client.use_preconfigured_tls(config);
+        }
+    }
+
     let cookie_jar = Arc::new(reqwest_cookie_store::CookieStoreMutex::default());
     client = client.cookie_provider(cookie_jar.clone());
 
-    client = match (args.ipv4, args.ipv6) {
-        (true, false) => client.local_address(IpAddr::from_str("0.0.0.0")?),
-        (false, true) => client.local_address(IpAddr::from_str("::")?),
-        _ => client,
+    let local_address = match (args.ipv4, args.ipv6) {
+        (true, false) => Some(IpAddr::from_str("0.0.0.0")?),
+        (false, true) => Some(IpAddr::from_str("::")?),
+        _ => None,
     };
+    if let Some(local_address) = local_address {
+        client = client.local_address(local_address);
+        doh_client = doh_client.local_address(local_address);
+    }
 
     if let Some(name_or_ip) = &args.interface {
         if let Ok(ip_addr) = IpAddr::from_str(name_or_ip) {
@@ -321,7 +533,28 @@ fn run(args: Cli) -> Result<i32> {
         };
     }
 
+    let mut dns_resolver = None;
+    if let Some(server) = &args.dns_server {
+        let resolver = Arc::new(dns::Resolver::new(
+            server.clone(),
+            args.ipv4,
+            args.ipv6,
+            doh_client,
+            (args.verbose > 1).then(|| args.bin_name.clone()),
+        )?);
+        // Look up the first host now so that failures are reported clearly
+        if let Some(Host::Domain(host)) = url.host() {
+            resolver.lookup(host)?;
+        }
+        client = client.dns_resolver(resolver.clone());
+        dns_resolver = Some(resolver);
+    }
+
+    // These take precedence over --dns-server
     for resolve in args.resolve {
+        if resolve.port.is_some() && resolve.port != url.port_or_known_default() {
+            continue;
+        }
         client = client.resolve(&resolve.domain, SocketAddr::new(resolve.addr, 0));
     }
 
@@ -337,6 +570,7 @@ fn run(args: Cli) -> Result<i32> {
         None => None,
     };
 
+    let mut session_cookies = Vec::new();
     if let Some(ref mut s) = session {
         auth = s.auth()?;
 
@@ -356,8 +590,69 @@ fn run(args: Cli) -> Result<i32> {
                 cookie_jar.insert_raw(&cookie?, &url)?;
             }
         }
+        session_cookies = cookie_jar.iter_unexpired().cloned().collect();
     }
 
+    let mut loaded_cookies = Vec::new();
+    let mut jar_cookies = Vec::new();
+    if let Some(path) = &args.cookie_jar {
+        jar_cookies = cookie_jar::load(path, &url)
+            .with_context(|| format!("couldn't load cookie jar {}", path.display()))?;
+        loaded_cookies.extend(jar_cookies.iter().cloned());
+    }
+    for path in args.cookies.iter().filter_map(|c| c.strip_prefix('@')) {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read cookies from {}", path))?;
+        loaded_cookies.extend(
+            cookie_jar::parse(Path::new(path), &text, &url)
+                .with_context(|| format!("couldn't read cookies from {}", path))?,
+        );
+    }
+    if !loaded_cookies.is_empty() {
+        let mut cookie_jar = cookie_jar.lock().unwrap();
+        // Cookies from files replace those from the session
+        let existing: Vec<_> = cookie_jar.iter_unexpired().cloned().collect();
+        *cookie_jar = CookieStore::from_cookies(
+            existing
+                .into_iter()
+                .chain(loaded_cookies)
+                .map(Ok::<_, anyhow::Error>),
+            false,
+        )?;
+    }
+
+    if !args.cookies.is_empty() {
+        let mut cookie_jar = cookie_jar.lock().unwrap();
+        // The client replaces any Cookie header with the one from the store,
+        // so a manual header has to go in the store as well
+        let header = headers
+            .remove(COOKIE)
+            .map(|cookie| cookie.to_str().map(str::to_string))
+            .transpose()?;
+        let cookies = header
+            .iter()
+            .chain(args.cookies.iter().filter(|c| !c.starts_with('@')));
+        for cookies in cookies {
+            for cookie in RawCookie::split_parse(cookies.as_str()) {
+                cookie_jar.insert_raw(&cookie?, &url)?;
+            }
+        }
+    }
+
+    // Only cookies that the server sets are saved, besides those that were
+    // loaded from the same file. So --cookie doesn't end up in the jar, and
+    // the jar doesn't end up in the session.
+    let initial_cookies: Vec<_> = cookie_jar
+        .lock()
+        .unwrap()
+        .iter_unexpired()
+        .cloned()
+        .collect();
+    let to_save = |cookie: &&cookie_store::Cookie<'static>,
+                   loaded: &[cookie_store::Cookie<'static>]| {
+        !initial_cookies.contains(cookie) || loaded.contains(cookie)
+    };
+
     let mut request = {
         let mut request_builder = client
             .request(method, url.clone())
@@ -369,7 +664,7 @@ fn run(args: Cli) -> Result<i32> {
 
         if matches!(
             args.http_version,
-            Some(HttpVersion::Http10) | Some(HttpVersion::Http11) | None
+            Some(HttpVersion::Http10) | Some(HttpVersion::Http11) | Some(HttpVersion::Auto) | None
         ) {
             request_builder =
                 request_builder.header(CONNECTION, HeaderValue::from_static("keep-alive"));
@@ -381,12 +676,16 @@ fn run(args: Cli) -> Result<i32> {
             Some(HttpVersion::Http2 | HttpVersion::Http2PriorKnowledge) => {
                 request_builder.version(reqwest::Version::HTTP_2)
             }
-            None => request_builder,
+            Some(HttpVersion::Http3) => request_builder.version(reqwest::Version::HTTP_3),
+            Some(HttpVersion::Auto) | None => request_builder,
         };
 
         request_builder = match body {
             Body::Form(body) => request_builder.form(&body),
             Body::Multipart(body) => request_builder.multipart(body),
+            Body::MultipartWithBoundary { boundary, body } => request_builder
+                .header(CONTENT_TYPE, multipart_content_type(&boundary))
+                .body(body),
             Body::Json(body) => {
                 // An empty JSON body would produce null instead of "", so
                 // this is the one kind of body that needs an is_null() check
@@ -404,29 +703,48 @@ fn run(args: Cli) -> Result<i32> {
                     request_builder
                 }
             }
-            Body::Raw(body) => {
-                if args.form {
+            Body::Raw(..) | Body::Stdin => {
+                let request_builder = if args.form {
                     request_builder
                         .header(CONTENT_TYPE, HeaderValue::from_static(FORM_CONTENT_TYPE))
                 } else {
                     request_builder
                         .header(ACCEPT, HeaderValue::from_static(JSON_ACCEPT))
                         .header(CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE))
+                };
+                match body {
+                    Body::Raw(body) => request_builder.body(body),
+                    // HTTP/1.0 has no chunked encoding, so a pipe can't be streamed,
+                    // and some auth types have to read or resend the payload
+                    _ => request_builder
+                        .body(stdin_body(is_http10 || needs_buffered_body, args.chunked)?),
                 }
             }
-            .body(body),
             Body::File {
                 file_name,
                 file_type,
-            } => request_builder.body(File::open(file_name)?).header(
-                CONTENT_TYPE,
-                file_type.unwrap_or_else(|| HeaderValue::from_static(JSON_CONTENT_TYPE)),
-            ),
+            } => {
+                let file = File::open(file_name)?;
+                request_builder
+                    .body(if args.chunked {
+                        reqwest::blocking::Body::new(file)
+                    } else {
+                        file.into()
+                    })
+                    .header(
+                        CONTENT_TYPE,
+                        file_type.unwrap_or_else(|| HeaderValue::from_static(JSON_CONTENT_TYPE)),
+                    )
+            }
         };
 
         if args.resume {
             if let Some(file_size) = get_file_size(args.output.as_deref()) {
                 request_builder = request_builder.header(RANGE, format!("bytes={}-", file_size));
+                // A file that changed since is downloaded again in full
+                if let Some(if_range) = args.output.as_deref().and_then(if_range) {
+                    request_builder = request_builder.header(IF_RANGE, if_range);
+                }
                 resume = Some(file_size);
             }
         }
@@ -438,11 +756,20 @@ fn run(args: Cli) -> Result<i32> {
                 auth_type,
                 url.host_str().unwrap_or("<host>"),
             )?);
+        } else if let Some(auth_from_env) = Auth::from_env(auth_type) {
+            auth = Some(auth_from_env);
+            save_auth_in_session = false;
         } else if !args.ignore_netrc {
             // I don't know if it's possible for host() to return None
             // But if it does we still want to use the default entry, if there is one
             let host = url.host().unwrap_or(Host::Domain(""));
-            if let Some(entry) = netrc::find_entry(host) {
+            let entry = match args.netrc_file.as_deref() {
+                Some(path) => netrc::find_entry(host, Some(path))
+                    .with_context(|| format!("couldn't read netrc file {:?}", path))?,
+                // Only an explicitly given file can cause an error
+                None => netrc::find_entry(host, None)?,
+            };
+            if let Some(entry) = entry {
                 auth = Auth::from_netrc(auth_type, entry);
                 save_auth_in_session = false;
             }
@@ -459,7 +786,24 @@ fn run(args: Cli) -> Result<i32> {
                     request_builder.basic_auth(username, password.as_ref())
                 }
                 Auth::Bearer(token) => request_builder.bearer_auth(token),
-                Auth::Digest(..) => request_builder,
+                Auth::OAuth2(..) if args.offline => request_builder,
+                Auth::OAuth2(client_id, client_secret) => {
+                    let token_url = args
+                        .oauth2_token_url
+                        .as_ref()
+                        .context("--auth-type=oauth2 requires --oauth2-token-url")?;
+                    let token = oauth2::access_token(
+                        &client,
+                        &oauth2::ClientCredentials {
+                            token_url,
+                            client_id,
+                            client_secret,
+                            scope: args.oauth2_scope.as_deref(),
+                        },
+                    )?;
+                    request_builder.bearer_auth(token)
+                }
+                Auth::Digest(..) | Auth::Ntlm(..) | Auth::AwsSigv4(..) => request_builder,
             }
         }
 
@@ -469,20 +813,60 @@ fn run(args: Cli) -> Result<i32> {
             request.headers_mut().remove(header);
         }
 
+        if let Some(level) = args.compress {
+            compress_request(&mut request, level)?;
+        }
+
+        // The signature covers the final headers and body, so this has to come last
+        if let Some(Auth::AwsSigv4(access_key, secret_key)) = &auth {
+            let session_token = env::var("AWS_SESSION_TOKEN").ok();
+            aws_sigv4::sign_request(
+                &mut request,
+                &aws_sigv4::Credentials {
+                    access_key,
+                    secret_key,
+                    session_token: session_token.as_deref(),
+                },
+                args.aws_sigv4.as_ref(),
+            )?;
+        }
+
         request
     };
 
+    if let Some(count) = args.bench {
+        // The benchmark sends requests without going through the middleware
+        if matches!(auth, Some(Auth::Digest(..) | Auth::Ntlm(..))) {
+            return Err(anyhow!("--bench can't be used with digest or NTLM auth"));
+        }
+        let report = bench::run(&client, &mut request, count, args.concurrency.unwrap_or(1))?;
+        print!("{}", report);
+        return Ok(exit_code);
+    }
+
     if args.download {
         request
             .headers_mut()
             .insert(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
     }
 
-    let buffer = Buffer::new(
-        args.download,
-        args.output.as_deref(),
-        io::stdout().is_terminal() || test_pretend_term(),
-    )?;
+    let paging = !args.download
+        && args.output.is_none()
+        && io::stdout().is_terminal()
+        && args.pager.unwrap_or(args.stream != Some(true));
+    // The pager has to be dropped after the buffer so it sees the end of the
+    // output before we wait for it
+    let (_pager, buffer) = match paging.then(Pager::start).flatten() {
+        Some((pager, input)) => (Some(pager), Buffer::pager(input)),
+        None => (
+            None,
+            Buffer::new(
+                args.download,
+                args.output.as_deref(),
+                io::stdout().is_terminal() || test_pretend_term(),
+            )?,
+        ),
+    };
     let is_output_redirected = buffer.is_redirect();
     let print = match args.print {
         Some(print) => print,
@@ -491,22 +875,44 @@ fn run(args: Cli) -> Result<i32> {
             args.headers,
             args.body,
             args.meta,
-            args.quiet,
+            args.quiet > 0,
             args.offline,
             &buffer,
         ),
     };
     let theme = args.style.unwrap_or_default();
     let pretty = args.pretty.unwrap_or_else(|| buffer.guess_pretty());
+    let pretty = match args.color {
+        Some(color) => pretty.with_color(color),
+        None => pretty,
+    };
     let format_options = args
         .format_options
         .iter()
         .fold(FormatOptions::default(), FormatOptions::merge);
-    let mut printer = Printer::new(pretty, theme, args.stream, buffer, format_options);
+    let protobuf = match (&args.proto, &args.proto_type) {
+        (Some(path), Some(message_type)) => Some(protobuf::Decoder::load(path, message_type)?),
+        _ => None,
+    };
+    let mut printer = Printer::new(pretty, theme, args.stream, buffer, format_options)
+        .with_json_filter(args.filter.clone())
+        .with_protobuf(protobuf)
+        .with_max_body_size(args.max_response_size)
+        .with_rate_limit(args.limit_rate)
+        .with_chunked(args.chunked)
+        .with_negotiated_version(matches!(args.http_version, Some(HttpVersion::Auto) | None))
+        .with_graphql(args.graphql.is_some())
+        .with_dns_resolver(dns_resolver);
 
     let response_charset = args.response_charset;
     let response_mime = args.response_mime.as_deref();
 
+    if print.request_headers && print.request_body {
+        // Read a streamed body now so the headers can include its length
+        if let Some(body) = request.body_mut() {
+            body.buffer()?;
+        }
+    }
     if print.request_headers {
         printer.print_request_headers(&request, &*cookie_jar)?;
     }
@@ -514,6 +920,9 @@ fn run(args: Cli) -> Result<i32> {
         printer.print_request_body(&mut request)?;
     }
 
+    let mut har = args.har.as_ref().map(|_| Har::new());
+    let mut history = args.history.then(|| History::new(args.raw_args.clone()));
+
     if !args.offline {
         let mut response = {
             let history_print = args.history_print.unwrap_or(print);
@@ -544,11 +953,50 @@ fn run(args: Cli) -> Result<i32> {
                 });
             }
             if args.follow {
-                client = client.with(RedirectFollower::new(args.max_redirects.unwrap_or(10)));
+                client = client.with(RedirectFollower::new(
+                    args.max_redirects.unwrap_or(10),
+                    args.preserve_method,
+                    args.trust_redirects,
+                ));
             }
             if let Some(Auth::Digest(username, password)) = &auth {
                 client = client.with(DigestAuthMiddleware::new(username, password));
             }
+            if let Some(Auth::Ntlm(username, password)) = &auth {
+                client = client.with(NtlmAuthMiddleware::new(username, password));
+            }
+            if let Some(path) = &args.dump_header {
+                client = client.with(HeaderDumper::open(path, args.dump_header_append)?);
+            }
+            if let Some(history) = &mut history {
+                client = client.with(HistoryRecorder::new(history));
+            }
+            if let Some(retries) = args.retry.filter(|&retries| retries > 0) {
+                let retried = args.retry_all_methods || retry::is_idempotent(request.method());
+                if retried && request.body().is_some_and(|body| body.as_bytes().is_none()) {
+                    warn(
+                        "--retry reads the whole request body into memory so it can be sent again",
+                    );
+                }
+                client = client.with(Retrier::new(
+                    retries,
+                    args.retry_delay.unwrap_or(Duration::from_secs(1)),
+                    args.retry_all_methods,
+                    &args.bin_name,
+                    args.verbose > 0,
+                ));
+            }
+            if let Some(har) = &mut har {
+                client = client.with(HarRecorder::new(har));
+            }
+            if let Some(rate) = args.limit_rate {
+                client = client.with(UploadRateLimiter::new(rate, args.chunked));
+            } else if args.chunked {
+                client = client.with(ChunkedUpload);
+            }
+            if args.verbose > 1 {
+                client = client.with(Logger::new(&args.bin_name));
+            }
             client.execute(request)?
         };
 
@@ -564,23 +1012,46 @@ fn run(args: Cli) -> Result<i32> {
         if is_output_redirected && exit_code != 0 {
             warn(&format!("HTTP {}", status));
         }
+        if status == StatusCode::PROXY_AUTHENTICATION_REQUIRED && args.proxy_auth.is_none() {
+            warn("The proxy requires authentication, pass credentials with --proxy-auth");
+        }
 
         if print.response_headers {
             printer.print_response_headers(&response)?;
         }
+        if let (Some(limit), Some(length)) = (args.max_response_size, response.content_length()) {
+            // Catch the obvious cases before reading anything. Compressed
+            // bodies are still checked after decoding.
+            if length > limit {
+                return Err(response_too_large(limit).into());
+            }
+        }
         if args.download {
             if exit_code == 0 {
                 download_file(
                     response,
                     args.output,
+                    args.output_dir.as_deref(),
+                    args.no_clobber,
                     &url,
                     resume,
+                    args.checksum,
+                    args.max_response_size,
+                    args.limit_rate,
                     pretty.color(),
-                    args.quiet,
+                    args.quiet > 0,
                 )?;
             }
         } else {
             if print.response_body {
+                if har.is_some() {
+                    printer.capture_response_body();
+                }
+                if let Some(path) = &args.tee {
+                    let file = File::create(path)
+                        .with_context(|| format!("couldn't create {}", path.display()))?;
+                    printer.tee_response_body(file);
+                }
                 printer.print_response_body(&mut response, response_charset, response_mime)?;
                 if print.response_meta {
                     printer.print_separator()?;
@@ -589,12 +1060,62 @@ fn run(args: Cli) -> Result<i32> {
             if print.response_meta {
                 printer.print_response_meta(&response)?;
             }
+            if args.tee.is_some() && !print.response_body {
+                warn("--tee has no effect when the response body isn't printed");
+            }
+            if let Some(har) = &mut har {
+                let body = match printer.take_captured_body() {
+                    Some(body) => body,
+                    None => {
+                        let mut body = Vec::new();
+                        let compression_type = get_compression_type(response.headers());
+                        SizeLimit::new(
+                            decompress(
+                                &mut RateLimit::new(&mut response, args.limit_rate),
+                                compression_type,
+                            ),
+                            args.max_response_size,
+                        )
+                        .read_to_end(&mut body)?;
+                        body
+                    }
+                };
+                har.set_response_body(&body, response.meta().content_download_duration);
+            }
+            if let (Some(history), Some(size)) = (&mut history, response.meta().body_size) {
+                history.set_response_size(size);
+            }
+        }
+    }
+
+    if let (Some(har), Some(path)) = (&har, &args.har) {
+        har.write(path)
+            .with_context(|| format!("couldn't write HAR file {}", path.display()))?;
+    }
+
+    if let Some(history) = &history {
+        // Failing to keep a history shouldn't fail the request
+        if let Err(err) = history_path().and_then(|path| history.save(&path)) {
+            warn(&format!("couldn't save history: {:#}", err));
         }
     }
 
+    if let Some(path) = &args.cookie_jar {
+        let cookie_jar = cookie_jar.lock().unwrap();
+        let cookies = cookie_jar
+            .iter_unexpired()
+            .filter(|cookie| to_save(cookie, &jar_cookies));
+        cookie_jar::save(path, cookies)
+            .with_context(|| format!("couldn't save cookie jar {}", path.display()))?;
+    }
+
     if let Some(ref mut s) = session {
         let cookie_jar = cookie_jar.lock().unwrap();
-        s.save_cookies(cookie_jar.iter_unexpired());
+        s.save_cookies(
+            cookie_jar
+                .iter_unexpired()
+                .filter(|cookie| to_save(cookie, &session_cookies)),
+        );
         s.persist()
             .with_context(|| format!("couldn't persist session {}", s.path.display()))?;
     }
@@ -7,6 +7,10 @@ use reqwest::blocking::{Client, Request, Response};
 pub struct ResponseMeta {
     pub request_duration: Duration,
     pub content_download_duration: Option<Duration>,
+    /// Size of the decoded body, if it has been read.
+    pub body_size: Option<u64>,
+    /// Size of the body as it was received, before decompression.
+    pub raw_body_size: Option<u64>,
 }
 
 pub trait ResponseExt {
@@ -53,6 +57,8 @@ impl<'a, 'b> Context<'a, 'b> {
                 response.extensions_mut().insert(ResponseMeta {
                     request_duration: starting_time.elapsed(),
                     content_download_duration: None,
+                    body_size: None,
+                    raw_body_size: None,
                 });
                 Ok(response)
             }
@@ -0,0 +1,248 @@
+//! Decoding MessagePack bodies to JSON, so they can be formatted and filtered.
+//!
+//! See https://github.com/msgpack/msgpack/blob/master/spec.md. Binary data is
+//! shown as base64, timestamps as RFC 3339 strings and other extension types as
+//! `{"type": TYPE, "data": BASE64}`. Map keys that aren't strings are turned
+//! into JSON text.
+
+use anyhow::{anyhow, bail, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde_json::{Map, Value};
+use time::OffsetDateTime;
+
+use crate::utils::{json_f32, json_float};
+
+/// Values that are nested deeper than this are assumed to be malformed.
+const MAX_DEPTH: usize = 100;
+
+/// The extension type of timestamps.
+const TIMESTAMP: i8 = -1;
+
+/// Decode a single MessagePack value.
+pub fn decode(data: &[u8]) -> Result<Value> {
+    let mut reader = Reader { data };
+    let value = reader
+        .value(0)
+        .map_err(|err| anyhow!("Invalid MessagePack body: {}", err))?;
+    if !reader.data.is_empty() {
+        bail!(
+            "Invalid MessagePack body: {} bytes of trailing data",
+            reader.data.len()
+        );
+    }
+    Ok(value)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.data.len() {
+            bail!("unexpected end of data");
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    /// Read the length that follows a marker, which takes up `size` bytes.
+    fn len(&mut self, size: usize) -> Result<usize> {
+        Ok(match size {
+            1 => self.u8()? as usize,
+            2 => self.u16()? as usize,
+            _ => self.u32()? as usize,
+        })
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("data is nested too deeply");
+        }
+        let marker = self.u8()?;
+        Ok(match marker {
+            0x00..=0x7f => Value::from(marker),
+            0x80..=0x8f => self.map(marker as usize & 0x0f, depth)?,
+            0x90..=0x9f => self.seq(marker as usize & 0x0f, depth)?,
+            0xa0..=0xbf => self.str(marker as usize & 0x1f)?,
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xc4..=0xc6 => {
+                let len = self.len(1 << (marker - 0xc4))?;
+                Value::String(BASE64_STANDARD.encode(self.take(len)?))
+            }
+            0xc7..=0xc9 => {
+                let len = self.len(1 << (marker - 0xc7))?;
+                self.ext(len)?
+            }
+            0xca => json_f32(f32::from_bits(self.u32()?)),
+            0xcb => json_float(f64::from_bits(self.u64()?)),
+            0xcc => Value::from(self.u8()?),
+            0xcd => Value::from(self.u16()?),
+            0xce => Value::from(self.u32()?),
+            0xcf => Value::from(self.u64()?),
+            0xd0 => Value::from(self.u8()? as i8),
+            0xd1 => Value::from(self.u16()? as i16),
+            0xd2 => Value::from(self.u32()? as i32),
+            0xd3 => Value::from(self.u64()? as i64),
+            0xd4..=0xd8 => self.ext(1 << (marker - 0xd4))?,
+            0xd9..=0xdb => {
+                let len = self.len(1 << (marker - 0xd9))?;
+                self.str(len)?
+            }
+            0xdc | 0xdd => {
+                let len = self.len(2 << (marker - 0xdc))?;
+                self.seq(len, depth)?
+            }
+            0xde | 0xdf => {
+                let len = self.len(2 << (marker - 0xde))?;
+                self.map(len, depth)?
+            }
+            0xe0..=0xff => Value::from(marker as i8),
+            0xc1 => bail!("invalid marker 0xc1"),
+        })
+    }
+
+    fn str(&mut self, len: usize) -> Result<Value> {
+        Ok(Value::String(
+            String::from_utf8_lossy(self.take(len)?).into_owned(),
+        ))
+    }
+
+    fn seq(&mut self, len: usize, depth: usize) -> Result<Value> {
+        // Every element takes at least one byte, so don't trust the length further than that
+        let mut elements = Vec::with_capacity(len.min(self.data.len()));
+        for _ in 0..len {
+            elements.push(self.value(depth + 1)?);
+        }
+        Ok(Value::Array(elements))
+    }
+
+    fn map(&mut self, len: usize, depth: usize) -> Result<Value> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let key = match self.value(depth + 1)? {
+                Value::String(key) => key,
+                key => key.to_string(),
+            };
+            map.insert(key, self.value(depth + 1)?);
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn ext(&mut self, len: usize) -> Result<Value> {
+        let ext_type = self.u8()? as i8;
+        let data = self.take(len)?;
+        if ext_type == TIMESTAMP {
+            if let Some(timestamp) = timestamp(data) {
+                return Ok(Value::String(timestamp));
+            }
+        }
+        let mut ext = Map::new();
+        ext.insert("type".to_string(), Value::from(ext_type));
+        ext.insert(
+            "data".to_string(),
+            Value::String(BASE64_STANDARD.encode(data)),
+        );
+        Ok(Value::Object(ext))
+    }
+}
+
+/// Format a timestamp extension value in RFC 3339 format.
+fn timestamp(data: &[u8]) -> Option<String> {
+    let (seconds, nanoseconds) = match data.len() {
+        4 => (i64::from(u32::from_be_bytes(data.try_into().ok()?)), 0),
+        8 => {
+            let value = u64::from_be_bytes(data.try_into().ok()?);
+            ((value & 0x3_ffff_ffff) as i64, (value >> 34) as u32)
+        }
+        12 => (
+            i64::from_be_bytes(data[4..].try_into().ok()?),
+            u32::from_be_bytes(data[..4].try_into().ok()?),
+        ),
+        _ => return None,
+    };
+    let time = OffsetDateTime::from_unix_timestamp(seconds)
+        .ok()?
+        .replace_nanosecond(nanoseconds)
+        .ok()?;
+    let mut text = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        time.year(),
+        u8::from(time.month()),
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    );
+    if nanoseconds != 0 {
+        let fraction = format!("{:09}", nanoseconds);
+        text.push('.');
+        text.push_str(fraction.trim_end_matches('0'));
+    }
+    text.push('Z');
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn decode_values() {
+        assert_eq!(
+            decode(b"\x87\xa4name\xa2xh\xa2id\xcd\x01\x2c\xa3neg\xe0\xa4tags\x92\xa3cli\xc0\xa5ratio\xcb\x3f\xb9\x99\x99\x99\x99\x99\x9a\xa3bin\xc4\x02\xff\x00\x01\xc3").unwrap(),
+            json!({
+                "name": "xh",
+                "id": 300,
+                "neg": -32,
+                "tags": ["cli", null],
+                "ratio": 0.1,
+                "bin": "/wA=",
+                "1": true,
+            })
+        );
+        assert_eq!(
+            decode(b"\x93\xd3\xff\xff\xff\xff\xff\xff\xff\xff\xcf\xff\xff\xff\xff\xff\xff\xff\xff\xca\x7f\xc0\x00\x00").unwrap(),
+            json!([-1, u64::MAX, "NaN"])
+        );
+        assert_eq!(
+            decode(b"\x92\xd6\xff\x65\x53\xf1\x00\xd7\xff\x77\x35\x94\x00\x65\x53\xf1\x00")
+                .unwrap(),
+            json!(["2023-11-14T22:13:20Z", "2023-11-14T22:13:20.5Z"])
+        );
+        assert_eq!(
+            decode(b"\xd5\x01\xab\xcd").unwrap(),
+            json!({"type": 1, "data": "q80="})
+        );
+
+        assert!(decode(b"\x92\x01").is_err());
+        assert!(decode(b"\x01\x02").is_err());
+        assert!(decode(b"\xc1").is_err());
+        assert!(decode(&[0x91; 200]).is_err());
+    }
+}
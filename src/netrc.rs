@@ -29,6 +29,7 @@
 use std::{
     fs::File,
     io::{self, BufRead, BufReader},
+    path::Path,
 };
 
 use encoding_rs::UTF_8;
@@ -42,8 +43,17 @@ pub struct Entry {
     pub password: String,
 }
 
-pub fn find_entry(host: url::Host<&str>) -> Option<Entry> {
-    let file = open_netrc()?;
+/// Look up the entry for `host` in `netrc_file`, or in the default locations.
+///
+/// Only an explicitly given file that can't be opened is an error.
+pub fn find_entry(host: url::Host<&str>, netrc_file: Option<&Path>) -> io::Result<Option<Entry>> {
+    let file = match netrc_file {
+        Some(path) => File::open(path)?,
+        None => match open_netrc() {
+            Some(file) => file,
+            None => return Ok(None),
+        },
+    };
     // UTF-16 is detected if it has a BOM.
     // Invalid UTF-8 is sanitized with replacement characters. That way it
     // at least won't stop us from parsing the rest of the file.
@@ -54,7 +64,7 @@ pub fn find_entry(host: url::Host<&str>) -> Option<Entry> {
     let file = BufReader::new(file);
     let parser = Parser::new(file, host);
     // Logging I/O errors would be nice.
-    parser.parse().ok()?
+    Ok(parser.parse().ok().flatten())
 }
 
 fn open_netrc() -> Option<File> {
@@ -0,0 +1,261 @@
+//! Just enough of NTLMv2 to authenticate against a server.
+//!
+//! See https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-nlmp/
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const NEGOTIATE_OEM: u32 = 0x0000_0002;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+
+const NEGOTIATE_FLAGS: u32 = NEGOTIATE_UNICODE
+    | NEGOTIATE_OEM
+    | REQUEST_TARGET
+    | NEGOTIATE_NTLM
+    | NEGOTIATE_ALWAYS_SIGN
+    | NEGOTIATE_EXTENDED_SESSIONSECURITY;
+
+const MSV_AV_EOL: u16 = 0;
+const MSV_AV_TIMESTAMP: u16 = 7;
+
+/// Seconds between 1601-01-01 (the Windows epoch) and 1970-01-01.
+const WINDOWS_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+pub struct Challenge {
+    server_challenge: [u8; 8],
+    target_info: Vec<u8>,
+}
+
+/// Build the NEGOTIATE_MESSAGE that starts the handshake.
+pub fn negotiate_message() -> Vec<u8> {
+    let mut message = Vec::with_capacity(32);
+    message.extend_from_slice(SIGNATURE);
+    message.extend_from_slice(&1u32.to_le_bytes());
+    message.extend_from_slice(&NEGOTIATE_FLAGS.to_le_bytes());
+    // Empty domain and workstation fields
+    message.extend_from_slice(&[0; 16]);
+    message
+}
+
+/// Parse the CHALLENGE_MESSAGE sent by the server.
+pub fn parse_challenge(message: &[u8]) -> Result<Challenge> {
+    if message.len() < 32 || &message[..8] != SIGNATURE || read_u32(message, 8) != Some(2) {
+        return Err(anyhow!("Invalid NTLM challenge from server"));
+    }
+    let mut server_challenge = [0; 8];
+    server_challenge.copy_from_slice(&message[24..32]);
+    let target_info = match (read_u16(message, 40), read_u32(message, 44)) {
+        (Some(len), Some(offset)) => message
+            .get(offset as usize..offset as usize + len as usize)
+            .ok_or_else(|| anyhow!("Invalid NTLM challenge from server"))?
+            .to_vec(),
+        _ => Vec::new(),
+    };
+    Ok(Challenge {
+        server_challenge,
+        target_info,
+    })
+}
+
+/// Build the AUTHENTICATE_MESSAGE in response to a challenge.
+///
+/// `username` may be given as `DOMAIN\user`.
+pub fn authenticate_message(challenge: &Challenge, username: &str, password: &str) -> Vec<u8> {
+    let (domain, user) = username.split_once('\\').unwrap_or(("", username));
+    let client_challenge: [u8; 8] = rand::random();
+    let server_timestamp = find_timestamp(&challenge.target_info);
+    let timestamp = server_timestamp.unwrap_or_else(|| {
+        let since_unix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        (since_unix.as_secs() + WINDOWS_EPOCH_OFFSET) * 10_000_000
+            + u64::from(since_unix.subsec_nanos() / 100)
+    });
+
+    let hash = ntlmv2_hash(user, domain, password);
+    let nt_response = nt_response(&hash, challenge, client_challenge, timestamp);
+    // MS-NLMP 3.1.5.1.2: the LMv2 response is all zeros when the server sent a
+    // timestamp, since the NTLMv2 response then covers the same ground
+    let lm_response = if server_timestamp.is_some() {
+        vec![0; 24]
+    } else {
+        let mut lm_response =
+            hmac_md5(&hash, &[&challenge.server_challenge[..], &client_challenge]);
+        lm_response.extend_from_slice(&client_challenge);
+        lm_response
+    };
+
+    let fields = [
+        lm_response,
+        nt_response,
+        utf16(domain),
+        utf16(user),
+        Vec::new(), // workstation
+        Vec::new(), // encrypted random session key
+    ];
+
+    let header_len = 64;
+    let mut message = Vec::new();
+    message.extend_from_slice(SIGNATURE);
+    message.extend_from_slice(&3u32.to_le_bytes());
+    let mut offset = header_len;
+    for field in &fields {
+        message.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        message.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        message.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    message.extend_from_slice(&NEGOTIATE_FLAGS.to_le_bytes());
+    for field in &fields {
+        message.extend_from_slice(field);
+    }
+    message
+}
+
+fn ntlmv2_hash(user: &str, domain: &str, password: &str) -> Vec<u8> {
+    let nt_hash = Md4::digest(utf16(password));
+    hmac_md5(&nt_hash, &[&utf16(&(user.to_uppercase() + domain))])
+}
+
+fn nt_response(
+    hash: &[u8],
+    challenge: &Challenge,
+    client_challenge: [u8; 8],
+    timestamp: u64,
+) -> Vec<u8> {
+    let mut blob = vec![1, 1, 0, 0, 0, 0, 0, 0];
+    blob.extend_from_slice(&timestamp.to_le_bytes());
+    blob.extend_from_slice(&client_challenge);
+    blob.extend_from_slice(&[0; 4]);
+    blob.extend_from_slice(&challenge.target_info);
+    blob.extend_from_slice(&[0; 4]);
+
+    let mut response = hmac_md5(hash, &[&challenge.server_challenge[..], &blob]);
+    response.extend_from_slice(&blob);
+    response
+}
+
+fn find_timestamp(target_info: &[u8]) -> Option<u64> {
+    let mut pos = 0;
+    loop {
+        let id = read_u16(target_info, pos)?;
+        let len = read_u16(target_info, pos + 2)? as usize;
+        let value = target_info.get(pos + 4..pos + 4 + len)?;
+        match id {
+            MSV_AV_EOL => return None,
+            MSV_AV_TIMESTAMP => return Some(u64::from_le_bytes(value.try_into().ok()?)),
+            _ => pos += 4 + len,
+        }
+    }
+}
+
+fn hmac_md5(key: &[u8], data: &[&[u8]]) -> Vec<u8> {
+    let mut mac = Hmac::<Md5>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    for chunk in data {
+        mac.update(chunk);
+    }
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn utf16(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(pos..pos + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(pos..pos + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn av_pair(id: u16, value: &str) -> Vec<u8> {
+        let value = utf16(value);
+        let mut pair = id.to_le_bytes().to_vec();
+        pair.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        pair.extend_from_slice(&value);
+        pair
+    }
+
+    // Values from section 4.2.4 of MS-NLMP
+    #[test]
+    fn ntlmv2_response() {
+        let hash = ntlmv2_hash("User", "Domain", "Password");
+        assert_eq!(hex(&hash), "0c868a403bfd7a93a3001ef22ef02e3f");
+
+        let mut target_info = av_pair(2, "Domain");
+        target_info.extend(av_pair(1, "Server"));
+        target_info.extend_from_slice(&[0; 4]);
+        let challenge = Challenge {
+            server_challenge: [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef],
+            target_info,
+        };
+        let response = nt_response(&hash, &challenge, [0xaa; 8], 0);
+        assert_eq!(hex(&response[..16]), "68cd0ab851e51c96aabc927bebef6a1c");
+    }
+
+    #[test]
+    fn challenge_roundtrip() {
+        let mut target_info = MSV_AV_TIMESTAMP.to_le_bytes().to_vec();
+        target_info.extend_from_slice(&8u16.to_le_bytes());
+        target_info.extend_from_slice(&42u64.to_le_bytes());
+        target_info.extend_from_slice(&[0; 4]);
+
+        let mut message = SIGNATURE.to_vec();
+        message.extend_from_slice(&2u32.to_le_bytes());
+        message.extend_from_slice(&[0; 8]); // target name
+        message.extend_from_slice(&NEGOTIATE_FLAGS.to_le_bytes());
+        message.extend_from_slice(&[7; 8]); // server challenge
+        message.extend_from_slice(&[0; 8]); // reserved
+        message.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+        message.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+        message.extend_from_slice(&48u32.to_le_bytes());
+        message.extend_from_slice(&target_info);
+
+        let challenge = parse_challenge(&message).unwrap();
+        assert_eq!(challenge.server_challenge, [7; 8]);
+        assert_eq!(find_timestamp(&challenge.target_info), Some(42));
+
+        let response = authenticate_message(&challenge, "DOMAIN\\user", "pass");
+        assert_eq!(&response[..12], b"NTLMSSP\0\x03\0\0\0");
+        // With a timestamp from the server the LMv2 response is zeroed
+        assert_eq!(read_u16(&response, 12), Some(24));
+        assert_eq!(read_u32(&response, 16), Some(64));
+        assert_eq!(&response[64..88], &[0; 24]);
+
+        let challenge = Challenge {
+            target_info: vec![0; 4],
+            ..challenge
+        };
+        let response = authenticate_message(&challenge, "DOMAIN\\user", "pass");
+        assert_ne!(&response[64..88], &[0; 24]);
+    }
+
+    #[test]
+    fn invalid_challenge() {
+        assert!(parse_challenge(b"garbage").is_err());
+        assert!(parse_challenge(&negotiate_message()).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! OAuth 2.0 client credentials grant, with tokens cached between runs.
+//!
+//! See https://datatracker.ietf.org/doc/html/rfc6749#section-4.4
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::utils::config_dir;
+
+/// Don't use tokens that are about to expire mid-request.
+const EXPIRY_MARGIN_SECS: u64 = 30;
+
+pub struct ClientCredentials<'a> {
+    pub token_url: &'a Url,
+    pub client_id: &'a str,
+    pub client_secret: &'a str,
+    pub scope: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedToken {
+    access_token: String,
+    expires_at: u64,
+}
+
+/// Return a cached access token if it's still valid, or request a new one.
+pub fn access_token(client: &Client, credentials: &ClientCredentials) -> Result<String> {
+    let key = credentials.cache_key();
+    let mut cache = read_cache();
+    if let Some(token) = cache.get(&key) {
+        if token.expires_at > now() + EXPIRY_MARGIN_SECS {
+            return Ok(token.access_token.clone());
+        }
+    }
+
+    let token = request_token(client, credentials)?;
+    if let Some(expires_in) = token.expires_in {
+        cache.insert(
+            key,
+            CachedToken {
+                access_token: token.access_token.clone(),
+                expires_at: now() + expires_in,
+            },
+        );
+        // Failing to cache shouldn't fail the request
+        let _ = write_cache(&cache);
+    }
+    Ok(token.access_token)
+}
+
+fn request_token(client: &Client, credentials: &ClientCredentials) -> Result<TokenResponse> {
+    let mut form = vec![("grant_type", "client_credentials")];
+    if let Some(scope) = credentials.scope {
+        form.push(("scope", scope));
+    }
+    let response = client
+        .post(credentials.token_url.clone())
+        .basic_auth(credentials.client_id, Some(credentials.client_secret))
+        .form(&form)
+        .send()
+        .with_context(|| {
+            format!(
+                "Failed to request an access token from {}",
+                credentials.token_url
+            )
+        })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!(
+            "Token endpoint returned {}: {}",
+            status,
+            response.text().unwrap_or_default()
+        ));
+    }
+    response
+        .json()
+        .context("Token endpoint returned an invalid response")
+}
+
+impl ClientCredentials<'_> {
+    /// Tokens are cached per secret too, so a rotated secret gets a new token.
+    /// Only a hash of it is stored.
+    fn cache_key(&self) -> String {
+        let secret_hash: String = Sha256::digest(self.client_secret.as_bytes())
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!(
+            "{} {} {} {}",
+            self.token_url,
+            self.client_id,
+            secret_hash,
+            self.scope.unwrap_or("")
+        )
+    }
+}
+
+fn cache_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("oauth2_tokens.json"))
+}
+
+fn read_cache() -> HashMap<String, CachedToken> {
+    cache_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_cache(cache: &HashMap<String, CachedToken>) -> Result<()> {
+    let path = cache_path().context("couldn't get config directory")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    serde_json::to_writer(&mut file, cache)?;
+    file.write_all(b"\n")?;
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_key_depends_on_secret() {
+        let token_url = Url::parse("https://auth.example.com/token").unwrap();
+        let credentials = |client_secret| ClientCredentials {
+            token_url: &token_url,
+            client_id: "client",
+            client_secret,
+            scope: None,
+        };
+        let key = credentials("secret").cache_key();
+        assert_eq!(key, credentials("secret").cache_key());
+        assert_ne!(key, credentials("other").cache_key());
+        assert!(!key.contains("secret"));
+    }
+}
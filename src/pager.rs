@@ -0,0 +1,110 @@
+//! Sending output through a pager, the way git does.
+
+use std::env;
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// A running pager process.
+///
+/// It's waited for when dropped, so it has to outlive the [`PagerInput`]
+/// (usually inside a [`Buffer`](crate::buffer::Buffer)) or we'll hang.
+pub struct Pager {
+    child: Child,
+}
+
+impl Pager {
+    /// Start `$PAGER`, or `less` if that isn't set.
+    ///
+    /// Returns `None` if paging is disabled or the pager can't be started,
+    /// in which case output should go to stdout as usual.
+    pub fn start() -> Option<(Pager, PagerInput)> {
+        let pager = env::var("PAGER").ok();
+        let mut words = pager_command(pager.as_deref())?.into_iter();
+        let mut command = Command::new(words.next()?);
+        command.args(words).stdin(Stdio::piped());
+        if env::var_os("LESS").is_none() {
+            // Exit right away if the output fits on one screen, pass colors
+            // through, and don't clear the screen afterwards
+            command.env("LESS", "FRX");
+        }
+        let mut child = command.spawn().ok()?;
+        let stdin = child.stdin.take()?;
+        Some((Pager { child }, PagerInput { stdin: Some(stdin) }))
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = self.child.wait();
+    }
+}
+
+/// The pager's stdin.
+///
+/// Once the user quits the pager nobody is reading anymore, so further
+/// output is silently thrown away instead of failing with a broken pipe.
+pub struct PagerInput {
+    stdin: Option<ChildStdin>,
+}
+
+impl PagerInput {
+    fn handle<T>(&mut self, result: io::Result<T>, closed: T) -> io::Result<T> {
+        match result {
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                self.stdin = None;
+                Ok(closed)
+            }
+            result => result,
+        }
+    }
+}
+
+impl Write for PagerInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.stdin {
+            Some(stdin) => {
+                let result = stdin.write(buf);
+                self.handle(result, buf.len())
+            }
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stdin {
+            Some(stdin) => {
+                let result = stdin.flush();
+                self.handle(result, ())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Split the pager command into words. An empty `$PAGER` or `cat` means
+/// that paging is disabled.
+fn pager_command(pager: Option<&str>) -> Option<Vec<String>> {
+    let words: Vec<String> = pager
+        .unwrap_or("less")
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    match words.first().map(String::as_str) {
+        None | Some("cat") => None,
+        Some(_) => Some(words),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pager_command() {
+        assert_eq!(pager_command(None).unwrap(), ["less"]);
+        assert_eq!(pager_command(Some("less -R")).unwrap(), ["less", "-R"]);
+        assert_eq!(pager_command(Some("  most ")).unwrap(), ["most"]);
+        assert_eq!(pager_command(Some("")), None);
+        assert_eq!(pager_command(Some("cat")), None);
+    }
+}
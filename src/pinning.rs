@@ -0,0 +1,109 @@
+//! Public key pinning, for --pinnedpubkey.
+//!
+//! The check itself happens during the handshake, in [`crate::cert_verifier`].
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::x509::parse_certificate;
+
+/// SHA-256 hashes of acceptable public keys, written like curl does:
+/// `sha256//BASE64`, separated by semicolons.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinnedKeys(Vec<[u8; 32]>);
+
+impl FromStr for PinnedKeys {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<PinnedKeys> {
+        let hashes = s
+            .split(';')
+            .map(|pin| {
+                pin.trim()
+                    .strip_prefix("sha256//")
+                    .and_then(|hash| BASE64.decode(hash).ok())
+                    .and_then(|hash| hash.try_into().ok())
+                    .ok_or_else(|| anyhow!("Invalid pin {:?}, expected sha256//BASE64", pin))
+            })
+            .collect::<Result<_>>()?;
+        Ok(PinnedKeys(hashes))
+    }
+}
+
+impl fmt::Display for PinnedKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, hash) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ";")?;
+            }
+            write!(f, "sha256//{}", BASE64.encode(hash))?;
+        }
+        Ok(())
+    }
+}
+
+impl PinnedKeys {
+    /// Check the public key of a DER-encoded certificate.
+    #[cfg_attr(not(feature = "rustls"), allow(dead_code))]
+    pub fn check(&self, cert: &[u8]) -> Result<()> {
+        let tbs = parse_certificate(cert)
+            .ok_or_else(|| anyhow!("Failed to parse the server certificate"))?;
+        let hash: [u8; 32] = Sha256::digest(tbs.subject_public_key_info.raw).into();
+        if self.0.contains(&hash) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "The server's public key (sha256//{}) does not match --pinnedpubkey",
+                BASE64.encode(hash)
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pins() {
+        let pins = "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=; sha256////////////////////////////////////////////8="
+            .parse::<PinnedKeys>()
+            .unwrap();
+        assert_eq!(pins, PinnedKeys(vec![[0; 32], [0xff; 32]]));
+        assert_eq!(
+            pins.to_string(),
+            "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=;sha256////////////////////////////////////////////8="
+        );
+        assert!("sha1//AAAA".parse::<PinnedKeys>().is_err());
+        assert!("sha256//AAAA".parse::<PinnedKeys>().is_err());
+        assert!("sha256//!!!".parse::<PinnedKeys>().is_err());
+    }
+
+    #[test]
+    fn check_pins() {
+        let pem = pem::parse(include_str!(
+            "../tests/fixtures/certs/wildcard-self-signed.pem"
+        ))
+        .unwrap();
+        let cert = pem.contents();
+        let err = PinnedKeys(vec![[0; 32]])
+            .check(cert)
+            .unwrap_err()
+            .to_string();
+        // The error reports the actual hash, which should then match
+        let actual = &err[err.find("sha256//").unwrap()..err.find(')').unwrap()];
+        let pins: PinnedKeys = format!(
+            "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=;{}",
+            actual
+        )
+        .parse()
+        .unwrap();
+        pins.check(cert).unwrap();
+        assert!(pins.check(&cert[..50]).is_err());
+    }
+}
@@ -1,7 +1,10 @@
 use std::borrow::Cow;
+use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::Arc;
 use std::time::Instant;
 
+use anyhow::Context;
 use encoding_rs::Encoding;
 use encoding_rs_io::DecodeReaderBytesBuilder;
 use mime::Mime;
@@ -9,19 +12,31 @@ use reqwest::blocking::{Body, Request, Response};
 use reqwest::cookie::CookieStore;
 use reqwest::header::{
     HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HOST,
+    TRANSFER_ENCODING,
 };
-use reqwest::Version;
+use reqwest::{tls::TlsInfo, StatusCode, Version};
+use serde_json::Value;
 use url::Url;
 
 use crate::{
     buffer::Buffer,
+    cbor::{self, ByteStrings},
     cli::FormatOptions,
     cli::{Pretty, Theme},
     decoder::{decompress, get_compression_type},
-    formatting::serde_json_format,
+    dns,
+    formatting::{format_xml, serde_json_format, sort_json_keys, write_hexdump, XmlFormatter},
     formatting::{get_json_formatter, Highlighter},
+    graphql,
+    image_preview::ImageProtocol,
+    json_filter::JsonFilter,
     middleware::ResponseExt,
-    utils::{copy_largebuf, test_mode, BUFFER_SIZE},
+    msgpack,
+    protobuf::Decoder,
+    rate_limit::RateLimit,
+    table::{parse_csv, parse_tsv, write_table},
+    utils::{copy_largebuf, test_mode, title_case_header, SizeLimit, BUFFER_SIZE},
+    x509::summarize_certificate,
 };
 
 const BINARY_SUPPRESSOR: &str = concat!(
@@ -31,6 +46,9 @@ const BINARY_SUPPRESSOR: &str = concat!(
     "\n"
 );
 
+/// How much of a binary body to show with `--format-options=binary.hexdump:true`.
+const HEXDUMP_LIMIT: usize = 64 * 1024;
+
 /// A wrapper around a reader that reads line by line, (optionally) returning
 /// an error if the line appears to be binary.
 ///
@@ -109,14 +127,53 @@ impl<'a, T: Read> BinaryGuard<'a, T> {
     }
 }
 
+/// A reader that counts the bytes that are read from it, and optionally writes
+/// a copy of them elsewhere.
+struct TeeReader<'a, T: Read> {
+    inner: T,
+    copy: Option<&'a mut dyn Write>,
+    count: u64,
+}
+
+impl<'a, T: Read> Read for TeeReader<'a, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(copy) = &mut self.copy {
+            copy.write_all(&buf[..n])?;
+        }
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
 pub struct Printer {
     format_json: bool,
     json_indent_level: usize,
+    format_xml: bool,
+    xml_indent_level: usize,
     sort_headers: bool,
+    sort_json_keys: bool,
+    hexdump: bool,
+    image_protocol: Option<ImageProtocol>,
+    csv_table: bool,
+    decode_msgpack: bool,
+    decode_cbor: bool,
+    cbor_bytes: ByteStrings,
     color: bool,
     theme: Theme,
     stream: Option<bool>,
     buffer: Buffer,
+    captured_body: Option<Vec<u8>>,
+    tee: Option<File>,
+    json_filter: Option<JsonFilter>,
+    max_body_size: Option<u64>,
+    rate_limit: Option<u64>,
+    chunked: bool,
+    negotiated_version: bool,
+    graphql: bool,
+    protobuf: Option<Decoder>,
+    /// Shows how --dns-server resolved each host.
+    dns_resolver: Option<Arc<dns::Resolver>>,
 }
 
 impl Printer {
@@ -130,14 +187,100 @@ impl Printer {
         Printer {
             format_json: format_options.json_format.unwrap_or(pretty.format()),
             json_indent_level: format_options.json_indent.unwrap_or(4),
+            format_xml: format_options.xml_format.unwrap_or(pretty.format()),
+            xml_indent_level: format_options.xml_indent.unwrap_or(2),
             sort_headers: format_options.headers_sort.unwrap_or(pretty.format()),
+            sort_json_keys: format_options.json_sort_keys.unwrap_or(false),
+            hexdump: format_options.binary_hexdump.unwrap_or(false),
+            image_protocol: if format_options.image_preview.unwrap_or(false) {
+                ImageProtocol::detect()
+            } else {
+                None
+            },
+            csv_table: format_options.csv_table.unwrap_or(false),
+            decode_msgpack: format_options.msgpack_decode.unwrap_or(true),
+            decode_cbor: format_options.cbor_decode.unwrap_or(true),
+            cbor_bytes: format_options.cbor_bytes.unwrap_or_default(),
             color: pretty.color(),
             stream: stream.into(),
             theme,
             buffer,
+            captured_body: None,
+            tee: None,
+            json_filter: None,
+            max_body_size: None,
+            rate_limit: None,
+            chunked: false,
+            negotiated_version: false,
+            graphql: false,
+            protobuf: None,
+            dns_resolver: None,
         }
     }
 
+    /// Fail if a response body turns out to be larger than this.
+    pub fn with_max_body_size(mut self, max_body_size: Option<u64>) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Limit how fast response bodies are read.
+    pub fn with_rate_limit(mut self, rate_limit: Option<u64>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Show request bodies as sent with chunked encoding rather than a length.
+    pub fn with_chunked(mut self, chunked: bool) -> Self {
+        self.chunked = chunked;
+        self
+    }
+
+    /// Leave the version out of HTTPS request lines, since it's only settled
+    /// when the connection is made.
+    pub fn with_negotiated_version(mut self, negotiated_version: bool) -> Self {
+        self.negotiated_version = negotiated_version;
+        self
+    }
+
+    /// Show how --dns-server resolved a host in the metadata of its responses.
+    pub fn with_dns_resolver(mut self, dns_resolver: Option<Arc<dns::Resolver>>) -> Self {
+        self.dns_resolver = dns_resolver;
+        self
+    }
+
+    /// Show request bodies as a GraphQL query and its variables.
+    pub fn with_graphql(mut self, graphql: bool) -> Self {
+        self.graphql = graphql;
+        self
+    }
+
+    /// Decode protobuf response bodies to JSON.
+    pub fn with_protobuf(mut self, protobuf: Option<Decoder>) -> Self {
+        self.protobuf = protobuf;
+        self
+    }
+
+    /// Apply a filter to JSON response bodies before printing them.
+    pub fn with_json_filter(mut self, json_filter: Option<JsonFilter>) -> Self {
+        self.json_filter = json_filter;
+        self
+    }
+
+    /// Keep a copy of the next response body that gets printed.
+    pub fn capture_response_body(&mut self) {
+        self.captured_body = Some(Vec::new());
+    }
+
+    pub fn take_captured_body(&mut self) -> Option<Vec<u8>> {
+        self.captured_body.take()
+    }
+
+    /// Also save the next response body that gets printed to a file.
+    pub fn tee_response_body(&mut self, file: File) {
+        self.tee = Some(file);
+    }
+
     fn get_highlighter(&mut self, syntax: &'static str) -> Highlighter<'_> {
         Highlighter::new(syntax, self.theme, &mut self.buffer)
     }
@@ -167,6 +310,8 @@ impl Printer {
             return self.print_syntax_text(text, "json");
         }
 
+        let text = self.maybe_sort_json_keys(text);
+        let text = text.as_ref();
         if self.color {
             let mut buf = Vec::new();
             serde_json_format(self.json_indent_level, text, &mut buf)?;
@@ -183,10 +328,44 @@ impl Printer {
         }
     }
 
+    /// Apply json.sort_keys. This is only done for complete documents, the
+    /// streaming formatter can't reorder anything.
+    fn maybe_sort_json_keys<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.sort_json_keys {
+            sort_json_keys(text).map_or(Cow::Borrowed(text), Cow::Owned)
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Print a single (known-valid) JSON document followed by one newline.
+    ///
+    /// Unlike [`Printer::print_json_text`] this doesn't leave blank lines
+    /// behind, which suits documents that arrive one after another.
+    fn print_json_record(&mut self, text: &str) -> io::Result<()> {
+        if self.format_json {
+            let text = self.maybe_sort_json_keys(text);
+            let mut buf = Vec::new();
+            serde_json_format(self.json_indent_level, &text, &mut buf)?;
+            buf.push(b'\n');
+            self.print_syntax_text(&String::from_utf8_lossy(&buf), "json")
+        } else {
+            self.print_syntax_text(text, "json")?;
+            self.buffer.print("\n")
+        }
+    }
+
     fn print_body_text(&mut self, content_type: ContentType, body: &str) -> io::Result<()> {
         match content_type {
             ContentType::Json => self.print_json_text(body, true),
-            ContentType::Xml => self.print_syntax_text(body, "xml"),
+            ContentType::Xml => self.print_xml_text(body),
+            ContentType::Yaml => self.print_syntax_text(body, "yaml"),
+            ContentType::Csv if self.csv_table => {
+                write_table(&mut self.buffer, &parse_csv(body, ','), self.color)
+            }
+            ContentType::Tsv if self.csv_table => {
+                write_table(&mut self.buffer, &parse_tsv(body), self.color)
+            }
             ContentType::Html => self.print_syntax_text(body, "html"),
             ContentType::Css => self.print_syntax_text(body, "css"),
             // In HTTPie part of this behavior is gated behind the --json flag
@@ -196,6 +375,8 @@ impl Printer {
                 self.print_json_text(body, false)
             }
             ContentType::JavaScript => self.print_syntax_text(body, "js"),
+            ContentType::EventStream => self.print_event_stream(&mut body.as_bytes()),
+            ContentType::NdJson => self.print_ndjson_stream(&mut body.as_bytes()),
             _ => self.buffer.print(body),
         }
     }
@@ -283,6 +464,126 @@ impl Printer {
         }
     }
 
+    fn print_xml_text(&mut self, text: &str) -> io::Result<()> {
+        let formatted = if self.format_xml {
+            format_xml(text, self.xml_indent_level)
+        } else {
+            None
+        };
+        self.print_syntax_text(formatted.as_deref().unwrap_or(text), "xml")
+    }
+
+    fn print_xml_stream(&mut self, stream: &mut impl Read) -> io::Result<()> {
+        if !self.format_xml {
+            return self.print_syntax_stream(stream, "xml");
+        }
+        let mut guard = BinaryGuard::new(stream, self.buffer.is_terminal());
+        let mut formatter = XmlFormatter::new(self.xml_indent_level);
+        let mut buf = String::new();
+        if self.color {
+            // The highlighter wants whole lines, so hold back the last one
+            let mut highlighter = self.get_highlighter("xml");
+            while let Some(lines) = guard.read_lines()? {
+                formatter.format(&String::from_utf8_lossy(lines), &mut buf);
+                if let Some(end) = buf.rfind('\n') {
+                    highlighter.highlight(&buf[..=end])?;
+                    highlighter.flush()?;
+                    buf.drain(..=end);
+                }
+            }
+            formatter.finish(&mut buf);
+            highlighter.highlight(&buf)?;
+        } else {
+            while let Some(lines) = guard.read_lines()? {
+                formatter.format(&String::from_utf8_lossy(lines), &mut buf);
+                self.buffer.print(&buf)?;
+                self.buffer.flush()?;
+                buf.clear();
+            }
+            formatter.finish(&mut buf);
+            self.buffer.print(&buf)?;
+        }
+        Ok(())
+    }
+
+    /// Print a `text/event-stream` body one event at a time, formatting
+    /// the data of each event if it's JSON.
+    ///
+    /// See https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation
+    fn print_event_stream(&mut self, stream: &mut impl Read) -> io::Result<()> {
+        if !self.format_json && !self.color {
+            return self.print_stream(stream);
+        }
+        let mut guard = BinaryGuard::new(stream, self.buffer.is_terminal());
+        let mut event = String::new();
+        while let Some(lines) = guard.read_lines()? {
+            for line in String::from_utf8_lossy(lines).split_inclusive('\n') {
+                if line.trim_end_matches(['\r', '\n']).is_empty() {
+                    self.print_event(&event)?;
+                    event.clear();
+                } else {
+                    event.push_str(line);
+                }
+            }
+            self.buffer.flush()?;
+        }
+        if !event.is_empty() {
+            self.print_event(&event)?;
+            self.buffer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Print newline-delimited JSON, formatting each line as a separate document.
+    ///
+    /// This covers NDJSON, JSON Lines and JSON text sequences (RFC 7464).
+    fn print_ndjson_stream(&mut self, stream: &mut impl Read) -> io::Result<()> {
+        if !self.format_json && !self.color {
+            return self.print_stream(stream);
+        }
+        let mut guard = BinaryGuard::new(stream, self.buffer.is_terminal());
+        while let Some(lines) = guard.read_lines()? {
+            for line in String::from_utf8_lossy(lines).split_inclusive('\n') {
+                // JSON text sequences start every record with a record separator
+                let record = line
+                    .trim_start_matches('\x1e')
+                    .trim_end_matches(['\r', '\n']);
+                if valid_json(record) {
+                    self.print_json_record(record)?;
+                } else {
+                    self.buffer.print(line)?;
+                }
+            }
+            self.buffer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn print_event(&mut self, event: &str) -> io::Result<()> {
+        let mut data = Vec::new();
+        for line in event.lines() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if let Some(value) = line.strip_prefix("data:") {
+                data.push(value.strip_prefix(' ').unwrap_or(value));
+            } else {
+                self.buffer.print(line)?;
+                self.buffer.print("\n")?;
+            }
+        }
+        let data = data.join("\n");
+        if valid_json(&data) {
+            self.buffer.print("data: ")?;
+            self.print_json_record(&data)?;
+        } else if !data.is_empty() || event.contains("data:") {
+            for line in data.split('\n') {
+                self.buffer.print("data: ")?;
+                self.buffer.print(line)?;
+                self.buffer.print("\n")?;
+            }
+        }
+        self.buffer.print("\n")
+    }
+
     fn print_body_stream(
         &mut self,
         content_type: ContentType,
@@ -290,11 +591,14 @@ impl Printer {
     ) -> io::Result<()> {
         match content_type {
             ContentType::Json => self.print_json_stream(body),
-            ContentType::Xml => self.print_syntax_stream(body, "xml"),
+            ContentType::Xml => self.print_xml_stream(body),
+            ContentType::Yaml => self.print_syntax_stream(body, "yaml"),
             ContentType::Html => self.print_syntax_stream(body, "html"),
             ContentType::Css => self.print_syntax_stream(body, "css"),
             // print_body_text() has fancy JSON detection, but we can't do that here
             ContentType::JavaScript => self.print_syntax_stream(body, "js"),
+            ContentType::EventStream => self.print_event_stream(body),
+            ContentType::NdJson => self.print_ndjson_stream(body),
             _ => self.print_stream(body),
         }
     }
@@ -321,17 +625,7 @@ impl Printer {
         let mut header_string = String::new();
         for (key, value) in headers {
             if as_titlecase {
-                // Ought to be equivalent to how hyper does it
-                // https://github.com/hyperium/hyper/blob/f46b175bf71b202fbb907c4970b5743881b891e1/src/proto/h1/role.rs#L1332
-                // Header names are ASCII so it's ok to operate on char instead of u8
-                let mut prev = '-';
-                for mut c in key.as_str().chars() {
-                    if prev == '-' {
-                        c.make_ascii_uppercase();
-                    }
-                    header_string.push(c);
-                    prev = c;
-                }
+                header_string.push_str(&title_case_header(key.as_str()));
             } else {
                 header_string.push_str(key.as_str());
             }
@@ -376,14 +670,24 @@ impl Printer {
         // reqwest and hyper add certain headers, but only in the process of
         // sending the request, which we haven't done yet
         if let Some(body) = request.body().and_then(Body::as_bytes) {
-            // Added at https://github.com/seanmonstar/reqwest/blob/e56bd160ba/src/blocking/request.rs#L132
-            headers
-                .entry(CONTENT_LENGTH)
-                .or_insert_with(|| body.len().into());
-        }
-        if let Some(host) = request.url().host_str() {
-            // This is incorrect in case of HTTP/2, but we're already assuming
-            // HTTP/1.1 anyway
+            if self.chunked {
+                // Added by hyper for bodies without a length
+                headers
+                    .entry(TRANSFER_ENCODING)
+                    .or_insert_with(|| HeaderValue::from_static("chunked"));
+            } else {
+                // Added at https://github.com/seanmonstar/reqwest/blob/e56bd160ba/src/blocking/request.rs#L132
+                headers
+                    .entry(CONTENT_LENGTH)
+                    .or_insert_with(|| body.len().into());
+            }
+        }
+        // HTTP/2 and HTTP/3 send the :authority pseudo-header instead of Host
+        let sends_host = matches!(
+            version,
+            Version::HTTP_09 | Version::HTTP_10 | Version::HTTP_11
+        );
+        if let (true, Some(host)) = (sends_host, request.url().host_str()) {
             headers.entry(HOST).or_insert_with(|| {
                 // Added at https://github.com/hyperium/hyper/blob/dfa1bb291d/src/client/client.rs#L237
                 if test_mode() {
@@ -397,7 +701,11 @@ impl Printer {
             });
         }
 
-        let request_line = format!("{} {}{} {:?}\n", method, url.path(), query_string, version);
+        let request_line = if self.negotiated_version && url.scheme() == "https" {
+            format!("{} {}{}\n", method, url.path(), query_string)
+        } else {
+            format!("{} {}{} {:?}\n", method, url.path(), query_string, version)
+        };
         let headers = self.headers_to_string(&headers, version);
 
         self.print_headers(&(request_line + &headers))?;
@@ -407,10 +715,15 @@ impl Printer {
     }
 
     pub fn print_response_headers(&mut self, response: &Response) -> io::Result<()> {
-        let version = response.version();
-        let status = response.status();
-        let headers = response.headers();
+        self.print_status_and_headers(response.version(), response.status(), response.headers())
+    }
 
+    pub fn print_status_and_headers(
+        &mut self,
+        version: Version,
+        status: StatusCode,
+        headers: &HeaderMap,
+    ) -> io::Result<()> {
         let status_line = format!("{:?} {}\n", version, status);
         let headers = self.headers_to_string(headers, version);
 
@@ -422,10 +735,26 @@ impl Printer {
 
     pub fn print_request_body(&mut self, request: &mut Request) -> anyhow::Result<()> {
         let content_type = get_content_type(request.headers());
+        let boundary = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()?.parse::<Mime>().ok())
+            .filter(|mime| mime.type_() == mime::MULTIPART)
+            .and_then(|mime| Some(mime.get_param(mime::BOUNDARY)?.to_string()));
         if let Some(body) = request.body_mut() {
             let body = body.buffer()?;
-            if body.contains(&b'\0') {
-                self.buffer.print(BINARY_SUPPRESSOR)?;
+            let graphql = (self.graphql && self.format_json)
+                .then(|| graphql::split_body(&String::from_utf8_lossy(body)))
+                .flatten();
+            if let Some((query, variables)) = graphql {
+                self.print_graphql(&query, variables)?;
+            } else if let (true, Some(boundary)) = (body.contains(&b'\0'), boundary) {
+                // Show everything but the binary parts
+                let preview = multipart_preview(body, &boundary);
+                self.print_body_text(content_type, &String::from_utf8_lossy(&preview))?;
+                self.buffer.print("\n")?;
+            } else if body.contains(&b'\0') {
+                self.print_binary_body(body, false)?;
             } else {
                 self.print_body_text(content_type, &String::from_utf8_lossy(body))?;
                 self.buffer.print("\n")?;
@@ -437,6 +766,19 @@ impl Printer {
         Ok(())
     }
 
+    /// Print the query on its own so it can be highlighted, followed by the
+    /// variables as JSON.
+    fn print_graphql(&mut self, query: &str, variables: Option<Value>) -> io::Result<()> {
+        self.print_syntax_text(query.trim_end(), "graphql")?;
+        self.buffer.print("\n")?;
+        if let Some(variables) = variables {
+            self.buffer.print("\n")?;
+            self.print_json_text(&variables.to_string(), false)?;
+            self.buffer.print("\n")?;
+        }
+        Ok(())
+    }
+
     pub fn print_response_body(
         &mut self,
         response: &mut Response,
@@ -448,13 +790,67 @@ impl Printer {
         let content_type =
             mime.map_or_else(|| get_content_type(response.headers()), ContentType::from);
         let encoding = encoding.or_else(|| get_charset(response));
+        let is_image = mime
+            .or_else(|| {
+                response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+            })
+            .is_some_and(|mime| mime.trim_start().starts_with("image/"));
         let compression_type = get_compression_type(response.headers());
-        let mut body = decompress(response, compression_type);
+        let mut captured_body = self.captured_body.take();
+        let mut tee = self.tee.take();
+        // Printing can stop early, e.g. for binary data, but the file and the
+        // HAR entry should have the whole body
+        let read_to_end = tee.is_some() || captured_body.is_some();
+        // Counts the bytes as they were received, before decompression
+        let mut raw_body = TeeReader {
+            inner: RateLimit::new(&mut *response, self.rate_limit),
+            copy: None,
+            count: 0,
+        };
+        let mut body = TeeReader {
+            inner: TeeReader {
+                inner: SizeLimit::new(
+                    decompress(&mut raw_body, compression_type),
+                    self.max_body_size,
+                ),
+                copy: tee.as_mut().map(|file| file as &mut dyn Write),
+                count: 0,
+            },
+            copy: captured_body.as_mut().map(|body| body as &mut dyn Write),
+            count: 0,
+        };
 
         // Automatically activate stream mode when it hasn't been set by the user and the content type is stream
         let stream = self.stream.unwrap_or(content_type.is_stream());
 
-        if !self.buffer.is_terminal() {
+        if self.decodes_to_json(&content_type) {
+            let mut buf = Vec::new();
+            body.read_to_end(&mut buf)?;
+            let value = self.decode_to_json(&content_type, &buf)?;
+            let values = match &self.json_filter {
+                Some(json_filter) => json_filter.apply(value)?,
+                None => vec![value],
+            };
+            for value in values {
+                self.print_json_record(&value.to_string())?;
+            }
+        } else if let Some(json_filter) = self
+            .json_filter
+            .clone()
+            .filter(|_| matches!(content_type, ContentType::Json))
+        {
+            let mut buf = Vec::new();
+            body.read_to_end(&mut buf)?;
+            let text = decode_blob_unconditional(&buf, encoding, &url);
+            let value =
+                serde_json::from_str(&text).context("--filter requires a JSON response body")?;
+            for value in json_filter.apply(value)? {
+                self.print_json_record(&value.to_string())?;
+            }
+        } else if !self.buffer.is_terminal() {
             if (self.color || self.format_json) && content_type.is_text() {
                 // The user explicitly asked for formatting even though this is
                 // going into a file, and the response is at least supposed to be
@@ -503,7 +899,7 @@ impl Printer {
             body.read_to_end(&mut buf)?;
             match decode_blob(&buf, encoding, &url) {
                 None => {
-                    self.buffer.print(BINARY_SUPPRESSOR)?;
+                    self.print_binary_body(&buf, is_image)?;
                 }
                 Some(text) => {
                     self.print_body_text(content_type, &text)?;
@@ -511,12 +907,95 @@ impl Printer {
                 }
             };
         }
+        if read_to_end {
+            io::copy(&mut body, &mut io::sink())?;
+        }
         self.buffer.flush()?;
+        let body_size = body.count;
         drop(body); // silence the borrow checker
-        response.meta_mut().content_download_duration = Some(starting_time.elapsed());
+        let raw_body_size = raw_body.count;
+        self.captured_body = captured_body;
+        let meta = response.meta_mut();
+        meta.content_download_duration = Some(starting_time.elapsed());
+        meta.body_size = Some(body_size);
+        meta.raw_body_size = Some(raw_body_size);
+        Ok(())
+    }
+
+    /// Whether a response body is in a binary format that we show as JSON.
+    fn decodes_to_json(&self, content_type: &ContentType) -> bool {
+        let enabled = match content_type {
+            ContentType::Protobuf => return self.protobuf.is_some(),
+            ContentType::MsgPack => self.decode_msgpack,
+            ContentType::Cbor => self.decode_cbor,
+            _ => false,
+        };
+        // Keep the original body when it's saved to a file, unless we'd
+        // format or filter it anyway
+        enabled
+            && (self.buffer.is_terminal()
+                || self.format_json
+                || self.color
+                || self.json_filter.is_some())
+    }
+
+    fn decode_to_json(&self, content_type: &ContentType, body: &[u8]) -> anyhow::Result<Value> {
+        match (content_type, &self.protobuf) {
+            (ContentType::Protobuf, Some(decoder)) => decoder.decode(body),
+            (ContentType::MsgPack, _) => msgpack::decode(body),
+            (ContentType::Cbor, _) => cbor::decode(body, self.cbor_bytes),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Print a binary body that we can't show as text on the terminal.
+    fn print_binary_body(&mut self, body: &[u8], is_image: bool) -> io::Result<()> {
+        if let Some(protocol) = self.image_protocol.filter(|_| is_image) {
+            if protocol.write_image(&mut self.buffer, body)? {
+                self.buffer.print("\n\n")?;
+                return Ok(());
+            }
+        }
+        if self.hexdump {
+            self.print_hexdump(body)
+        } else {
+            self.buffer.print(BINARY_SUPPRESSOR)
+        }
+    }
+
+    /// Print binary data as a hexdump, cut off after [`HEXDUMP_LIMIT`] bytes.
+    fn print_hexdump(&mut self, data: &[u8]) -> io::Result<()> {
+        let shown = &data[..data.len().min(HEXDUMP_LIMIT)];
+        write_hexdump(&mut self.buffer, shown, 0, self.color)?;
+        if data.len() > shown.len() {
+            self.buffer
+                .print(format!("... {} more bytes\n", data.len() - shown.len()))?;
+        }
+        self.buffer.print("\n")?;
         Ok(())
     }
 
+    /// Print a single WebSocket text message, formatting it if it's JSON.
+    pub fn print_websocket_text(&mut self, text: &str) -> io::Result<()> {
+        if valid_json(text) {
+            self.print_json_record(text)?;
+        } else {
+            self.buffer.print(text)?;
+            self.buffer.print("\n")?;
+        }
+        self.buffer.flush()
+    }
+
+    /// Print a single WebSocket binary message.
+    pub fn print_websocket_binary(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.buffer.is_terminal() {
+            self.buffer.print(BINARY_SUPPRESSOR)?;
+        } else {
+            self.buffer.print(data)?;
+        }
+        self.buffer.flush()
+    }
+
     pub fn print_response_meta(&mut self, response: &Response) -> anyhow::Result<()> {
         let meta = response.meta();
         let mut total_elapsed_time = meta.request_duration.as_secs_f64();
@@ -526,10 +1005,68 @@ impl Printer {
         self.buffer
             .print(format!("Elapsed time: {:.5}s\n", total_elapsed_time))?;
 
+        // Fall back to Content-Length if the body wasn't read. That's the size
+        // before decompression.
+        let compression_type = get_compression_type(response.headers());
+        let raw_body_size = meta.raw_body_size.or_else(|| {
+            response
+                .headers()
+                .get(CONTENT_LENGTH)?
+                .to_str()
+                .ok()?
+                .parse()
+                .ok()
+        });
+        let body_size = match compression_type {
+            Some(_) => meta.body_size,
+            None => meta.body_size.or(raw_body_size),
+        };
+        if let Some(body_size) = body_size {
+            self.buffer
+                .print(format!("Body size: {} bytes\n", body_size))?;
+        }
+        if let (Some(compression_type), Some(raw_body_size)) = (compression_type, raw_body_size) {
+            self.buffer.print(format!(
+                "Compressed size: {} bytes ({})\n",
+                raw_body_size, compression_type
+            ))?;
+        }
+
         if let Some(remote_addr) = response.remote_addr() {
             self.buffer
                 .print(format!("Remote address: {:?}\n", remote_addr))?;
         }
+        let resolution = self
+            .dns_resolver
+            .as_ref()
+            .zip(response.url().host_str())
+            .and_then(|(resolver, host)| resolver.describe(host));
+        if let Some(resolution) = resolution {
+            self.buffer
+                .print(format!("DNS resolution: {}\n", resolution))?;
+        }
+
+        let certificate = response
+            .extensions()
+            .get::<TlsInfo>()
+            .and_then(TlsInfo::peer_certificate)
+            .and_then(summarize_certificate);
+        if let Some(certificate) = certificate {
+            self.buffer
+                .print(format!("Certificate subject: {}\n", certificate.subject))?;
+            if !certificate.alt_names.is_empty() {
+                self.buffer.print(format!(
+                    "Certificate names: {}\n",
+                    certificate.alt_names.join(", ")
+                ))?;
+            }
+            self.buffer
+                .print(format!("Certificate issuer: {}\n", certificate.issuer))?;
+            self.buffer.print(format!(
+                "Certificate validity: {} to {}\n",
+                certificate.not_before, certificate.not_after
+            ))?;
+        }
 
         self.buffer.print("\n")?;
         Ok(())
@@ -540,39 +1077,61 @@ enum ContentType {
     Json,
     Html,
     Xml,
+    Yaml,
+    Csv,
+    Tsv,
     JavaScript,
     Css,
     Text,
     UrlencodedForm,
     Multipart,
     EventStream,
+    NdJson,
+    Protobuf,
+    MsgPack,
+    Cbor,
     Unknown,
 }
 
 impl ContentType {
     fn is_text(&self) -> bool {
         match self {
-            ContentType::Unknown | ContentType::UrlencodedForm | ContentType::Multipart => false,
+            ContentType::Unknown
+            | ContentType::UrlencodedForm
+            | ContentType::Multipart
+            | ContentType::Protobuf
+            | ContentType::MsgPack
+            | ContentType::Cbor => false,
             ContentType::Json
             | ContentType::Html
             | ContentType::Xml
+            | ContentType::Yaml
+            | ContentType::Csv
+            | ContentType::Tsv
             | ContentType::JavaScript
             | ContentType::Css
             | ContentType::Text
-            | ContentType::EventStream => true,
+            | ContentType::EventStream
+            | ContentType::NdJson => true,
         }
     }
     fn is_stream(&self) -> bool {
         match self {
-            ContentType::EventStream => true,
+            ContentType::EventStream | ContentType::NdJson => true,
             ContentType::Json
             | ContentType::Html
             | ContentType::Xml
+            | ContentType::Yaml
+            | ContentType::Csv
+            | ContentType::Tsv
             | ContentType::JavaScript
             | ContentType::Css
             | ContentType::Text
             | ContentType::UrlencodedForm
             | ContentType::Multipart
+            | ContentType::Protobuf
+            | ContentType::MsgPack
+            | ContentType::Cbor
             | ContentType::Unknown => false,
         }
     }
@@ -580,12 +1139,23 @@ impl ContentType {
 
 impl From<&str> for ContentType {
     fn from(content_type: &str) -> Self {
-        if content_type.contains("json") {
+        if ["ndjson", "jsonl", "json-seq"]
+            .iter()
+            .any(|subtype| content_type.contains(subtype))
+        {
+            ContentType::NdJson
+        } else if content_type.contains("json") {
             ContentType::Json
         } else if content_type.contains("html") {
             ContentType::Html
         } else if content_type.contains("xml") {
             ContentType::Xml
+        } else if content_type.contains("yaml") {
+            ContentType::Yaml
+        } else if content_type.contains("csv") {
+            ContentType::Csv
+        } else if content_type.contains("tab-separated-values") {
+            ContentType::Tsv
         } else if content_type.contains("multipart") {
             ContentType::Multipart
         } else if content_type.contains("x-www-form-urlencoded") {
@@ -596,6 +1166,12 @@ impl From<&str> for ContentType {
             ContentType::Css
         } else if content_type.contains("event-stream") {
             ContentType::EventStream
+        } else if content_type.contains("protobuf") {
+            ContentType::Protobuf
+        } else if content_type.contains("msgpack") {
+            ContentType::MsgPack
+        } else if content_type.contains("cbor") {
+            ContentType::Cbor
         } else if content_type.contains("text") {
             // We later check if this one's JSON
             // HTTPie checks for "json", "javascript" and "text" in one place:
@@ -615,6 +1191,39 @@ fn get_content_type(headers: &HeaderMap) -> ContentType {
         .map_or(ContentType::Unknown, ContentType::from)
 }
 
+/// Replace the contents of binary parts in a multipart body with a note.
+fn multipart_preview(body: &[u8], boundary: &str) -> Vec<u8> {
+    let delimiter = format!("--{}", boundary);
+    let mut preview = Vec::with_capacity(body.len().min(BUFFER_SIZE));
+    let mut start = 0;
+    let mut delimiters = memchr::memmem::find_iter(body, &delimiter).peekable();
+    while let Some(part_start) = delimiters.next() {
+        preview.extend_from_slice(&body[start..part_start]);
+        let part_end = delimiters.peek().copied().unwrap_or(body.len());
+        let part = &body[part_start..part_end];
+        let content_start = memchr::memmem::find(part, b"\r\n\r\n").map(|i| i + 4);
+        match content_start {
+            Some(content_start) => {
+                // The line break before the next delimiter isn't part of the content
+                let content = &part[content_start..];
+                let content = content.strip_suffix(b"\r\n").unwrap_or(content);
+                if content.contains(&b'\0') {
+                    preview.extend_from_slice(&part[..content_start]);
+                    preview.extend_from_slice(
+                        format!("<binary data not shown: {} bytes>\r\n", content.len()).as_bytes(),
+                    );
+                } else {
+                    preview.extend_from_slice(part);
+                }
+            }
+            None => preview.extend_from_slice(part),
+        }
+        start = part_end;
+    }
+    preview.extend_from_slice(&body[start..]);
+    preview
+}
+
 fn valid_json(text: &str) -> bool {
     serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok()
 }
@@ -844,15 +1453,13 @@ mod tests {
 
     #[test]
     fn test_header_casing() {
-        let p = Printer {
-            json_indent_level: 4,
-            format_json: false,
-            sort_headers: false,
-            color: false,
-            theme: Theme::Auto,
-            stream: false.into(),
-            buffer: Buffer::new(false, None, false).unwrap(),
-        };
+        let p = Printer::new(
+            Pretty::None,
+            Theme::Auto,
+            false,
+            Buffer::new(false, None, false).unwrap(),
+            FormatOptions::default(),
+        );
 
         let mut headers = HeaderMap::new();
         headers.insert("ab-cd", "0".parse().unwrap());
@@ -883,4 +1490,33 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn multipart_previews() {
+        let body = concat!(
+            "--xyz\r\n",
+            "Content-Disposition: form-data; name=\"x\"\r\n",
+            "\r\n",
+            "1\r\n",
+            "--xyz\r\n",
+            "Content-Disposition: form-data; name=\"y\"; filename=\"y.bin\"\r\n",
+            "\r\n",
+            "a\0b\r\n",
+            "--xyz--\r\n",
+        );
+        assert_eq!(
+            String::from_utf8(multipart_preview(body.as_bytes(), "xyz")).unwrap(),
+            concat!(
+                "--xyz\r\n",
+                "Content-Disposition: form-data; name=\"x\"\r\n",
+                "\r\n",
+                "1\r\n",
+                "--xyz\r\n",
+                "Content-Disposition: form-data; name=\"y\"; filename=\"y.bin\"\r\n",
+                "\r\n",
+                "<binary data not shown: 3 bytes>\r\n",
+                "--xyz--\r\n",
+            )
+        );
+    }
 }
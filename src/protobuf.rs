@@ -0,0 +1,643 @@
+//! Decoding protobuf messages to JSON, for --proto.
+//!
+//! Message types are looked up in a FileDescriptorSet, as written by
+//! `protoc --include_imports --descriptor_set_out=FILE` or `buf build -o FILE`.
+//! Decoded messages follow the proto3 JSON mapping: field names are in
+//! lowerCamelCase, 64-bit integers are strings, bytes are base64 and enum
+//! values are shown by name. Fields that aren't in the descriptors are left out.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde_json::{Map, Value};
+
+use crate::utils::{json_f32, json_float};
+
+/// Messages that are nested deeper than this are assumed to be malformed.
+const MAX_DEPTH: usize = 100;
+
+// Field types and labels from descriptor.proto
+const TYPE_DOUBLE: i32 = 1;
+const TYPE_FLOAT: i32 = 2;
+const TYPE_INT64: i32 = 3;
+const TYPE_UINT64: i32 = 4;
+const TYPE_INT32: i32 = 5;
+const TYPE_FIXED64: i32 = 6;
+const TYPE_FIXED32: i32 = 7;
+const TYPE_BOOL: i32 = 8;
+const TYPE_STRING: i32 = 9;
+const TYPE_GROUP: i32 = 10;
+const TYPE_MESSAGE: i32 = 11;
+const TYPE_BYTES: i32 = 12;
+const TYPE_UINT32: i32 = 13;
+const TYPE_ENUM: i32 = 14;
+const TYPE_SFIXED32: i32 = 15;
+const TYPE_SFIXED64: i32 = 16;
+const TYPE_SINT32: i32 = 17;
+const TYPE_SINT64: i32 = 18;
+const LABEL_REPEATED: i32 = 3;
+
+/// A field value as it appears on the wire, before we know what it means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> WireValue<'a> {
+    fn as_bytes(self) -> Result<&'a [u8]> {
+        match self {
+            WireValue::Bytes(bytes) => Ok(bytes),
+            _ => bail!("expected a length-delimited field"),
+        }
+    }
+
+    fn as_string(self) -> Result<String> {
+        Ok(String::from_utf8_lossy(self.as_bytes()?).into_owned())
+    }
+
+    fn as_varint(self) -> Result<u64> {
+        match self {
+            WireValue::Varint(value) => Ok(value),
+            _ => bail!("expected a varint field"),
+        }
+    }
+}
+
+/// A reader for the fields of an encoded message.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.data.len() {
+            bail!("unexpected end of message");
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("varint is too long")
+    }
+
+    fn fixed32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn fixed64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    /// Read the next field number and value, if there are any left.
+    fn field(&mut self) -> Result<Option<(u32, WireValue<'a>)>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let number = u32::try_from(key >> 3)
+            .ok()
+            .filter(|&number| number != 0)
+            .ok_or_else(|| anyhow!("invalid field number {}", key >> 3))?;
+        let value = match key & 7 {
+            0 => WireValue::Varint(self.varint()?),
+            1 => WireValue::Fixed64(self.fixed64()?),
+            2 => {
+                let len = self.varint()?;
+                WireValue::Bytes(self.take(len.try_into().unwrap_or(usize::MAX))?)
+            }
+            3 | 4 => bail!("groups are not supported"),
+            5 => WireValue::Fixed32(self.fixed32()?),
+            wire_type => bail!("invalid wire type {}", wire_type),
+        };
+        Ok(Some((number, value)))
+    }
+
+    /// Read one element of a packed repeated field.
+    fn packed(&mut self, field_type: i32) -> Result<WireValue<'a>> {
+        Ok(match field_type {
+            TYPE_DOUBLE | TYPE_FIXED64 | TYPE_SFIXED64 => WireValue::Fixed64(self.fixed64()?),
+            TYPE_FLOAT | TYPE_FIXED32 | TYPE_SFIXED32 => WireValue::Fixed32(self.fixed32()?),
+            _ => WireValue::Varint(self.varint()?),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct MessageType {
+    fields: Vec<Field>,
+    map_entry: bool,
+}
+
+#[derive(Debug, Default)]
+struct Field {
+    name: String,
+    json_name: Option<String>,
+    number: u32,
+    label: i32,
+    field_type: i32,
+    /// The fully qualified name of a message or enum type, without a leading dot.
+    type_name: String,
+}
+
+impl Field {
+    fn parse(data: &[u8]) -> Result<Field> {
+        let mut field = Field::default();
+        let mut reader = Reader::new(data);
+        while let Some((number, value)) = reader.field()? {
+            match number {
+                1 => field.name = value.as_string()?,
+                3 => field.number = value.as_varint()? as u32,
+                4 => field.label = value.as_varint()? as i32,
+                5 => field.field_type = value.as_varint()? as i32,
+                6 => field.type_name = value.as_string()?.trim_start_matches('.').to_string(),
+                10 => field.json_name = Some(value.as_string()?),
+                _ => {}
+            }
+        }
+        Ok(field)
+    }
+
+    fn json_name(&self) -> String {
+        self.json_name
+            .clone()
+            .unwrap_or_else(|| lower_camel_case(&self.name))
+    }
+
+    fn is_packable(&self) -> bool {
+        !matches!(
+            self.field_type,
+            TYPE_STRING | TYPE_GROUP | TYPE_MESSAGE | TYPE_BYTES
+        )
+    }
+}
+
+/// The message and enum types from a descriptor set, by fully qualified name.
+#[derive(Debug, Default)]
+struct Descriptors {
+    messages: HashMap<String, MessageType>,
+    enums: HashMap<String, HashMap<i32, String>>,
+}
+
+impl Descriptors {
+    fn parse(data: &[u8]) -> Result<Descriptors> {
+        let mut descriptors = Descriptors::default();
+        let mut reader = Reader::new(data);
+        while let Some((number, value)) = reader.field()? {
+            if number == 1 {
+                descriptors.add_file(value.as_bytes()?)?;
+            }
+        }
+        Ok(descriptors)
+    }
+
+    fn add_file(&mut self, data: &[u8]) -> Result<()> {
+        let mut package = String::new();
+        let mut messages = Vec::new();
+        let mut enums = Vec::new();
+        let mut reader = Reader::new(data);
+        while let Some((number, value)) = reader.field()? {
+            match number {
+                2 => package = value.as_string()?,
+                4 => messages.push(value.as_bytes()?),
+                5 => enums.push(value.as_bytes()?),
+                _ => {}
+            }
+        }
+        for message in messages {
+            self.add_message(&package, message)?;
+        }
+        for enum_type in enums {
+            self.add_enum(&package, enum_type)?;
+        }
+        Ok(())
+    }
+
+    fn add_message(&mut self, scope: &str, data: &[u8]) -> Result<()> {
+        let mut name = String::new();
+        let mut message = MessageType::default();
+        let mut nested = Vec::new();
+        let mut enums = Vec::new();
+        let mut reader = Reader::new(data);
+        while let Some((number, value)) = reader.field()? {
+            match number {
+                1 => name = value.as_string()?,
+                2 => message.fields.push(Field::parse(value.as_bytes()?)?),
+                3 => nested.push(value.as_bytes()?),
+                4 => enums.push(value.as_bytes()?),
+                7 => {
+                    let mut options = Reader::new(value.as_bytes()?);
+                    while let Some((number, value)) = options.field()? {
+                        if number == 7 {
+                            message.map_entry = value.as_varint()? != 0;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let full_name = qualify(scope, &name);
+        for message in nested {
+            self.add_message(&full_name, message)?;
+        }
+        for enum_type in enums {
+            self.add_enum(&full_name, enum_type)?;
+        }
+        self.messages.insert(full_name, message);
+        Ok(())
+    }
+
+    fn add_enum(&mut self, scope: &str, data: &[u8]) -> Result<()> {
+        let mut name = String::new();
+        let mut values = HashMap::new();
+        let mut reader = Reader::new(data);
+        while let Some((number, value)) = reader.field()? {
+            match number {
+                1 => name = value.as_string()?,
+                2 => {
+                    let (mut value_name, mut value_number) = (String::new(), 0);
+                    let mut value_reader = Reader::new(value.as_bytes()?);
+                    while let Some((number, value)) = value_reader.field()? {
+                        match number {
+                            1 => value_name = value.as_string()?,
+                            2 => value_number = value.as_varint()? as i32,
+                            _ => {}
+                        }
+                    }
+                    // With allow_alias the first name is the canonical one
+                    values.entry(value_number).or_insert(value_name);
+                }
+                _ => {}
+            }
+        }
+        self.enums.insert(qualify(scope, &name), values);
+        Ok(())
+    }
+
+    fn message(&self, type_name: &str) -> Result<&MessageType> {
+        self.messages
+            .get(type_name)
+            .ok_or_else(|| anyhow!("unknown message type {:?}", type_name))
+    }
+
+    fn decode_message(&self, type_name: &str, data: &[u8], depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("message is nested too deeply");
+        }
+        let message = self.message(type_name)?;
+        let mut object = Map::new();
+        let mut reader = Reader::new(data);
+        while let Some((number, value)) = reader.field()? {
+            let Some(field) = message.fields.iter().find(|field| field.number == number) else {
+                continue;
+            };
+            let entry = object.entry(field.json_name());
+            if let Some(map_entry) = self.map_entry(field) {
+                let (key, value) =
+                    self.decode_map_entry(map_entry, value.as_bytes()?, depth + 1)?;
+                if let Some(map) = entry
+                    .or_insert_with(|| Value::Object(Map::new()))
+                    .as_object_mut()
+                {
+                    map.insert(key, value);
+                }
+            } else if field.label == LABEL_REPEATED {
+                let mut elements = Vec::new();
+                match value {
+                    WireValue::Bytes(packed) if field.is_packable() => {
+                        let mut packed = Reader::new(packed);
+                        while !packed.is_empty() {
+                            let value = packed.packed(field.field_type)?;
+                            elements.push(self.decode_value(field, value, depth)?);
+                        }
+                    }
+                    _ => elements.push(self.decode_value(field, value, depth)?),
+                }
+                if let Some(array) = entry
+                    .or_insert_with(|| Value::Array(Vec::new()))
+                    .as_array_mut()
+                {
+                    array.extend(elements);
+                }
+            } else {
+                // The last value wins
+                let value = self.decode_value(field, value, depth)?;
+                *entry.or_insert(Value::Null) = value;
+            }
+        }
+        Ok(Value::Object(object))
+    }
+
+    /// The entry type of a map field.
+    fn map_entry(&self, field: &Field) -> Option<&MessageType> {
+        if field.label != LABEL_REPEATED || field.field_type != TYPE_MESSAGE {
+            return None;
+        }
+        self.messages
+            .get(&field.type_name)
+            .filter(|message| message.map_entry)
+    }
+
+    fn decode_map_entry(
+        &self,
+        entry: &MessageType,
+        data: &[u8],
+        depth: usize,
+    ) -> Result<(String, Value)> {
+        let key_field = entry.fields.iter().find(|field| field.number == 1);
+        let value_field = entry.fields.iter().find(|field| field.number == 2);
+        let (mut key, mut value) = (None, None);
+        let mut reader = Reader::new(data);
+        while let Some((number, wire_value)) = reader.field()? {
+            match (number, key_field, value_field) {
+                (1, Some(field), _) => key = Some(self.decode_value(field, wire_value, depth)?),
+                (2, _, Some(field)) => value = Some(self.decode_value(field, wire_value, depth)?),
+                _ => {}
+            }
+        }
+        // Keys and values that are missing have their default value
+        let key = match key.or_else(|| key_field.map(|field| self.default_value(field))) {
+            Some(Value::String(key)) => key,
+            Some(key) => key.to_string(),
+            None => String::new(),
+        };
+        let value = value
+            .or_else(|| value_field.map(|field| self.default_value(field)))
+            .unwrap_or(Value::Null);
+        Ok((key, value))
+    }
+
+    fn decode_value(&self, field: &Field, value: WireValue, depth: usize) -> Result<Value> {
+        use WireValue::*;
+
+        Ok(match (field.field_type, value) {
+            (TYPE_DOUBLE, Fixed64(bits)) => json_float(f64::from_bits(bits)),
+            (TYPE_FLOAT, Fixed32(bits)) => json_f32(f32::from_bits(bits)),
+            (TYPE_INT64, Varint(value)) => Value::String((value as i64).to_string()),
+            (TYPE_UINT64, Varint(value)) | (TYPE_FIXED64, Fixed64(value)) => {
+                Value::String(value.to_string())
+            }
+            (TYPE_SFIXED64, Fixed64(value)) => Value::String((value as i64).to_string()),
+            (TYPE_SINT64, Varint(value)) => Value::String(zigzag(value).to_string()),
+            (TYPE_INT32, Varint(value)) => Value::from(value as i32),
+            (TYPE_UINT32, Varint(value)) => Value::from(value as u32),
+            (TYPE_FIXED32, Fixed32(value)) => Value::from(value),
+            (TYPE_SFIXED32, Fixed32(value)) => Value::from(value as i32),
+            (TYPE_SINT32, Varint(value)) => Value::from(zigzag(value) as i32),
+            (TYPE_BOOL, Varint(value)) => Value::Bool(value != 0),
+            (TYPE_ENUM, Varint(value)) => self.enum_value(&field.type_name, value as i32),
+            (TYPE_STRING, Bytes(bytes)) => Value::String(String::from_utf8_lossy(bytes).into()),
+            (TYPE_BYTES, Bytes(bytes)) => Value::String(BASE64_STANDARD.encode(bytes)),
+            (TYPE_MESSAGE, Bytes(bytes)) => {
+                self.decode_message(&field.type_name, bytes, depth + 1)?
+            }
+            _ => bail!("field {:?} has an unexpected wire type", field.name),
+        })
+    }
+
+    fn default_value(&self, field: &Field) -> Value {
+        match field.field_type {
+            TYPE_INT64 | TYPE_UINT64 | TYPE_FIXED64 | TYPE_SFIXED64 | TYPE_SINT64 => {
+                Value::String("0".to_string())
+            }
+            TYPE_STRING | TYPE_BYTES => Value::String(String::new()),
+            TYPE_BOOL => Value::Bool(false),
+            TYPE_ENUM => self.enum_value(&field.type_name, 0),
+            TYPE_MESSAGE => Value::Object(Map::new()),
+            _ => Value::from(0),
+        }
+    }
+
+    /// The name of an enum value, or its number if it's unknown.
+    fn enum_value(&self, type_name: &str, number: i32) -> Value {
+        match self
+            .enums
+            .get(type_name)
+            .and_then(|values| values.get(&number))
+        {
+            Some(name) => Value::String(name.clone()),
+            None => Value::from(number),
+        }
+    }
+}
+
+/// Decodes messages of one type to JSON.
+#[derive(Debug)]
+pub struct Decoder {
+    descriptors: Descriptors,
+    message_type: String,
+}
+
+impl Decoder {
+    /// Load a descriptor set and look up the type of message to decode.
+    pub fn load(path: &Path, message_type: &str) -> Result<Decoder> {
+        let data = fs::read(path)
+            .with_context(|| format!("Failed to read protobuf descriptors from {:?}", path))?;
+        let descriptors = Descriptors::parse(&data)
+            .with_context(|| format!("Invalid protobuf descriptor set {:?}", path))?;
+        Decoder::new(descriptors, message_type)
+    }
+
+    fn new(descriptors: Descriptors, message_type: &str) -> Result<Decoder> {
+        let message_type = message_type.trim_start_matches('.');
+        if !descriptors.messages.contains_key(message_type) {
+            bail!(
+                "Message type {:?} not found in protobuf descriptors",
+                message_type
+            );
+        }
+        Ok(Decoder {
+            message_type: message_type.to_string(),
+            descriptors,
+        })
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Value> {
+        self.descriptors
+            .decode_message(&self.message_type, data, 0)
+            .with_context(|| format!("Failed to decode protobuf message {}", self.message_type))
+    }
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+/// Turn a field name like "user_id" into "userId", like protoc does when
+/// json_name isn't set.
+fn lower_camel_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            result.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn zigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn varint(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while value >= 0x80 {
+            bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+        bytes
+    }
+
+    fn int_field(number: u32, value: u64) -> Vec<u8> {
+        [varint(u64::from(number) << 3), varint(value)].concat()
+    }
+
+    fn bytes_field(number: u32, value: &[u8]) -> Vec<u8> {
+        [
+            varint(u64::from(number) << 3 | 2),
+            varint(value.len() as u64),
+            value.to_vec(),
+        ]
+        .concat()
+    }
+
+    fn field(name: &str, number: u64, label: u64, field_type: u64, type_name: &str) -> Vec<u8> {
+        let mut field = [
+            bytes_field(1, name.as_bytes()),
+            int_field(3, number),
+            int_field(4, label),
+            int_field(5, field_type),
+        ]
+        .concat();
+        if !type_name.is_empty() {
+            field.extend(bytes_field(6, type_name.as_bytes()));
+        }
+        bytes_field(2, &field)
+    }
+
+    /// package test;
+    /// message User {
+    ///   string name = 1;
+    ///   int64 id = 2;
+    ///   repeated int32 scores = 3;
+    ///   Status status = 4;
+    ///   map<string, sint32> counts = 5;
+    ///   User best_friend = 6;
+    ///   bytes avatar = 7;
+    ///   float ratio = 8;
+    ///   enum Status { UNKNOWN = 0; ACTIVE = 1; }
+    /// }
+    fn descriptors() -> Descriptors {
+        let counts_entry = [
+            bytes_field(1, b"CountsEntry"),
+            field("key", 1, 1, 9, ""),
+            field("value", 2, 1, 17, ""),
+            bytes_field(7, &int_field(7, 1)),
+        ]
+        .concat();
+        let status = [
+            bytes_field(1, b"Status"),
+            bytes_field(2, &[bytes_field(1, b"UNKNOWN"), int_field(2, 0)].concat()),
+            bytes_field(2, &[bytes_field(1, b"ACTIVE"), int_field(2, 1)].concat()),
+        ]
+        .concat();
+        let user = [
+            bytes_field(1, b"User"),
+            field("name", 1, 1, 9, ""),
+            field("id", 2, 1, 3, ""),
+            field("scores", 3, 3, 5, ""),
+            field("status", 4, 1, 14, ".test.User.Status"),
+            field("counts", 5, 3, 11, ".test.User.CountsEntry"),
+            field("best_friend", 6, 1, 11, ".test.User"),
+            field("avatar", 7, 1, 12, ""),
+            field("ratio", 8, 1, 2, ""),
+            bytes_field(3, &counts_entry),
+            bytes_field(4, &status),
+        ]
+        .concat();
+        let file = [
+            bytes_field(1, b"user.proto"),
+            bytes_field(2, b"test"),
+            bytes_field(4, &user),
+        ]
+        .concat();
+        Descriptors::parse(&bytes_field(1, &file)).unwrap()
+    }
+
+    #[test]
+    fn decode() {
+        let decoder = Decoder::new(descriptors(), ".test.User").unwrap();
+        let friend = [bytes_field(1, b"bob"), int_field(4, 7)].concat();
+        let message = [
+            bytes_field(1, b"alice"),
+            int_field(2, u64::MAX),
+            bytes_field(3, &[varint(1), varint(300)].concat()),
+            int_field(3, 2),
+            int_field(4, 1),
+            bytes_field(5, &[bytes_field(1, b"a"), int_field(2, 3)].concat()),
+            bytes_field(5, &bytes_field(1, b"b")),
+            bytes_field(6, &friend),
+            bytes_field(7, b"\xff\x00"),
+            [varint(8 << 3 | 5), 0.1f32.to_le_bytes().to_vec()].concat(),
+            int_field(99, 1),
+        ]
+        .concat();
+        assert_eq!(
+            decoder.decode(&message).unwrap(),
+            json!({
+                "name": "alice",
+                "id": "-1",
+                "scores": [1, 300, 2],
+                "status": "ACTIVE",
+                "counts": {"a": -2, "b": 0},
+                "bestFriend": {"name": "bob", "status": 7},
+                "avatar": "/wA=",
+                "ratio": 0.1,
+            })
+        );
+
+        assert!(decoder.decode(&bytes_field(1, b"alice")[..3]).is_err());
+        assert!(decoder.decode(&int_field(1, 1)).is_err());
+        assert!(Decoder::new(descriptors(), "test.Missing").is_err());
+    }
+}
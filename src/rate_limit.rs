@@ -0,0 +1,101 @@
+//! Throttling transfers, for --limit-rate, and chunked uploads for --chunked.
+
+use std::io::{self, Cursor, Read};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use reqwest::blocking::{Body, Request, Response};
+
+use crate::middleware::{Context, Middleware};
+
+/// A reader that sleeps as needed to stay under `rate` bytes per second.
+pub struct RateLimit<R> {
+    inner: R,
+    rate: Option<u64>,
+    start: Instant,
+    count: u64,
+}
+
+impl<R: Read> RateLimit<R> {
+    pub fn new(inner: R, rate: Option<u64>) -> Self {
+        RateLimit {
+            inner,
+            rate,
+            start: Instant::now(),
+            count: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for RateLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(rate) = self.rate else {
+            return self.inner.read(buf);
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // Read in small pieces so the transfer stays smooth instead of
+        // alternating between bursts and long pauses
+        let chunk_size = (rate / 10).clamp(1, buf.len() as u64) as usize;
+        let n = self.inner.read(&mut buf[..chunk_size])?;
+        self.count += n as u64;
+        let due = Duration::from_secs_f64(self.count as f64 / rate as f64);
+        if let Some(wait) = due.checked_sub(self.start.elapsed()) {
+            thread::sleep(wait);
+        }
+        Ok(n)
+    }
+}
+
+/// Throttles request bodies while they're being sent.
+///
+/// This has to wrap the body of every attempt separately, because other
+/// middleware buffers the body to be able to send it again. It should come
+/// right before the [`Logger`](crate::logging::Logger).
+pub struct UploadRateLimiter {
+    rate: u64,
+    chunked: bool,
+}
+
+impl UploadRateLimiter {
+    pub fn new(rate: u64, chunked: bool) -> Self {
+        UploadRateLimiter { rate, chunked }
+    }
+}
+
+impl Middleware for UploadRateLimiter {
+    fn handle(&mut self, mut ctx: Context, mut request: Request) -> Result<Response> {
+        if let Some(body) = request.body_mut() {
+            let bytes = body.buffer()?.to_vec();
+            let len = bytes.len() as u64;
+            let reader = RateLimit::new(Cursor::new(bytes), Some(self.rate));
+            *body = if self.chunked {
+                Body::new(reader)
+            } else {
+                Body::sized(reader, len)
+            };
+        }
+        self.next(&mut ctx, request)
+    }
+}
+
+/// Sends request bodies without a length, so that HTTP/1.1 uses chunked
+/// encoding, for --chunked.
+///
+/// Like [`UploadRateLimiter`], which takes care of this itself when it's used,
+/// this has to come after any middleware that buffers the body.
+pub struct ChunkedUpload;
+
+impl Middleware for ChunkedUpload {
+    fn handle(&mut self, mut ctx: Context, mut request: Request) -> Result<Response> {
+        if let Some(body) = request.body_mut() {
+            // Streamed bodies are already sent the right way
+            if let Some(bytes) = body.as_bytes() {
+                *body = Body::new(Cursor::new(bytes.to_vec()));
+            }
+        }
+        self.next(&mut ctx, request)
+    }
+}
@@ -11,11 +11,17 @@ use crate::utils::clone_request;
 
 pub struct RedirectFollower {
     max_redirects: usize,
+    preserve_method: bool,
+    trust_redirects: bool,
 }
 
 impl RedirectFollower {
-    pub fn new(max_redirects: usize) -> Self {
-        RedirectFollower { max_redirects }
+    pub fn new(max_redirects: usize, preserve_method: bool, trust_redirects: bool) -> Self {
+        RedirectFollower {
+            max_redirects,
+            preserve_method,
+            trust_redirects,
+        }
     }
 }
 
@@ -27,7 +33,7 @@ impl Middleware for RedirectFollower {
         let mut response = self.next(&mut ctx, first_request)?;
         let mut remaining_redirects = self.max_redirects - 1;
 
-        while let Some(mut next_request) = get_next_request(request, &response) {
+        while let Some(mut next_request) = self.get_next_request(request, &response) {
             if remaining_redirects > 0 {
                 remaining_redirects -= 1;
             } else {
@@ -45,43 +51,40 @@ impl Middleware for RedirectFollower {
     }
 }
 
-// See https://github.com/seanmonstar/reqwest/blob/bbeb1ede4e8098481c3de6f2cafb8ecca1db4ede/src/async_impl/client.rs#L1500-L1607
-fn get_next_request(mut request: Request, response: &Response) -> Option<Request> {
-    let get_next_url = |request: &Request| {
-        response
-            .headers()
-            .get(LOCATION)
-            .and_then(|location| location.to_str().ok())
-            .and_then(|location| request.url().join(location).ok())
-    };
+impl RedirectFollower {
+    // See https://github.com/seanmonstar/reqwest/blob/bbeb1ede4e8098481c3de6f2cafb8ecca1db4ede/src/async_impl/client.rs#L1500-L1607
+    fn get_next_request(&self, mut request: Request, response: &Response) -> Option<Request> {
+        let get_next_url = |request: &Request| {
+            response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| request.url().join(location).ok())
+        };
+
+        let keep_method = match response.status() {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => self.preserve_method,
+            StatusCode::SEE_OTHER => false,
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => true,
+            _ => return None,
+        };
 
-    match response.status() {
-        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER => {
-            let next_url = get_next_url(&request)?;
-            let prev_url = request.url();
-            if is_cross_domain_redirect(&next_url, prev_url) {
-                remove_sensitive_headers(request.headers_mut());
-            }
+        let next_url = get_next_url(&request)?;
+        let prev_url = request.url();
+        if is_cross_domain_redirect(&next_url, prev_url) {
+            remove_sensitive_headers(request.headers_mut(), self.trust_redirects);
+        }
+        if !keep_method {
             remove_content_headers(request.headers_mut());
-            *request.url_mut() = next_url;
             *request.body_mut() = None;
             *request.method_mut() = match *request.method() {
                 Method::GET => Method::GET,
                 Method::HEAD => Method::HEAD,
                 _ => Method::GET,
             };
-            Some(request)
-        }
-        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => {
-            let next_url = get_next_url(&request)?;
-            let prev_url = request.url();
-            if is_cross_domain_redirect(&next_url, prev_url) {
-                remove_sensitive_headers(request.headers_mut());
-            }
-            *request.url_mut() = next_url;
-            Some(request)
         }
-        _ => None,
+        *request.url_mut() = next_url;
+        Some(request)
     }
 }
 
@@ -92,8 +95,10 @@ fn is_cross_domain_redirect(next: &Url, previous: &Url) -> bool {
 }
 
 // See https://github.com/seanmonstar/reqwest/blob/bbeb1ede4e8098481c3de6f2cafb8ecca1db4ede/src/redirect.rs#L234-L246
-fn remove_sensitive_headers(headers: &mut HeaderMap) {
-    headers.remove(AUTHORIZATION);
+fn remove_sensitive_headers(headers: &mut HeaderMap, keep_authorization: bool) {
+    if !keep_authorization {
+        headers.remove(AUTHORIZATION);
+    }
     headers.remove(COOKIE);
     headers.remove("cookie2");
     headers.remove(PROXY_AUTHORIZATION);
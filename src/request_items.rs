@@ -1,19 +1,24 @@
 use std::{
     borrow::Cow,
     collections::HashSet,
+    env,
     fs::{self, File},
     io,
     path::{Path, PathBuf},
+    process::{Command, Stdio},
     str::FromStr,
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use reqwest::{blocking::multipart, Method};
+use serde_json::Value;
 
 use crate::cli::BodyType;
+use crate::generators::{generate, generate_string};
 use crate::nested_json;
-use crate::utils::{expand_tilde, unescape};
+use crate::utils::{expand_tilde, test_mode, unescape};
 
 pub const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
 pub const JSON_CONTENT_TYPE: &str = "application/json";
@@ -23,9 +28,11 @@ pub const JSON_ACCEPT: &str = "application/json, */*;q=0.5";
 pub enum RequestItem {
     HttpHeader(String, String),
     HttpHeaderFromFile(String, String),
+    HttpHeaderFromCommand(String, String),
     HttpHeaderToUnset(String),
     UrlParam(String, String),
     UrlParamFromFile(String, String),
+    UrlParamFromCommand(String, String),
     DataField {
         key: String,
         raw_key: String,
@@ -36,8 +43,21 @@ pub enum RequestItem {
         raw_key: String,
         value: String,
     },
+    DataFieldFromCommand {
+        key: String,
+        raw_key: String,
+        value: String,
+    },
+    /// A file embedded as a base64 string, from `key=@file;encoding=base64`
+    /// or `key:=@file;encoding=base64`.
+    Base64FieldFromFile {
+        key: String,
+        raw_key: String,
+        value: String,
+    },
     JsonField(String, serde_json::Value),
     JsonFieldFromFile(String, String),
+    JsonFieldFromCommand(String, String),
     FormFile {
         key: String,
         file_name: String,
@@ -49,8 +69,11 @@ pub enum RequestItem {
 impl FromStr for RequestItem {
     type Err = clap::Error;
     fn from_str(request_item: &str) -> clap::error::Result<RequestItem> {
-        const SPECIAL_CHARS: &str = "=@:;\\";
-        const SEPS: &[&str] = &["==@", "=@", ":=@", ":@", "==", ":=", "=", "@", ":"];
+        const SPECIAL_CHARS: &str = "=@:;!\\";
+        const BASE64_SUFFIX: &str = ";encoding=base64";
+        const SEPS: &[&str] = &[
+            "==@", "==!", "=@", "=!", ":=@", ":=!", ":@", ":!", "==", ":=", "=", "@", ":",
+        ];
 
         fn split(request_item: &str) -> Option<(&str, &'static str, &str)> {
             let mut char_inds = request_item.char_indices();
@@ -77,6 +100,22 @@ impl FromStr for RequestItem {
             let raw_key = raw_key.to_string();
             let key = unescape(&raw_key, SPECIAL_CHARS);
             let value = unescape(value, SPECIAL_CHARS);
+            let invalid_value = |err: &dyn std::fmt::Display| {
+                clap::Error::raw(
+                    clap::error::ErrorKind::InvalidValue,
+                    format!(
+                        "Invalid value for '[REQUEST_ITEM]...': {:?} {}",
+                        request_item, err
+                    ),
+                )
+            };
+            let value = match sep {
+                "==" | "=" | ":" => match generate_string(&value) {
+                    Ok(generated) => generated.unwrap_or(value),
+                    Err(err) => return Err(invalid_value(&err)),
+                },
+                _ => value,
+            };
             match sep {
                 "==" => Ok(RequestItem::UrlParam(key, value)),
                 "=" => Ok(RequestItem::DataField {
@@ -84,18 +123,16 @@ impl FromStr for RequestItem {
                     raw_key,
                     value,
                 }),
-                ":=" => Ok(RequestItem::JsonField(
-                    raw_key,
-                    serde_json::from_str(&value).map_err(|err| {
-                        clap::Error::raw(
-                            clap::error::ErrorKind::InvalidValue,
-                            format!(
-                                "Invalid value for '[REQUEST_ITEM]...': {:?} {}",
-                                request_item, err
-                            ),
-                        )
-                    })?,
-                )),
+                ":=" => {
+                    let value = match generate(&value) {
+                        Ok(Some(generated)) => generated,
+                        Ok(None) => {
+                            serde_json::from_str(&value).map_err(|err| invalid_value(&err))?
+                        }
+                        Err(err) => return Err(invalid_value(&err)),
+                    };
+                    Ok(RequestItem::JsonField(raw_key, value))
+                }
                 "@" => {
                     let PartWithParams {
                         value,
@@ -112,6 +149,15 @@ impl FromStr for RequestItem {
                 ":" if value.is_empty() => Ok(RequestItem::HttpHeaderToUnset(key)),
                 ":" => Ok(RequestItem::HttpHeader(key, value)),
                 "==@" => Ok(RequestItem::UrlParamFromFile(key, value)),
+                "=@" | ":=@" if value.ends_with(BASE64_SUFFIX) => {
+                    let mut value = value;
+                    value.truncate(value.len() - BASE64_SUFFIX.len());
+                    Ok(RequestItem::Base64FieldFromFile {
+                        key,
+                        raw_key,
+                        value,
+                    })
+                }
                 "=@" => Ok(RequestItem::DataFieldFromFile {
                     key,
                     raw_key,
@@ -119,6 +165,14 @@ impl FromStr for RequestItem {
                 }),
                 ":=@" => Ok(RequestItem::JsonFieldFromFile(raw_key, value)),
                 ":@" => Ok(RequestItem::HttpHeaderFromFile(key, value)),
+                "==!" => Ok(RequestItem::UrlParamFromCommand(key, value)),
+                "=!" => Ok(RequestItem::DataFieldFromCommand {
+                    key,
+                    raw_key,
+                    value,
+                }),
+                ":=!" => Ok(RequestItem::JsonFieldFromCommand(raw_key, value)),
+                ":!" => Ok(RequestItem::HttpHeaderFromCommand(key, value)),
                 _ => unreachable!(),
             }
         } else if let Some(header) = request_item.strip_suffix(';') {
@@ -210,13 +264,22 @@ fn rsplit_once_any<'a>(
 pub struct RequestItems {
     pub items: Vec<RequestItem>,
     pub body_type: BodyType,
+    pub boundary: Option<String>,
 }
 
 pub enum Body {
     Json(serde_json::Value),
     Form(Vec<(String, String)>),
     Multipart(multipart::Form),
+    /// A multipart body with a chosen boundary, which reqwest's `Form`
+    /// doesn't support.
+    MultipartWithBoundary {
+        boundary: String,
+        body: Vec<u8>,
+    },
     Raw(Vec<u8>),
+    /// Standard input, which is read while the request is sent.
+    Stdin,
     File {
         file_name: PathBuf,
         file_type: Option<HeaderValue>,
@@ -234,9 +297,10 @@ impl Body {
             // This is a slight divergence from HTTPie, which will simply
             // discard stdin if it receives --multipart without request items,
             // but that behavior is useless so there's no need to match it
-            Body::Multipart(..) => false,
+            Body::Multipart(..) | Body::MultipartWithBoundary { .. } => false,
             Body::File { .. } => false,
             Body::Raw(..) => false,
+            Body::Stdin => false,
         }
     }
 
@@ -250,6 +314,36 @@ impl Body {
 }
 
 impl RequestItems {
+    /// Expand `${VAR}` references to environment variables in item values.
+    pub fn expand_env_vars(&mut self) -> Result<()> {
+        let expand = |text: &mut String| -> Result<()> {
+            *text = expand_env(text, |name| env::var(name).ok())?;
+            Ok(())
+        };
+        for item in &mut self.items {
+            match item {
+                RequestItem::HttpHeader(_, value)
+                | RequestItem::HttpHeaderFromFile(_, value)
+                | RequestItem::HttpHeaderFromCommand(_, value)
+                | RequestItem::UrlParam(_, value)
+                | RequestItem::UrlParamFromFile(_, value)
+                | RequestItem::UrlParamFromCommand(_, value)
+                | RequestItem::DataField { value, .. }
+                | RequestItem::DataFieldFromFile { value, .. }
+                | RequestItem::DataFieldFromCommand { value, .. }
+                | RequestItem::Base64FieldFromFile { value, .. }
+                | RequestItem::JsonFieldFromFile(_, value)
+                | RequestItem::JsonFieldFromCommand(_, value)
+                | RequestItem::FormFile {
+                    file_name: value, ..
+                } => expand(value)?,
+                RequestItem::JsonField(_, value) => expand_json_strings(value, &expand)?,
+                RequestItem::HttpHeaderToUnset(..) => {}
+            }
+        }
+        Ok(())
+    }
+
     pub fn has_form_files(&self) -> bool {
         self.items
             .iter()
@@ -275,6 +369,12 @@ impl RequestItems {
                     headers_to_unset.remove(&key);
                     headers.append(key, value);
                 }
+                RequestItem::HttpHeaderFromCommand(key, command) => {
+                    let key = HeaderName::from_bytes(key.as_bytes())?;
+                    let value = HeaderValue::from_str(&command_output(command)?)?;
+                    headers_to_unset.remove(&key);
+                    headers.append(key, value);
+                }
                 RequestItem::HttpHeaderToUnset(key) => {
                     let key = HeaderName::from_bytes(key.as_bytes())?;
                     headers.remove(&key);
@@ -282,10 +382,14 @@ impl RequestItems {
                 }
                 RequestItem::UrlParam(..) => {}
                 RequestItem::UrlParamFromFile(..) => {}
+                RequestItem::UrlParamFromCommand(..) => {}
                 RequestItem::DataField { .. } => {}
                 RequestItem::DataFieldFromFile { .. } => {}
+                RequestItem::DataFieldFromCommand { .. } => {}
+                RequestItem::Base64FieldFromFile { .. } => {}
                 RequestItem::JsonField(..) => {}
                 RequestItem::JsonFieldFromFile(..) => {}
+                RequestItem::JsonFieldFromCommand(..) => {}
                 RequestItem::FormFile { .. } => {}
             }
         }
@@ -298,16 +402,18 @@ impl RequestItems {
             if let RequestItem::UrlParam(key, value) = item {
                 query.push((key, Cow::Borrowed(value)));
             } else if let RequestItem::UrlParamFromFile(key, value) = item {
-                let value = fs::read_to_string(expand_tilde(value))?;
+                let mut value = fs::read_to_string(expand_tilde(value))?;
+                // Editors like to add a final newline, which is never wanted in a URL
+                value.truncate(value.trim_end_matches(['\r', '\n']).len());
                 query.push((key, Cow::Owned(value)));
+            } else if let RequestItem::UrlParamFromCommand(key, command) = item {
+                query.push((key, Cow::Owned(command_output(command)?)));
             }
         }
         Ok(query)
     }
 
-    fn body_as_json(self) -> Result<Body> {
-        use serde_json::Value;
-        let mut body = None;
+    fn body_as_json(self, mut body: Option<Value>) -> Result<Body> {
         for item in self.items {
             let (raw_key, value) = match item {
                 RequestItem::JsonField(raw_key, value) => (raw_key, value),
@@ -316,16 +422,29 @@ impl RequestItems {
                     (raw_key, value)
                 }
                 RequestItem::DataField { raw_key, value, .. } => (raw_key, Value::String(value)),
+                RequestItem::JsonFieldFromCommand(raw_key, command) => {
+                    let value = serde_json::from_str(&command_output(&command)?)
+                        .with_context(|| format!("Command {:?} didn't output JSON", command))?;
+                    (raw_key, value)
+                }
                 RequestItem::DataFieldFromFile { raw_key, value, .. } => {
                     let value = fs::read_to_string(expand_tilde(value))?;
                     (raw_key, Value::String(value))
                 }
+                RequestItem::DataFieldFromCommand { raw_key, value, .. } => {
+                    (raw_key, Value::String(command_output(&value)?))
+                }
+                RequestItem::Base64FieldFromFile { raw_key, value, .. } => {
+                    (raw_key, Value::String(read_base64(&value)?))
+                }
                 RequestItem::FormFile { .. } => unreachable!(),
                 RequestItem::HttpHeader(..)
                 | RequestItem::HttpHeaderFromFile(..)
+                | RequestItem::HttpHeaderFromCommand(..)
                 | RequestItem::HttpHeaderToUnset(..)
                 | RequestItem::UrlParam(..)
-                | RequestItem::UrlParamFromFile(..) => continue,
+                | RequestItem::UrlParamFromFile(..)
+                | RequestItem::UrlParamFromCommand(..) => continue,
             };
             let json_path = nested_json::parse_path(&raw_key)?;
             body = nested_json::insert(body, &json_path, value)
@@ -339,7 +458,9 @@ impl RequestItems {
         let mut text_fields = Vec::<(String, String)>::new();
         for item in self.items {
             match item {
-                RequestItem::JsonField(..) | RequestItem::JsonFieldFromFile(..) => {
+                RequestItem::JsonField(..)
+                | RequestItem::JsonFieldFromFile(..)
+                | RequestItem::JsonFieldFromCommand(..) => {
                     return Err(anyhow!("JSON values are not supported in Form fields"));
                 }
                 RequestItem::DataField { key, value, .. } => text_fields.push((key, value)),
@@ -347,54 +468,108 @@ impl RequestItems {
                     let path = expand_tilde(value);
                     text_fields.push((key, fs::read_to_string(path)?));
                 }
+                RequestItem::DataFieldFromCommand { key, value, .. } => {
+                    text_fields.push((key, command_output(&value)?));
+                }
+                RequestItem::Base64FieldFromFile { key, value, .. } => {
+                    text_fields.push((key, read_base64(&value)?));
+                }
                 RequestItem::FormFile { .. } => unreachable!(),
                 RequestItem::HttpHeader(..) => {}
                 RequestItem::HttpHeaderFromFile(..) => {}
+                RequestItem::HttpHeaderFromCommand(..) => {}
                 RequestItem::HttpHeaderToUnset(..) => {}
                 RequestItem::UrlParam(..) => {}
                 RequestItem::UrlParamFromFile(..) => {}
+                RequestItem::UrlParamFromCommand(..) => {}
             }
         }
         Ok(Body::Form(text_fields))
     }
 
     fn body_as_multipart(self) -> Result<Body> {
+        let boundary = match self.boundary.clone() {
+            Some(boundary) => Some(boundary),
+            // Random boundaries would make test output unpredictable
+            None if test_mode() => Some(TEST_BOUNDARY.to_string()),
+            None => None,
+        };
+        let parts = self.multipart_parts()?;
+        if let Some(boundary) = boundary {
+            let body = encode_multipart(parts, &boundary)?;
+            return Ok(Body::MultipartWithBoundary { boundary, body });
+        }
+
         let mut form = multipart::Form::new();
+        for part in parts {
+            match part {
+                FormPart::Text { key, value } => form = form.text(key, value),
+                FormPart::File {
+                    key,
+                    file_name,
+                    file_type,
+                    file_name_header,
+                } => {
+                    let mut part = file_to_part(file_name)?;
+                    if let Some(file_type) = file_type {
+                        part = part.mime_str(&file_type)?;
+                    }
+                    if let Some(file_name_header) = file_name_header {
+                        part = part.file_name(file_name_header);
+                    }
+                    form = form.part(key, part);
+                }
+            }
+        }
+        Ok(Body::Multipart(form))
+    }
+
+    fn multipart_parts(self) -> Result<Vec<FormPart>> {
+        let mut parts = Vec::new();
         for item in self.items {
             match item {
-                RequestItem::JsonField(..) | RequestItem::JsonFieldFromFile(..) => {
+                RequestItem::JsonField(..)
+                | RequestItem::JsonFieldFromFile(..)
+                | RequestItem::JsonFieldFromCommand(..) => {
                     return Err(anyhow!("JSON values are not supported in multipart fields"));
                 }
                 RequestItem::DataField { key, value, .. } => {
-                    form = form.text(key, value);
+                    parts.push(FormPart::Text { key, value });
                 }
                 RequestItem::DataFieldFromFile { key, value, .. } => {
                     let path = expand_tilde(value);
-                    form = form.text(key, fs::read_to_string(path)?);
+                    let value = fs::read_to_string(path)?;
+                    parts.push(FormPart::Text { key, value });
+                }
+                RequestItem::DataFieldFromCommand { key, value, .. } => {
+                    let value = command_output(&value)?;
+                    parts.push(FormPart::Text { key, value });
+                }
+                RequestItem::Base64FieldFromFile { key, value, .. } => {
+                    let value = read_base64(&value)?;
+                    parts.push(FormPart::Text { key, value });
                 }
                 RequestItem::FormFile {
                     key,
                     file_name,
                     file_type,
                     file_name_header,
-                } => {
-                    let mut part = file_to_part(expand_tilde(file_name))?;
-                    if let Some(file_type) = file_type {
-                        part = part.mime_str(&file_type)?;
-                    }
-                    if let Some(file_name_header) = file_name_header {
-                        part = part.file_name(file_name_header);
-                    }
-                    form = form.part(key, part);
-                }
+                } => parts.push(FormPart::File {
+                    key,
+                    file_name: expand_tilde(file_name),
+                    file_type,
+                    file_name_header,
+                }),
                 RequestItem::HttpHeader(..) => {}
                 RequestItem::HttpHeaderFromFile(..) => {}
+                RequestItem::HttpHeaderFromCommand(..) => {}
                 RequestItem::HttpHeaderToUnset(..) => {}
                 RequestItem::UrlParam(..) => {}
                 RequestItem::UrlParamFromFile(..) => {}
+                RequestItem::UrlParamFromCommand(..) => {}
             }
         }
-        Ok(Body::Multipart(form))
+        Ok(parts)
     }
 
     fn body_from_file(self) -> Result<Body> {
@@ -413,7 +588,10 @@ impl RequestItems {
                 RequestItem::DataField { .. }
                 | RequestItem::JsonField(..)
                 | RequestItem::DataFieldFromFile { .. }
-                | RequestItem::JsonFieldFromFile(..) => {
+                | RequestItem::JsonFieldFromFile(..)
+                | RequestItem::DataFieldFromCommand { .. }
+                | RequestItem::Base64FieldFromFile { .. }
+                | RequestItem::JsonFieldFromCommand(..) => {
                     return Err(anyhow!(
                         "Request body (from a file) and request data (key=value) cannot be mixed."
                     ));
@@ -439,9 +617,11 @@ impl RequestItems {
                 }
                 RequestItem::HttpHeader(..)
                 | RequestItem::HttpHeaderFromFile(..)
+                | RequestItem::HttpHeaderFromCommand(..)
                 | RequestItem::HttpHeaderToUnset(..)
                 | RequestItem::UrlParam(..)
-                | RequestItem::UrlParamFromFile(..) => {}
+                | RequestItem::UrlParamFromFile(..)
+                | RequestItem::UrlParamFromCommand(..) => {}
             }
         }
         let body = body.expect("Should have had at least one file field");
@@ -454,10 +634,22 @@ impl RequestItems {
             BodyType::Form if self.has_form_files() => self.body_as_multipart(),
             BodyType::Form => self.body_as_form(),
             BodyType::Json if self.has_form_files() => self.body_from_file(),
-            BodyType::Json => self.body_as_json(),
+            BodyType::Json => self.body_as_json(None),
         }
     }
 
+    /// Whether the data fields can be merged into a JSON document with
+    /// `body_merged_into`, rather than making up the whole body.
+    pub fn can_merge_into_json(&self) -> bool {
+        self.body_type == BodyType::Json && !self.has_form_files()
+    }
+
+    /// Insert the data fields into `base`, like they'd be inserted into an
+    /// empty body.
+    pub fn body_merged_into(self, base: Value) -> Result<Body> {
+        self.body_as_json(Some(base))
+    }
+
     /// Determine whether a multipart request should be used.
     ///
     /// This duplicates logic in `body()` for the benefit of `to_curl`.
@@ -482,6 +674,39 @@ impl RequestItems {
         }
     }
 
+    /// Keep values that start with "!" as they are instead of running them,
+    /// for when --allow-commands isn't given.
+    pub fn disable_commands(&mut self) -> Result<()> {
+        for item in &mut self.items {
+            let literal = |command: &str| format!("!{}", command);
+            *item = match item {
+                RequestItem::HttpHeaderFromCommand(key, command) => {
+                    RequestItem::HttpHeader(key.clone(), literal(command))
+                }
+                RequestItem::UrlParamFromCommand(key, command) => {
+                    RequestItem::UrlParam(key.clone(), literal(command))
+                }
+                RequestItem::DataFieldFromCommand {
+                    key,
+                    raw_key,
+                    value,
+                } => RequestItem::DataField {
+                    key: key.clone(),
+                    raw_key: raw_key.clone(),
+                    value: literal(value),
+                },
+                RequestItem::JsonFieldFromCommand(raw_key, _) => {
+                    return Err(anyhow!(
+                        "The value of {:?} is not valid JSON. Use --allow-commands to run it as a command.",
+                        raw_key
+                    ));
+                }
+                _ => continue,
+            };
+        }
+        Ok(())
+    }
+
     pub fn is_body_empty(&self) -> bool {
         if self.body_type == BodyType::Multipart {
             return false;
@@ -490,13 +715,18 @@ impl RequestItems {
             match item {
                 RequestItem::HttpHeader(..)
                 | RequestItem::HttpHeaderFromFile(..)
+                | RequestItem::HttpHeaderFromCommand(..)
                 | RequestItem::HttpHeaderToUnset(..)
                 | RequestItem::UrlParam(..)
-                | RequestItem::UrlParamFromFile(..) => continue,
+                | RequestItem::UrlParamFromFile(..)
+                | RequestItem::UrlParamFromCommand(..) => continue,
                 RequestItem::DataField { .. }
                 | RequestItem::DataFieldFromFile { .. }
+                | RequestItem::DataFieldFromCommand { .. }
+                | RequestItem::Base64FieldFromFile { .. }
                 | RequestItem::JsonField(..)
                 | RequestItem::JsonFieldFromFile(..)
+                | RequestItem::JsonFieldFromCommand(..)
                 | RequestItem::FormFile { .. } => return false,
             }
         }
@@ -504,6 +734,201 @@ impl RequestItems {
     }
 }
 
+fn expand_json_strings(
+    value: &mut Value,
+    expand: &impl Fn(&mut String) -> Result<()>,
+) -> Result<()> {
+    match value {
+        Value::String(text) => expand(text)?,
+        Value::Array(items) => {
+            for item in items {
+                expand_json_strings(item, expand)?;
+            }
+        }
+        Value::Object(map) => {
+            for item in map.values_mut() {
+                expand_json_strings(item, expand)?;
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+    Ok(())
+}
+
+/// Replace `${VAR}` with the value of an environment variable.
+///
+/// `${VAR:-default}` falls back to a default if the variable isn't set,
+/// and `$$` stands for a single `$`. A `$` followed by anything else is
+/// left alone.
+fn expand_env(text: &str, var: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| anyhow!("Missing closing brace in {:?}", text))?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+            match (var(name), default) {
+                (Some(value), _) => out.push_str(&value),
+                (None, Some(default)) => out.push_str(default),
+                (None, None) => {
+                    return Err(anyhow!("Environment variable {:?} is not set", name));
+                }
+            }
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Read a file and encode it as base64.
+pub fn read_base64(path: &str) -> Result<String> {
+    let data = fs::read(expand_tilde(path))?;
+    Ok(BASE64_STANDARD.encode(data))
+}
+
+/// Run a command through the shell and return its output without trailing
+/// newlines, like `$(...)` does.
+pub fn command_output(command: &str) -> Result<String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    // stdin might be the request body, so don't let the command read it
+    let output = shell
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run command {:?}", command))?;
+    if !output.status.success() {
+        return Err(anyhow!("Command {:?} failed: {}", command, output.status));
+    }
+    let output = String::from_utf8(output.stdout)
+        .with_context(|| format!("Command {:?} didn't output valid UTF-8", command))?;
+    Ok(output.trim_end_matches(['\r', '\n']).to_string())
+}
+
+const TEST_BOUNDARY: &str = "xh-test-boundary";
+
+enum FormPart {
+    Text {
+        key: String,
+        value: String,
+    },
+    File {
+        key: String,
+        file_name: PathBuf,
+        file_type: Option<String>,
+        file_name_header: Option<String>,
+    },
+}
+
+/// The Content-Type of a multipart body. Boundaries that contain spaces or
+/// separators like `:` and `=` have to be quoted.
+pub fn multipart_content_type(boundary: &str) -> String {
+    let is_token = boundary
+        .chars()
+        .all(|c| c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?=".contains(c));
+    if is_token {
+        format!("multipart/form-data; boundary={}", boundary)
+    } else {
+        format!("multipart/form-data; boundary=\"{}\"", boundary)
+    }
+}
+
+/// Encode a multipart/form-data body the same way reqwest does, but with
+/// a boundary of our choosing.
+fn encode_multipart(parts: Vec<FormPart>, boundary: &str) -> Result<Vec<u8>> {
+    // RFC 2046 limits boundaries to 70 characters from a small set
+    let valid_boundary = !boundary.is_empty()
+        && boundary.len() <= 70
+        && !boundary.ends_with(' ')
+        && boundary
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(c));
+    if !valid_boundary {
+        return Err(anyhow!("Invalid multipart boundary: {:?}", boundary));
+    }
+
+    let mut body = Vec::new();
+    if parts.is_empty() {
+        // reqwest sends nothing at all for an empty form
+        return Ok(body);
+    }
+    for part in parts {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        match part {
+            FormPart::Text { key, value } => {
+                body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                        quote_param(&key)
+                    )
+                    .as_bytes(),
+                );
+                body.extend_from_slice(value.as_bytes());
+            }
+            FormPart::File {
+                key,
+                file_name,
+                file_type,
+                file_name_header,
+            } => {
+                let file_name_header = file_name_header.or_else(|| {
+                    file_name
+                        .file_name()
+                        .map(|file_name| file_name.to_string_lossy().to_string())
+                });
+                let mut headers = format!(
+                    "Content-Disposition: form-data; name=\"{}\"",
+                    quote_param(&key)
+                );
+                if let Some(file_name_header) = file_name_header {
+                    headers.push_str(&format!(
+                        "; filename=\"{}\"",
+                        quote_param(&file_name_header)
+                    ));
+                }
+                if let Some(file_type) = file_type {
+                    headers.push_str(&format!("\r\nContent-Type: {}", file_type));
+                }
+                headers.push_str("\r\n\r\n");
+                body.extend_from_slice(headers.as_bytes());
+                body.extend_from_slice(&fs::read(file_name)?);
+            }
+        }
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    Ok(body)
+}
+
+/// Escape a name for use inside a quoted Content-Disposition parameter,
+/// following the HTML standard.
+fn quote_param(name: &str) -> String {
+    name.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
 pub fn file_to_part(path: impl AsRef<Path>) -> io::Result<multipart::Part> {
     let path = path.as_ref();
     let file_name = path
@@ -522,7 +947,23 @@ pub fn file_to_part(path: impl AsRef<Path>) -> io::Result<multipart::Part> {
 mod tests {
     use super::*;
 
-    use serde_json::json;
+    #[test]
+    fn env_var_expansion() {
+        let var = |name: &str| (name == "HOST").then(|| "example.org".to_string());
+        assert_eq!(
+            expand_env("https://${HOST}/x", var).unwrap(),
+            "https://example.org/x"
+        );
+        assert_eq!(expand_env("${PORT:-8080}", var).unwrap(), "8080");
+        assert_eq!(expand_env("${HOST:-x}", var).unwrap(), "example.org");
+        assert_eq!(
+            expand_env("$$${HOST} $5 $", var).unwrap(),
+            "$example.org $5 $"
+        );
+        assert_eq!(expand_env("$${HOST}", var).unwrap(), "${HOST}");
+        expand_env("${PORT}", var).unwrap_err();
+        expand_env("${HOST", var).unwrap_err();
+    }
 
     #[test]
     fn request_item_parsing() {
@@ -582,6 +1023,37 @@ mod tests {
             parse("foo:=@data.json"),
             JsonFieldFromFile("foo".into(), "data.json".into())
         );
+        // Base64 field from file
+        assert_eq!(
+            parse("foo:=@data.bin;encoding=base64"),
+            Base64FieldFromFile {
+                key: "foo".into(),
+                raw_key: "foo".into(),
+                value: "data.bin".into()
+            }
+        );
+        // Values from commands
+        assert_eq!(
+            parse("foo:!cat token"),
+            HttpHeaderFromCommand("foo".into(), "cat token".into())
+        );
+        assert_eq!(
+            parse("foo==!date"),
+            UrlParamFromCommand("foo".into(), "date".into())
+        );
+        assert_eq!(
+            parse("foo=!date"),
+            DataFieldFromCommand {
+                key: "foo".into(),
+                raw_key: "foo".into(),
+                value: "date".into()
+            }
+        );
+        assert_eq!(
+            parse("foo:=!date +%s"),
+            JsonFieldFromCommand("foo".into(), "date +%s".into())
+        );
+        assert_eq!(parse(r"foo:\!bar"), HttpHeader("foo".into(), "!bar".into()));
         // Bad JSON field
         "foo:=bar".parse::<RequestItem>().unwrap_err();
         // Can't escape normal chars
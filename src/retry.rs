@@ -0,0 +1,159 @@
+//! Retrying requests that fail for reasons that might go away, for --retry.
+
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::blocking::{Request, Response};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Method, StatusCode};
+
+use crate::middleware::{Context, Middleware};
+use crate::utils::clone_request;
+
+/// The delay stops doubling once it reaches this.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Retries requests after connection errors, timeouts and responses like
+/// 503 Service Unavailable, with exponential backoff. Requests with methods
+/// that aren't idempotent are only retried if `all_methods` is set.
+///
+/// This should come right before the [`Logger`](crate::logging::Logger) so
+/// that every attempt gets logged.
+pub struct Retrier<'a> {
+    retries: usize,
+    delay: Duration,
+    all_methods: bool,
+    bin_name: &'a str,
+    verbose: bool,
+}
+
+impl<'a> Retrier<'a> {
+    pub fn new(
+        retries: usize,
+        delay: Duration,
+        all_methods: bool,
+        bin_name: &'a str,
+        verbose: bool,
+    ) -> Self {
+        Retrier {
+            retries,
+            delay,
+            all_methods,
+            bin_name,
+            verbose,
+        }
+    }
+}
+
+impl<'a> Middleware for Retrier<'a> {
+    fn handle(&mut self, mut ctx: Context, mut request: Request) -> Result<Response> {
+        if !self.all_methods && !is_idempotent(request.method()) {
+            return self.next(&mut ctx, request);
+        }
+        let mut attempt = 0;
+        loop {
+            // This buffers the body so that it can be sent again
+            let next_request = clone_request(&mut request)?;
+            let (reason, retry_after) = match self.next(&mut ctx, next_request) {
+                Ok(response) if attempt < self.retries && is_transient(response.status()) => {
+                    (response.status().to_string(), retry_after(&response))
+                }
+                Err(err) if attempt < self.retries && is_transient_error(&err) => {
+                    (err.to_string(), None)
+                }
+                result => return result,
+            };
+            attempt += 1;
+            let delay = retry_after.unwrap_or_else(|| backoff(self.delay, attempt, rand::random()));
+            if self.verbose {
+                eprintln!(
+                    "{}: {}, retrying in {:.1}s (retry {} of {})",
+                    self.bin_name,
+                    reason,
+                    delay.as_secs_f64(),
+                    attempt,
+                    self.retries
+                );
+            }
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Whether sending a request twice has the same effect as sending it once,
+/// as defined in RFC 9110.
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    match status {
+        StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => true,
+        StatusCode::NOT_IMPLEMENTED | StatusCode::HTTP_VERSION_NOT_SUPPORTED => false,
+        status => status.is_server_error(),
+    }
+}
+
+fn is_transient_error(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<reqwest::Error>() {
+        Some(err) => err.is_connect() || err.is_timeout() || err.is_request(),
+        None => false,
+    }
+}
+
+/// Read a Retry-After header given in seconds. (It may also be a date, but
+/// servers rarely do that.)
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    Some(Duration::from_secs(seconds.trim().parse().ok()?).min(MAX_DELAY))
+}
+
+/// The delay before a retry: the base delay doubles with each attempt, and
+/// then a random jitter of up to half of it is subtracted so that many
+/// clients don't all retry at the same moment.
+///
+/// `jitter` should be a random number between 0 and 1.
+fn backoff(base: Duration, attempt: usize, jitter: f64) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16) as u32;
+    let delay = base.saturating_mul(2u32.pow(exponent)).min(MAX_DELAY);
+    delay.mul_f64(1.0 - jitter / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_backoff() {
+        let base = Duration::from_secs(1);
+        assert_eq!(backoff(base, 1, 0.0), Duration::from_secs(1));
+        assert_eq!(backoff(base, 2, 0.0), Duration::from_secs(2));
+        assert_eq!(backoff(base, 3, 0.0), Duration::from_secs(4));
+        assert_eq!(backoff(base, 3, 1.0), Duration::from_secs(2));
+        assert_eq!(backoff(base, 100, 0.0), MAX_DELAY);
+        assert_eq!(backoff(Duration::ZERO, 5, 0.5), Duration::ZERO);
+    }
+
+    #[test]
+    fn transient_statuses() {
+        assert!(is_transient(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_transient(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_transient(StatusCode::NOT_IMPLEMENTED));
+        assert!(!is_transient(StatusCode::NOT_FOUND));
+        assert!(!is_transient(StatusCode::OK));
+    }
+
+    #[test]
+    fn idempotent_methods() {
+        assert!(is_idempotent(&Method::GET));
+        assert!(is_idempotent(&Method::PUT));
+        assert!(is_idempotent(&Method::DELETE));
+        assert!(!is_idempotent(&Method::POST));
+        assert!(!is_idempotent(&Method::PATCH));
+        assert!(!is_idempotent(&Method::from_bytes(b"PURGE").unwrap()));
+    }
+}
@@ -56,8 +56,9 @@ struct LegacyCookie {
     secure: Option<bool>,
 }
 
+/// A cookie as it's stored in session files and JSON cookie jars.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-struct Cookie {
+pub struct Cookie {
     name: String,
     value: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -243,6 +244,27 @@ impl Session {
                     )))
                 }
                 "bearer" => Ok(Some(auth::Auth::Bearer(raw_auth.into()))),
+                "ntlm" => {
+                    let (username, password) = auth::parse_auth(raw_auth, "")?;
+                    Ok(Some(auth::Auth::Ntlm(
+                        username,
+                        password.unwrap_or_else(|| "".into()),
+                    )))
+                }
+                "oauth2" => {
+                    let (client_id, client_secret) = auth::parse_auth(raw_auth, "")?;
+                    Ok(Some(auth::Auth::OAuth2(
+                        client_id,
+                        client_secret.unwrap_or_else(|| "".into()),
+                    )))
+                }
+                "aws-sigv4" => {
+                    let (access_key, secret_key) = auth::parse_auth(raw_auth, "")?;
+                    Ok(Some(auth::Auth::AwsSigv4(
+                        access_key,
+                        secret_key.unwrap_or_else(|| "".into()),
+                    )))
+                }
                 _ => Err(anyhow!("Unknown auth type {}", raw_auth)),
             }
         } else {
@@ -271,42 +293,31 @@ impl Session {
                     raw_auth: Some(token.into()),
                 }
             }
+            auth::Auth::Ntlm(username, password) => {
+                self.content.auth = Auth {
+                    auth_type: Some("ntlm".into()),
+                    raw_auth: Some(format!("{}:{}", username, password)),
+                }
+            }
+            auth::Auth::OAuth2(client_id, client_secret) => {
+                self.content.auth = Auth {
+                    auth_type: Some("oauth2".into()),
+                    raw_auth: Some(format!("{}:{}", client_id, client_secret)),
+                }
+            }
+            auth::Auth::AwsSigv4(access_key, secret_key) => {
+                self.content.auth = Auth {
+                    auth_type: Some("aws-sigv4".into()),
+                    raw_auth: Some(format!("{}:{}", access_key, secret_key)),
+                }
+            }
         }
     }
 
     pub fn cookies(&self) -> impl Iterator<Item = Result<cookie_store::Cookie<'static>>> + '_ {
         match &self.content.cookies {
             Cookies::Map(_) => unreachable!(),
-            Cookies::List(cookies) => cookies.iter().map(|cookie| {
-                let mut cookie_builder =
-                    cookie_store::RawCookie::build(cookie.name.clone(), cookie.value.clone());
-
-                if let Some(expires) = cookie.expires {
-                    cookie_builder =
-                        cookie_builder.expires(time::OffsetDateTime::from_unix_timestamp(expires)?);
-                }
-                if let Some(path) = &cookie.path {
-                    cookie_builder = cookie_builder.path(path.clone());
-                }
-                if let Some(secure) = cookie.secure {
-                    cookie_builder = cookie_builder.secure(secure);
-                }
-
-                let mut cookie_url = self.url.clone();
-                if let Some(domain) = &cookie.domain {
-                    cookie_url = format!("http://{domain}").parse()?;
-                    // The cookie's domain attribute cannot be an IP address.
-                    // See https://stackoverflow.com/a/30676300/5915221
-                    if let Some(url::Host::Domain(_)) = cookie_url.host() {
-                        cookie_builder = cookie_builder.domain(domain.clone());
-                    }
-                }
-
-                Ok(cookie_store::Cookie::try_from_raw_cookie(
-                    &cookie_builder.finish(),
-                    &cookie_url,
-                )?)
-            }),
+            Cookies::List(cookies) => cookies.iter().map(|cookie| cookie.to_cookie(&self.url)),
         }
     }
 
@@ -320,25 +331,7 @@ impl Session {
         };
 
         session_cookies.clear();
-
-        for cookie in cookies {
-            let mut domain = cookie.domain();
-            if let cookie_store::CookieDomain::HostOnly(s) = &cookie.domain {
-                domain = Some(s);
-            }
-
-            session_cookies.push(Cookie {
-                name: cookie.name().into(),
-                value: cookie.value().into(),
-                expires: cookie
-                    .expires()
-                    .and_then(|v| v.datetime())
-                    .map(|v| v.unix_timestamp()),
-                path: cookie.path().map(Into::into),
-                secure: cookie.secure(),
-                domain: domain.map(Into::into),
-            });
-        }
+        session_cookies.extend(cookies.map(Cookie::from_cookie));
     }
 
     pub fn persist(&self) -> Result<()> {
@@ -356,6 +349,60 @@ impl Session {
     }
 }
 
+impl Cookie {
+    /// Convert to a cookie for the cookie store. `url` is used if the cookie
+    /// doesn't have a domain.
+    pub fn to_cookie(&self, url: &Url) -> Result<cookie_store::Cookie<'static>> {
+        let mut cookie_builder =
+            cookie_store::RawCookie::build(self.name.clone(), self.value.clone());
+
+        if let Some(expires) = self.expires {
+            cookie_builder =
+                cookie_builder.expires(time::OffsetDateTime::from_unix_timestamp(expires)?);
+        }
+        if let Some(path) = &self.path {
+            cookie_builder = cookie_builder.path(path.clone());
+        }
+        if let Some(secure) = self.secure {
+            cookie_builder = cookie_builder.secure(secure);
+        }
+
+        let mut cookie_url = url.clone();
+        if let Some(domain) = &self.domain {
+            cookie_url = format!("http://{domain}").parse()?;
+            // The cookie's domain attribute cannot be an IP address.
+            // See https://stackoverflow.com/a/30676300/5915221
+            if let Some(url::Host::Domain(_)) = cookie_url.host() {
+                cookie_builder = cookie_builder.domain(domain.clone());
+            }
+        }
+
+        Ok(cookie_store::Cookie::try_from_raw_cookie(
+            &cookie_builder.finish(),
+            &cookie_url,
+        )?)
+    }
+
+    pub fn from_cookie(cookie: &cookie_store::Cookie<'static>) -> Self {
+        let mut domain = cookie.domain();
+        if let cookie_store::CookieDomain::HostOnly(s) = &cookie.domain {
+            domain = Some(s);
+        }
+
+        Cookie {
+            name: cookie.name().into(),
+            value: cookie.value().into(),
+            expires: cookie
+                .expires()
+                .and_then(|v| v.datetime())
+                .map(|v| v.unix_timestamp()),
+            path: cookie.path().map(Into::into),
+            secure: cookie.secure(),
+            domain: domain.map(Into::into),
+        }
+    }
+}
+
 fn xh_version() -> String {
     if test_mode() {
         "0.0.0".into()
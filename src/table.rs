@@ -0,0 +1,139 @@
+//! Rendering CSV and TSV bodies as aligned tables.
+
+use std::io::{self, Write};
+
+use termcolor::{ColorSpec, WriteColor};
+
+/// Cells wider than this are cut off.
+const MAX_COLUMN_WIDTH: usize = 40;
+
+/// Split CSV text into rows of cells, following RFC 4180: cells may be
+/// quoted, and quoted cells may contain the delimiter, newlines and
+/// doubled quotes.
+pub fn parse_csv(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    cell.push('"');
+                }
+                '"' => in_quotes = false,
+                c => cell.push(c),
+            }
+        } else {
+            match c {
+                '"' if cell.is_empty() => in_quotes = true,
+                c if c == delimiter => row.push(std::mem::take(&mut cell)),
+                '\r' if chars.peek() == Some(&'\n') => (),
+                '\n' => {
+                    row.push(std::mem::take(&mut cell));
+                    rows.push(std::mem::take(&mut row));
+                }
+                c => cell.push(c),
+            }
+        }
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Split TSV text into rows of cells. TSV has no quoting.
+pub fn parse_tsv(text: &str) -> Vec<Vec<String>> {
+    text.lines()
+        .map(|line| line.split('\t').map(String::from).collect())
+        .collect()
+}
+
+/// Write rows as a table with aligned columns, treating the first row as
+/// the header.
+pub fn write_table(out: &mut impl WriteColor, rows: &[Vec<String>], color: bool) -> io::Result<()> {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| clean_cell(cell)).collect())
+        .collect();
+    let column_count = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; column_count];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut header_color = ColorSpec::new();
+    header_color.set_bold(true);
+
+    for (i, row) in rows.iter().enumerate() {
+        if i == 0 && color {
+            out.set_color(&header_color)?;
+        }
+        write_row(out, row, &widths)?;
+        if i == 0 {
+            if color {
+                out.reset()?;
+            }
+            writeln!(out)?;
+            let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+            write_row(out, &rule, &widths)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn write_row(out: &mut impl Write, row: &[String], widths: &[usize]) -> io::Result<()> {
+    for (j, cell) in row.iter().enumerate() {
+        if j > 0 {
+            write!(out, "  ")?;
+        }
+        if j + 1 == row.len() {
+            // Don't leave trailing whitespace
+            write!(out, "{}", cell)?;
+        } else {
+            let padding = widths[j] - cell.chars().count();
+            write!(out, "{}{:padding$}", cell, "", padding = padding)?;
+        }
+    }
+    Ok(())
+}
+
+/// Put a cell on a single line and cut it off if it's too wide.
+fn clean_cell(cell: &str) -> String {
+    let cell = cell.replace(['\r', '\n', '\t'], " ");
+    if cell.chars().count() > MAX_COLUMN_WIDTH {
+        let mut cell: String = cell.chars().take(MAX_COLUMN_WIDTH - 1).collect();
+        cell.push('…');
+        cell
+    } else {
+        cell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_csv("a,b\r\n\"x, \"\"y\"\"\",\"multi\nline\"\n1,\n", ','),
+            [
+                vec!["a", "b"],
+                vec!["x, \"y\"", "multi\nline"],
+                vec!["1", ""]
+            ]
+        );
+        assert_eq!(parse_csv("a;b", ';'), [vec!["a", "b"]]);
+        assert!(parse_csv("", ',').is_empty());
+        assert_eq!(parse_tsv("a\tb\n1\t2\n"), [vec!["a", "b"], vec!["1", "2"]]);
+    }
+}
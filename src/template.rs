@@ -0,0 +1,161 @@
+//! Request templates for --template.
+//!
+//! A template looks like an HTTP request, with `{{name}}` placeholders that
+//! are filled in from --var:
+//!
+//! ```text
+//! # Lines starting with # are comments
+//! POST https://{{env}}.example.com/items/{{id}}
+//! Authorization: Bearer {{token}}
+//!
+//! {"id": {{id}}}
+//! ```
+//!
+//! The method is optional. Everything after the first blank line is the body.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use regex_lite::{Captures, Regex};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Template {
+    pub method: Option<String>,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+/// Read a template file and fill in its placeholders.
+pub fn load(path: &Path, vars: &[(String, String)]) -> Result<Template> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to read template {:?}", path))?;
+    parse(&substitute(&text, vars)?)
+}
+
+/// Replace `{{name}}` placeholders with the values of variables.
+fn substitute(text: &str, vars: &[(String, String)]) -> Result<String> {
+    let placeholder = Regex::new(r"\{\{\s*([A-Za-z0-9_.-]+)\s*\}\}").unwrap();
+    let mut missing = None;
+    let text = placeholder.replace_all(text, |caps: &Captures| {
+        let name = &caps[1];
+        // Later definitions win, like other repeatable options
+        match vars.iter().rev().find(|(key, _)| key == name) {
+            Some((_, value)) => value.clone(),
+            None => {
+                missing.get_or_insert_with(|| name.to_string());
+                String::new()
+            }
+        }
+    });
+    match missing {
+        Some(name) => Err(anyhow!(
+            "Template variable {:?} is not defined, pass it with --var {}=VALUE",
+            name,
+            name
+        )),
+        None => Ok(text.into_owned()),
+    }
+}
+
+fn parse(text: &str) -> Result<Template> {
+    let mut lines = text.lines();
+
+    let request_line = lines
+        .by_ref()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| anyhow!("Template has no request line"))?;
+    let (method, url) = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        [url] => (None, url),
+        [method, url] => (Some(method.to_string()), url),
+        _ => {
+            return Err(anyhow!(
+                "Invalid request line in template: {:?}",
+                request_line
+            ))
+        }
+    };
+
+    let mut headers = Vec::new();
+    for line in lines.by_ref() {
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid header in template: {:?}", line))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let body = lines.collect::<Vec<_>>().join("\n");
+    let body = body.trim_end_matches(['\r', '\n']);
+
+    Ok(Template {
+        method,
+        url: url.to_string(),
+        headers,
+        body: (!body.trim().is_empty()).then(|| body.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_template() {
+        let text = "\
+# Create an item
+POST https://{{ env }}.example.com/items/{{id}}
+Authorization: Bearer {{token}}
+# x-debug: 1
+Content-Type: application/json
+
+{\"id\": {{id}}}
+";
+        let vars = vars(&[("env", "prod"), ("id", "1"), ("id", "42"), ("token", "abc")]);
+        assert_eq!(
+            parse(&substitute(text, &vars).unwrap()).unwrap(),
+            Template {
+                method: Some("POST".into()),
+                url: "https://prod.example.com/items/42".into(),
+                headers: vec![
+                    ("Authorization".into(), "Bearer abc".into()),
+                    ("Content-Type".into(), "application/json".into()),
+                ],
+                body: Some("{\"id\": 42}".into()),
+            }
+        );
+
+        assert_eq!(
+            parse("example.org\n").unwrap(),
+            Template {
+                method: None,
+                url: "example.org".into(),
+                headers: vec![],
+                body: None,
+            }
+        );
+    }
+
+    #[test]
+    fn template_errors() {
+        let err = substitute("GET {{host}}/{{path}}", &vars(&[("host", "x")])).unwrap_err();
+        assert!(err.to_string().contains("\"path\" is not defined"));
+        assert!(parse("# nothing here\n").is_err());
+        assert!(parse("GET example.org HTTP/1.1\n").is_err());
+        assert!(parse("GET example.org\nno colon\n").is_err());
+    }
+}
@@ -4,10 +4,17 @@ use anyhow::{anyhow, Context, Result};
 use os_display::Quotable;
 use reqwest::{tls, Method};
 use std::ffi::OsString;
+use std::fs;
 
 use crate::cli::{AuthType, Cli, HttpVersion, Verify};
-use crate::request_items::{Body, RequestItem, FORM_CONTENT_TYPE, JSON_ACCEPT, JSON_CONTENT_TYPE};
+use crate::dns::DnsServer;
+use crate::insecure_hosts::is_insecure_host;
+use crate::request_items::{
+    command_output, read_base64, Body, RequestItem, FORM_CONTENT_TYPE, JSON_ACCEPT,
+    JSON_CONTENT_TYPE,
+};
 use crate::utils::url_with_query;
+use crate::yaml;
 
 pub fn print_curl_translation(args: Cli) -> Result<()> {
     let cmd = translate(args)?;
@@ -80,6 +87,16 @@ impl std::fmt::Display for Command {
     }
 }
 
+fn curl_tls_version(version: tls::Version) -> &'static str {
+    match version {
+        tls::Version::TLS_1_0 => "1.0",
+        tls::Version::TLS_1_1 => "1.1",
+        tls::Version::TLS_1_2 => "1.2",
+        tls::Version::TLS_1_3 => "1.3",
+        _ => unreachable!(),
+    }
+}
+
 pub fn translate(args: Cli) -> Result<Command> {
     let (headers, headers_to_unset) = args.request_items.headers()?;
 
@@ -93,10 +110,12 @@ pub fn translate(args: Cli) -> Result<Command> {
         // No straightforward equivalent
         (args.print.is_some(), "-p/--print"),
         // No equivalent, -s/--silent suppresses other stuff
-        (args.quiet, "-q/--quiet"),
+        (args.quiet > 0, "-q/--quiet"),
         // No equivalent
         (args.pretty.is_some(), "--pretty"),
         // No equivalent
+        (args.color.is_some(), "--color"),
+        // No equivalent
         (args.style.is_some(), "-s/--style"),
         // No equivalent
         (args.response_charset.is_some(), "--response-charset"),
@@ -111,6 +130,30 @@ pub fn translate(args: Cli) -> Result<Command> {
         // Already the default (usually, depends on compile time options)
         // Unclear if you can even change this at runtime
         (args.native_tls, "--native-tls"),
+        // No equivalent
+        (args.checksum.is_some(), "--checksum"),
+        // No equivalent
+        (args.har.is_some(), "--har"),
+        // No equivalent
+        (args.history, "--history"),
+        // No equivalent
+        (args.dump_header_append, "--dump-header-append"),
+        // No equivalent
+        (args.bench.is_some(), "--bench"),
+        // curl's --parallel is for different URLs
+        (args.concurrency.is_some(), "--concurrency"),
+        // No equivalent
+        (args.filter.is_some(), "--filter"),
+        // No equivalent, curl can only print or save the body
+        (args.tee.is_some(), "--tee"),
+        // No equivalent
+        (args.proto.is_some(), "--proto"),
+        // No equivalent
+        (args.pager == Some(true), "--pager"),
+        // No equivalent, curl always picks its own boundary
+        (args.boundary.is_some(), "--boundary"),
+        // No equivalent
+        (args.compress.is_some(), "-x/--compress"),
     ];
 
     for (present, flag) in ignored {
@@ -127,6 +170,8 @@ pub fn translate(args: Cli) -> Result<Command> {
     // - .ignore_stdin: assumed by default
     //   (to send stdin, --data-binary @- -H 'Content-Type: application/octet-stream')
     // - .curl and .curl_long: you are here
+    // - .allow_commands: the commands' output is put in the curl command
+    // - .retry_all_methods: curl's --retry doesn't check the method
 
     // Output options
     if args.verbose > 0 {
@@ -153,12 +198,30 @@ pub fn translate(args: Cli) -> Result<Command> {
         cmd.arg("--max-redirs");
         cmd.arg(num.to_string());
     }
+    if args.preserve_method {
+        cmd.arg("--post301");
+        cmd.arg("--post302");
+    }
+    if args.trust_redirects {
+        cmd.arg("--location-trusted");
+    }
+    if args.no_clobber {
+        cmd.arg("--no-clobber");
+    }
     if let Some(filename) = args.output {
         let filename = filename.to_str().ok_or_else(|| anyhow!("Invalid UTF-8"))?;
         cmd.opt("-o", "--output");
         cmd.arg(filename);
     } else if args.download {
         cmd.opt("-O", "--remote-name");
+        if let Some(dir) = args.output_dir {
+            cmd.arg("--output-dir");
+            cmd.arg(dir.to_str().ok_or_else(|| anyhow!("Invalid UTF-8"))?);
+        }
+    }
+    if let Some(filename) = args.dump_header {
+        cmd.opt("-D", "--dump-header");
+        cmd.arg(filename.to_str().ok_or_else(|| anyhow!("Invalid UTF-8"))?);
     }
     if args.resume {
         cmd.opt("-C", "--continue-at");
@@ -166,12 +229,23 @@ pub fn translate(args: Cli) -> Result<Command> {
     }
     match args.verify.unwrap_or(Verify::Yes) {
         Verify::CustomCaBundle(filename) => {
-            cmd.arg("--cacert");
+            if filename.is_dir() {
+                cmd.arg("--capath");
+            } else {
+                cmd.arg("--cacert");
+            }
             cmd.arg(filename);
+            if args.ca_merge {
+                cmd.warn("curl has no equivalent of --ca-merge.");
+            }
         }
         Verify::No => {
             cmd.opt("-k", "--insecure");
         }
+        Verify::Yes if is_insecure_host(&args.url, &args.insecure_host) => {
+            // curl can't limit this to a single host
+            cmd.opt("-k", "--insecure");
+        }
         Verify::Yes => {}
     }
     if let Some(cert) = args.cert {
@@ -182,6 +256,19 @@ pub fn translate(args: Cli) -> Result<Command> {
         cmd.arg("--key");
         cmd.arg(keyfile);
     }
+    if let Some(pins) = args.pinnedpubkey {
+        cmd.arg("--pinnedpubkey");
+        cmd.arg(pins.to_string());
+    }
+    let tls_min: Option<tls::Version> = args.tls_min.and_then(Into::into);
+    if let Some(tls_min) = tls_min {
+        cmd.arg(format!("--tlsv{}", curl_tls_version(tls_min)));
+    }
+    let tls_max: Option<tls::Version> = args.tls_max.and_then(Into::into);
+    if let Some(tls_max) = tls_max {
+        cmd.arg("--tls-max");
+        cmd.arg(curl_tls_version(tls_max));
+    }
     if let Some(tls_version) = args.ssl.and_then(Into::into) {
         match tls_version {
             tls::Version::TLS_1_0 => {
@@ -207,6 +294,7 @@ pub fn translate(args: Cli) -> Result<Command> {
             _ => unreachable!(),
         }
     }
+    let ignore_env_proxy = args.ignore_env_proxy && args.proxy.is_empty();
     for proxy in args.proxy {
         match proxy {
             crate::cli::Proxy::All(proxy) => {
@@ -222,20 +310,78 @@ pub fn translate(args: Cli) -> Result<Command> {
             }
         }
     }
+    if ignore_env_proxy {
+        cmd.arg("--noproxy");
+        cmd.arg("*");
+    }
+    if let Some(proxy_auth) = args.proxy_auth {
+        cmd.opt("-U", "--proxy-user");
+        cmd.arg(proxy_auth);
+    }
     if let Some(timeout) = args.timeout.and_then(|t| t.as_duration()) {
         cmd.arg("--max-time");
         cmd.arg(timeout.as_secs_f64().to_string());
     }
+    if let Some(timeout) = args.connect_timeout.and_then(|t| t.as_duration()) {
+        cmd.arg("--connect-timeout");
+        cmd.arg(timeout.as_secs_f64().to_string());
+    }
+    if let Some(timeout) = args.read_timeout.and_then(|t| t.as_duration()) {
+        // Give up if less than a byte per second arrives for that long
+        cmd.opt("-Y", "--speed-limit");
+        cmd.arg("1");
+        cmd.opt("-y", "--speed-time");
+        cmd.arg(timeout.as_secs().max(1).to_string());
+    }
+    if let Some(max_size) = args.max_response_size {
+        // curl checks the size before decompression
+        cmd.arg("--max-filesize");
+        cmd.arg(max_size.to_string());
+    }
+    if args.chunked {
+        cmd.header("transfer-encoding", "chunked");
+    }
+    if let Some(rate) = args.limit_rate {
+        cmd.arg("--limit-rate");
+        cmd.arg(rate.to_string());
+    }
+    for cookies in &args.cookies {
+        // curl reads a file if there's no "="
+        cmd.opt("-b", "--cookie");
+        cmd.arg(cookies.strip_prefix('@').unwrap_or(cookies));
+    }
+    if let Some(retry) = args.retry {
+        cmd.arg("--retry");
+        cmd.arg(retry.to_string());
+    }
+    if let Some(retry_delay) = args.retry_delay {
+        // curl only takes whole seconds, and stops backing off when given a delay
+        cmd.arg("--retry-delay");
+        cmd.arg(retry_delay.as_secs_f64().ceil().to_string());
+    }
     if let Some(http_version) = args.http_version {
         match http_version {
             HttpVersion::Http10 => cmd.arg("--http1.0"),
             HttpVersion::Http11 => cmd.arg("--http1.1"),
             HttpVersion::Http2 => cmd.arg("--http2"),
             HttpVersion::Http2PriorKnowledge => cmd.arg("--http2-prior-knowledge"),
+            HttpVersion::Http3 => cmd.arg("--http3-only"),
+            HttpVersion::Auto => {}
         }
     }
 
-    if args.method == Some(Method::HEAD) {
+    let has_body = args.raw.is_some() || !args.request_items.is_body_empty();
+
+    if args.method == Some(Method::HEAD) && has_body {
+        // -I/--head can't be combined with sending data
+        cmd.opt("-X", "--request");
+        cmd.arg("HEAD");
+        cmd.warn(
+            "curl will wait for a response body after a HEAD request with -X/--request. \
+            Consider adding -m/--max-time."
+                .to_string(),
+        );
+    } else if args.method == Some(Method::HEAD) {
         cmd.opt("-I", "--head");
     } else if args.method == Some(Method::OPTIONS) {
         // If you're sending an OPTIONS you almost certainly want to see the headers
@@ -256,7 +402,7 @@ pub fn translate(args: Cli) -> Result<Command> {
         cmd.opt("-I", "--head");
         cmd.opt("-X", "--request");
         cmd.arg(method.to_string());
-        if method != Method::GET {
+        if has_body {
             cmd.warn(
                 "-I/--head is incompatible with sending data. Consider omitting -h/--headers."
                     .to_string(),
@@ -290,16 +436,29 @@ pub fn translate(args: Cli) -> Result<Command> {
         cmd.arg(interface);
     };
 
-    if !args.resolve.is_empty() {
-        let port = url
-            .port_or_known_default()
-            .with_context(|| format!("Unsupported URL scheme: '{}'", url.scheme()))?;
-
-        cmd.warn("Inferred port number in --resolve from request URL.");
-        for resolve in args.resolve {
-            cmd.arg("--resolve");
-            cmd.arg(format!("{}:{}:{}", resolve.domain, port, resolve.addr));
+    match args.dns_server {
+        Some(DnsServer::Udp(addr)) => {
+            cmd.arg("--dns-servers");
+            cmd.arg(addr.to_string());
+        }
+        Some(DnsServer::Https(url)) => {
+            cmd.arg("--doh-url");
+            cmd.arg(url.to_string());
         }
+        None => {}
+    }
+    if args.resolve.iter().any(|resolve| resolve.port.is_none()) {
+        cmd.warn("Inferred port number in --resolve from request URL.");
+    }
+    for resolve in args.resolve {
+        let port = match resolve.port {
+            Some(port) => port,
+            None => url
+                .port_or_known_default()
+                .with_context(|| format!("Unsupported URL scheme: '{}'", url.scheme()))?,
+        };
+        cmd.arg("--resolve");
+        cmd.arg(format!("{}:{}:{}", resolve.domain, port, resolve.addr));
     }
 
     // Payload
@@ -319,6 +478,10 @@ pub fn translate(args: Cli) -> Result<Command> {
         // Already the default, so a bit questionable
         cmd.arg("--no-netrc");
     }
+    if let Some(netrc_file) = args.netrc_file {
+        cmd.arg("--netrc-file");
+        cmd.arg(netrc_file);
+    }
     if let Some(auth) = args.auth {
         match args.auth_type.unwrap_or_default() {
             AuthType::Basic => {
@@ -337,10 +500,31 @@ pub fn translate(args: Cli) -> Result<Command> {
                 cmd.arg("--oauth2-bearer");
                 cmd.arg(auth);
             }
+            AuthType::Ntlm => {
+                cmd.arg("--ntlm");
+                cmd.opt("-u", "--user");
+                cmd.arg(auth);
+            }
+            AuthType::OAuth2 => {
+                cmd.warn("curl can't request OAuth 2.0 tokens, use --oauth2-bearer with an access token instead.");
+            }
+            AuthType::AwsSigv4 => {
+                // curl derives the region and service from the hostname as well
+                cmd.arg("--aws-sigv4");
+                cmd.arg(match &args.aws_sigv4 {
+                    Some(scope) => scope.to_string(),
+                    None => "aws:amz".to_string(),
+                });
+                cmd.opt("-u", "--user");
+                cmd.arg(auth);
+            }
         }
     }
 
-    if let Some(raw) = args.raw {
+    if let Some(mut raw) = args.raw {
+        if args.yaml {
+            raw = yaml::yaml_to_json(&raw)?.to_string();
+        }
         if args.form {
             cmd.header("content-type", FORM_CONTENT_TYPE);
         } else {
@@ -355,7 +539,9 @@ pub fn translate(args: Cli) -> Result<Command> {
         // form after construction and we don't want to actually read the files
         for item in args.request_items.items {
             match item {
-                RequestItem::JsonField(..) | RequestItem::JsonFieldFromFile(..) => {
+                RequestItem::JsonField(..)
+                | RequestItem::JsonFieldFromFile(..)
+                | RequestItem::JsonFieldFromCommand(..) => {
                     return Err(anyhow!("JSON values are not supported in multipart fields"));
                 }
                 RequestItem::DataField { key, value, .. } => {
@@ -366,6 +552,14 @@ pub fn translate(args: Cli) -> Result<Command> {
                     cmd.opt("-F", "--form");
                     cmd.arg(format!("{}=<{}", key, value));
                 }
+                RequestItem::DataFieldFromCommand { key, value, .. } => {
+                    cmd.opt("-F", "--form");
+                    cmd.arg(format!("{}={}", key, command_output(&value)?));
+                }
+                RequestItem::Base64FieldFromFile { key, value, .. } => {
+                    cmd.opt("-F", "--form");
+                    cmd.arg(format!("{}={}", key, read_base64(&value)?));
+                }
                 RequestItem::FormFile {
                     key,
                     file_name,
@@ -386,9 +580,11 @@ pub fn translate(args: Cli) -> Result<Command> {
                 }
                 RequestItem::HttpHeader(..) => {}
                 RequestItem::HttpHeaderFromFile(..) => {}
+                RequestItem::HttpHeaderFromCommand(..) => {}
                 RequestItem::HttpHeaderToUnset(..) => {}
                 RequestItem::UrlParam(..) => {}
                 RequestItem::UrlParamFromFile(..) => {}
+                RequestItem::UrlParamFromCommand(..) => {}
             }
         }
     } else {
@@ -424,7 +620,15 @@ pub fn translate(args: Cli) -> Result<Command> {
             }
             Body::Json(..) => {}
             Body::Multipart { .. } => unreachable!(),
-            Body::Raw(..) => unreachable!(),
+            Body::MultipartWithBoundary { .. } => unreachable!(),
+            Body::Raw(..) | Body::Stdin => unreachable!(),
+            Body::File { file_name, .. } if args.yaml => {
+                cmd.header("content-type", JSON_CONTENT_TYPE);
+                cmd.header("accept", JSON_ACCEPT);
+                let text = fs::read_to_string(file_name)?;
+                cmd.opt("-d", "--data");
+                cmd.arg(yaml::yaml_to_json(&text)?.to_string());
+            }
             Body::File {
                 file_name,
                 file_type,
@@ -455,6 +659,54 @@ mod tests {
             ("xh httpbin.org/get", "curl http://httpbin.org/get"),
             ("xh httpbin.org/get -4", "curl http://httpbin.org/get -4"),
             ("xh httpbin.org/get -6", "curl http://httpbin.org/get -6"),
+            (
+                "xh httpbin.org/get --connect-timeout=2.5 --read-timeout=10",
+                "curl --connect-timeout 2.5 -Y 1 -y 10 http://httpbin.org/get",
+            ),
+            (
+                "xh httpbin.org/get --proxy=all:http://proxy.test --proxy-auth=user:pass",
+                "curl -x http://proxy.test/ -U user:pass http://httpbin.org/get",
+            ),
+            (
+                "xh httpbin.org/get --resolve=httpbin.org:80:127.0.0.1",
+                "curl http://httpbin.org/get --resolve httpbin.org:80:127.0.0.1",
+            ),
+            (
+                "xh httpbin.org/get --dns-server=1.1.1.1",
+                "curl http://httpbin.org/get --dns-servers 1.1.1.1:53",
+            ),
+            (
+                "xh https://httpbin.org/get --insecure-host=httpbin.org",
+                "curl -k https://httpbin.org/get",
+            ),
+            (
+                "xh https://example.org/get --insecure-host=httpbin.org",
+                "curl https://example.org/get",
+            ),
+            (
+                "xh https://httpbin.org/get --tls-min=tls1.2 --tls-max=tls1.3",
+                "curl --tlsv1.2 --tls-max 1.3 https://httpbin.org/get",
+            ),
+            (
+                "xh httpbin.org/get --max-response-size=1M",
+                "curl --max-filesize 1048576 http://httpbin.org/get",
+            ),
+            (
+                "xh httpbin.org/get --chunked",
+                "curl -H 'transfer-encoding: chunked' http://httpbin.org/get",
+            ),
+            (
+                "xh https://storage.test/bucket --aws-sigv4=aws:amz:eu-central-1:s3 -a key:secret",
+                "curl https://storage.test/bucket --aws-sigv4 aws:amz:eu-central-1:s3 -u key:secret",
+            ),
+            (
+                "xh httpbin.org/get --limit-rate=500k",
+                "curl --limit-rate 512000 http://httpbin.org/get",
+            ),
+            (
+                "xh httpbin.org/get --cookie a=1;b=2 --cookie @cookies.txt",
+                "curl -b 'a=1;b=2' -b cookies.txt http://httpbin.org/get",
+            ),
             (
                 "xh httpbin.org/post x=3",
                 #[cfg(not(windows))]
@@ -462,6 +714,20 @@ mod tests {
                 #[cfg(windows)]
                 r#"curl http://httpbin.org/post -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5' -d '{\"x\":\"3\"}'"#,
             ),
+            (
+                "xh httpbin.org/post --graphql={me} --graphql-var=id:=1",
+                #[cfg(not(windows))]
+                r#"curl http://httpbin.org/post -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5' -d '{"query":"{me}","variables":{"id":1}}'"#,
+                #[cfg(windows)]
+                r#"curl http://httpbin.org/post -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5' -d '{\"query\":\"{me}\",\"variables\":{\"id\":1}}'"#,
+            ),
+            (
+                "xh head httpbin.org/anything x=3",
+                #[cfg(not(windows))]
+                r#"curl -X HEAD http://httpbin.org/anything -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5' -d '{"x":"3"}'"#,
+                #[cfg(windows)]
+                r#"curl -X HEAD http://httpbin.org/anything -H 'content-type: application/json' -H 'accept: application/json, */*;q=0.5' -d '{\"x\":\"3\"}'"#,
+            ),
             (
                 "xh --form httpbin.org/post x\\=y=z=w",
                 "curl http://httpbin.org/post --data-urlencode 'x%3Dy=z=w'",
@@ -525,10 +791,26 @@ mod tests {
                 "xh -d httpbin.org/get",
                 "curl -f -L -O http://httpbin.org/get",
             ),
+            (
+                "xh -d --output-dir files httpbin.org/get",
+                "curl -f -L -O --output-dir files http://httpbin.org/get",
+            ),
+            (
+                "xh -d --no-clobber -o foobar httpbin.org/get",
+                "curl -f -L --no-clobber -o foobar http://httpbin.org/get",
+            ),
+            (
+                "xh -d -o files/ httpbin.org/get",
+                "curl -f -L -O --output-dir files/ http://httpbin.org/get",
+            ),
             (
                 "xh -d -o foobar --continue httpbin.org/get",
                 "curl -f -L -o foobar -C - http://httpbin.org/get",
             ),
+            (
+                "xh --dump-header headers.txt httpbin.org/get",
+                "curl -D headers.txt http://httpbin.org/get",
+            ),
             (
                 "xh --curl-long -d -o foobar --continue httpbin.org/get",
                 "curl --fail --location --output foobar --continue-at - http://httpbin.org/get",
@@ -1,10 +1,14 @@
 use std::borrow::Cow;
 use std::env::var_os;
-use std::io::{self, Write};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
-use reqwest::blocking::Request;
+use flate2::{write::GzEncoder, Compression};
+use reqwest::blocking::{Body, Request};
+use reqwest::header::{HeaderValue, CONTENT_ENCODING};
+use serde_json::{Number, Value};
 use url::Url;
 
 pub fn unescape(text: &str, special_chars: &'static str) -> String {
@@ -34,6 +38,64 @@ pub fn unescape(text: &str, special_chars: &'static str) -> String {
     out
 }
 
+/// Decode %XX escapes. Invalid escapes are kept as they are.
+pub fn percent_decode(input: &str) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex_byte = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex_byte) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Turn a float into JSON. JSON has no NaN or infinity, so those become strings,
+/// as in the proto3 JSON mapping.
+pub fn json_float(float: f64) -> Value {
+    match Number::from_f64(float) {
+        Some(number) => Value::Number(number),
+        None if float.is_nan() => Value::String("NaN".to_string()),
+        None if float > 0.0 => Value::String("Infinity".to_string()),
+        None => Value::String("-Infinity".to_string()),
+    }
+}
+
+/// Like [`json_float`], but going through a string so that e.g. 0.1 doesn't
+/// turn into 0.10000000149011612.
+pub fn json_f32(float: f32) -> Value {
+    json_float(float.to_string().parse().unwrap_or(f64::from(float)))
+}
+
+/// Capitalize a header name the way it's sent over HTTP/1.x.
+pub fn title_case_header(name: &str) -> String {
+    // Ought to be equivalent to how hyper does it
+    // https://github.com/hyperium/hyper/blob/f46b175bf71b202fbb907c4970b5743881b891e1/src/proto/h1/role.rs#L1332
+    // Header names are ASCII so it's ok to operate on char instead of u8
+    let mut title_case = String::with_capacity(name.len());
+    let mut prev = '-';
+    for mut c in name.chars() {
+        if prev == '-' {
+            c.make_ascii_uppercase();
+        }
+        title_case.push(c);
+        prev = c;
+    }
+    title_case
+}
+
 pub fn clone_request(request: &mut Request) -> Result<Request> {
     if let Some(b) = request.body_mut().as_mut() {
         b.buffer()?;
@@ -43,6 +105,100 @@ pub fn clone_request(request: &mut Request) -> Result<Request> {
     Ok(request.try_clone().unwrap()) // guaranteed to not fail if body is already buffered
 }
 
+/// Standard input as a request body.
+///
+/// Pipes are streamed, which means chunked encoding, unless `buffer` is set.
+/// Regular files are streamed with their size unless `chunked` is set.
+pub fn stdin_body(buffer: bool, chunked: bool) -> io::Result<Body> {
+    if buffer {
+        let mut buffer = Vec::new();
+        io::stdin().read_to_end(&mut buffer)?;
+        return Ok(buffer.into());
+    }
+    Ok(match stdin_file_size() {
+        Some(len) if !chunked => Body::sized(io::stdin(), len),
+        _ => Body::new(io::stdin()),
+    })
+}
+
+/// The size of standard input, if it's a regular file rather than a pipe.
+fn stdin_file_size() -> Option<u64> {
+    #[cfg(unix)]
+    let handle = {
+        use std::os::fd::AsFd;
+        io::stdin().as_fd().try_clone_to_owned()
+    };
+    #[cfg(windows)]
+    let handle = {
+        use std::os::windows::io::AsHandle;
+        io::stdin().as_handle().try_clone_to_owned()
+    };
+    let metadata = File::from(handle.ok()?).metadata().ok()?;
+    metadata.is_file().then_some(metadata.len())
+}
+
+/// Gzip the request body and set Content-Encoding to match.
+///
+/// Bodies that are empty or already have a Content-Encoding are left alone.
+pub fn compress_request(request: &mut Request, level: u32) -> Result<()> {
+    if request.headers().contains_key(CONTENT_ENCODING) {
+        return Ok(());
+    }
+    let compressed = match request.body_mut() {
+        Some(body) => {
+            let body = body.buffer()?;
+            if body.is_empty() {
+                return Ok(());
+            }
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level));
+            encoder.write_all(body)?;
+            encoder.finish()?
+        }
+        None => return Ok(()),
+    };
+    *request.body_mut() = Some(compressed.into());
+    request
+        .headers_mut()
+        .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    Ok(())
+}
+
+/// A reader that fails once more than `limit` bytes have been read from it,
+/// for --max-response-size.
+pub struct SizeLimit<R> {
+    inner: R,
+    limit: Option<u64>,
+    count: u64,
+}
+
+impl<R: Read> SizeLimit<R> {
+    pub fn new(inner: R, limit: Option<u64>) -> Self {
+        SizeLimit {
+            inner,
+            limit,
+            count: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for SizeLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        match self.limit {
+            Some(limit) if self.count > limit => Err(response_too_large(limit)),
+            _ => Ok(n),
+        }
+    }
+}
+
+pub fn response_too_large(limit: u64) -> io::Error {
+    io::Error::other(format!(
+        "Response body is larger than --max-response-size ({} bytes)",
+        limit
+    ))
+}
+
 /// Whether to make some things more deterministic for the benefit of tests
 pub fn test_mode() -> bool {
     // In integration tests the binary isn't compiled with cfg(test), so we
@@ -120,6 +276,29 @@ pub fn expand_tilde(path: impl AsRef<Path>) -> PathBuf {
     }
 }
 
+/// Read a CA bundle file, or every certificate file in a directory.
+pub fn read_ca_bundle(path: &Path) -> io::Result<Vec<u8>> {
+    if !path.is_dir() {
+        return fs::read(path);
+    }
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if matches!(extension, Some("pem" | "crt" | "cer")) && path.is_file() {
+            paths.push(path);
+        }
+    }
+    // Keep the order predictable
+    paths.sort();
+    let mut buffer = Vec::new();
+    for path in paths {
+        buffer.extend(fs::read(path)?);
+        buffer.push(b'\n');
+    }
+    Ok(buffer)
+}
+
 pub fn url_with_query(mut url: Url, query: &[(&str, Cow<str>)]) -> Url {
     if !query.is_empty() {
         // If we run this even without adding pairs it adds a `?`, hence
@@ -0,0 +1,221 @@
+use std::io::{self, BufRead, IsTerminal};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+use url::Url;
+
+use crate::auth::Auth;
+use crate::buffer::Buffer;
+use crate::cli::{Cli, FormatOptions, Print};
+use crate::printer::Printer;
+use crate::utils::test_pretend_term;
+
+/// How long to block on the socket before checking stdin for new messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub fn is_websocket_url(url: &Url) -> bool {
+    matches!(url.scheme(), "ws" | "wss")
+}
+
+/// Connect to a WebSocket server, send each line from stdin as a text message
+/// and print incoming messages until either side closes the connection.
+pub fn run(args: &Cli, url: Url, headers: HeaderMap) -> Result<i32> {
+    if args.offline {
+        return Err(anyhow!("WebSocket URLs cannot be used with --offline"));
+    }
+    if args.raw.is_some() || !args.request_items.is_body_empty() {
+        return Err(anyhow!(
+            "Request data (key=value) cannot be sent to a WebSocket URL. \
+            Write messages to standard input instead."
+        ));
+    }
+    // The connection is made by tungstenite rather than reqwest, so these
+    // would otherwise be ignored
+    let unsupported = [
+        ("--verify", args.verify.is_some()),
+        ("--cert", args.cert.is_some()),
+        ("--cert-key", args.cert_key.is_some()),
+        ("--insecure-host", !args.insecure_host.is_empty()),
+        ("--pinnedpubkey", args.pinnedpubkey.is_some()),
+        ("--proxy", !args.proxy.is_empty()),
+        ("--timeout", args.timeout.is_some()),
+        ("--connect-timeout", args.connect_timeout.is_some()),
+        ("--read-timeout", args.read_timeout.is_some()),
+        ("--resolve", !args.resolve.is_empty()),
+        ("--dns-server", args.dns_server.is_some()),
+        ("--interface", args.interface.is_some()),
+        ("--ipv4", args.ipv4),
+        ("--ipv6", args.ipv6),
+        ("--ssl", args.ssl.is_some()),
+        ("--tls-min", args.tls_min.is_some()),
+        ("--tls-max", args.tls_max.is_some()),
+        ("--native-tls", args.native_tls),
+        ("--session", args.session.is_some()),
+        ("--session-read-only", args.session_read_only.is_some()),
+        ("--cookie-jar", args.cookie_jar.is_some()),
+        ("--har", args.har.is_some()),
+        ("--aws-sigv4", args.aws_sigv4.is_some()),
+    ];
+    if let Some((option, _)) = unsupported.iter().find(|(_, used)| *used) {
+        return Err(anyhow!("{} is not supported for WebSocket URLs", option));
+    }
+
+    let buffer = Buffer::new(
+        false,
+        args.output.as_deref(),
+        io::stdout().is_terminal() || test_pretend_term(),
+    )?;
+    let print = match args.print {
+        Some(print) => print,
+        None => Print::new(
+            args.verbose,
+            args.headers,
+            args.body,
+            args.meta,
+            args.quiet > 0,
+            args.offline,
+            &buffer,
+        ),
+    };
+    let pretty = args.pretty.unwrap_or_else(|| buffer.guess_pretty());
+    let pretty = match args.color {
+        Some(color) => pretty.with_color(color),
+        None => pretty,
+    };
+    let format_options = args
+        .format_options
+        .iter()
+        .fold(FormatOptions::default(), FormatOptions::merge);
+    let theme = args.style.unwrap_or_default();
+    let mut printer = Printer::new(pretty, theme, true, buffer, format_options);
+
+    let mut request = url.as_str().into_client_request()?;
+    request.headers_mut().extend(headers);
+    if let Some(auth) = &args.auth {
+        let host = url.host_str().unwrap_or("<host>");
+        let value = match Auth::from_str(auth, args.auth_type.unwrap_or_default(), host)? {
+            Auth::Basic(username, password) => format!(
+                "Basic {}",
+                BASE64_STANDARD.encode(format!("{}:{}", username, password.unwrap_or_default()))
+            ),
+            Auth::Bearer(token) => format!("Bearer {}", token),
+            _ => {
+                return Err(anyhow!(
+                    "Only basic and bearer auth are supported for WebSocket URLs"
+                ))
+            }
+        };
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, HeaderValue::from_str(&value)?);
+    }
+    let (mut socket, response) = tungstenite::connect(request)?;
+    if print.response_headers {
+        printer.print_status_and_headers(
+            response.version(),
+            response.status(),
+            response.headers(),
+        )?;
+    }
+    set_read_timeout(&socket, Some(POLL_INTERVAL))?;
+
+    let mut lines = if args.ignore_stdin {
+        None
+    } else {
+        Some(spawn_line_reader())
+    };
+
+    loop {
+        if let Some(receiver) = &lines {
+            match send_pending_lines(&mut socket, receiver, print, &mut printer)? {
+                true => {}
+                false => {
+                    // stdin was closed, so we're done sending. Keep reading
+                    // until the server acknowledges the close.
+                    socket.close(None)?;
+                    lines = None;
+                }
+            }
+        }
+
+        match socket.read() {
+            Ok(Message::Text(text)) if print.response_body => {
+                printer.print_websocket_text(&text)?
+            }
+            Ok(Message::Binary(data)) if print.response_body => {
+                printer.print_websocket_binary(&data)?
+            }
+            // Pings, pongs and close frames are answered by tungstenite
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(0),
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Send all lines that have been read so far. Returns `false` once stdin is exhausted.
+fn send_pending_lines(
+    socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+    receiver: &Receiver<String>,
+    print: Print,
+    printer: &mut Printer,
+) -> Result<bool> {
+    loop {
+        match receiver.try_recv() {
+            Ok(line) => {
+                if print.request_body {
+                    printer.print_websocket_text(&line)?;
+                }
+                socket.send(Message::Text(line))?;
+            }
+            Err(TryRecvError::Empty) => return Ok(true),
+            Err(TryRecvError::Disconnected) => return Ok(false),
+        }
+    }
+}
+
+/// Read stdin on a separate thread so that we can keep printing incoming
+/// messages while waiting for input.
+fn spawn_line_reader() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+    receiver
+}
+
+fn set_read_timeout(
+    socket: &WebSocket<MaybeTlsStream<TcpStream>>,
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(timeout),
+        #[cfg(feature = "native-tls")]
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref().set_read_timeout(timeout),
+        #[cfg(feature = "rustls")]
+        MaybeTlsStream::Rustls(stream) => stream.get_ref().set_read_timeout(timeout),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,291 @@
+//! Just enough DER parsing to pick apart X.509 certificates.
+
+use std::net::IpAddr;
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const OID: u8 = 0x06;
+const OCTET_STRING: u8 = 0x04;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const VERSION: u8 = 0xa0;
+const EXTENSIONS: u8 = 0xa3;
+const DNS_NAME: u8 = 0x82;
+const IP_ADDRESS: u8 = 0x87;
+
+/// 2.5.29.17
+const SUBJECT_ALT_NAME: &[u8] = b"\x55\x1d\x11";
+
+/// A reader for a sequence of DER-encoded values.
+pub struct Der<'a> {
+    data: &'a [u8],
+}
+
+/// A single DER value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    /// The contents, without the tag and length.
+    pub value: &'a [u8],
+    /// The whole encoding, including the tag and length.
+    pub raw: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Der { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Read the next value. Only definite lengths are supported, which is all
+    /// that DER allows.
+    pub fn read(&mut self) -> Option<Tlv<'a>> {
+        let tag = *self.data.first()?;
+        let first = *self.data.get(1)? as usize;
+        let (len, header) = if first < 0x80 {
+            (first, 2)
+        } else {
+            let count = first & 0x7f;
+            if count == 0 || count > 4 {
+                return None;
+            }
+            let bytes = self.data.get(2..2 + count)?;
+            let len = bytes.iter().fold(0, |len, &b| (len << 8) | b as usize);
+            (len, 2 + count)
+        };
+        let raw = self.data.get(..header.checked_add(len)?)?;
+        self.data = &self.data[raw.len()..];
+        Some(Tlv {
+            tag,
+            value: &raw[header..],
+            raw,
+        })
+    }
+
+    /// Read the next value, which must have this tag.
+    pub fn expect(&mut self, tag: u8) -> Option<Tlv<'a>> {
+        self.read().filter(|tlv| tlv.tag == tag)
+    }
+}
+
+/// The fields of the TBSCertificate structure that we use.
+pub struct TbsCertificate<'a> {
+    pub issuer: Tlv<'a>,
+    pub validity: Tlv<'a>,
+    pub subject: Tlv<'a>,
+    pub subject_public_key_info: Tlv<'a>,
+    /// Whatever follows, such as extensions.
+    pub rest: Der<'a>,
+}
+
+/// Parse the outer layers of a DER-encoded certificate.
+pub fn parse_certificate(cert: &[u8]) -> Option<TbsCertificate<'_>> {
+    let certificate = Der::new(cert).expect(SEQUENCE)?;
+    let tbs = Der::new(certificate.value).expect(SEQUENCE)?;
+    let mut fields = Der::new(tbs.value);
+    // The version is optional, the serial number isn't
+    if fields.read()?.tag == VERSION {
+        let _serial = fields.read()?;
+    }
+    let _signature = fields.expect(SEQUENCE)?;
+    let issuer = fields.expect(SEQUENCE)?;
+    let validity = fields.expect(SEQUENCE)?;
+    let subject = fields.expect(SEQUENCE)?;
+    let subject_public_key_info = fields.expect(SEQUENCE)?;
+    Some(TbsCertificate {
+        issuer,
+        validity,
+        subject,
+        subject_public_key_info,
+        rest: fields,
+    })
+}
+
+/// The parts of a certificate that are interesting to a person.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CertificateSummary {
+    pub subject: String,
+    pub issuer: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub alt_names: Vec<String>,
+}
+
+pub fn summarize_certificate(cert: &[u8]) -> Option<CertificateSummary> {
+    let tbs = parse_certificate(cert)?;
+    let mut validity = Der::new(tbs.validity.value);
+    Some(CertificateSummary {
+        subject: format_name(tbs.subject)?,
+        issuer: format_name(tbs.issuer)?,
+        not_before: format_time(validity.read()?)?,
+        not_after: format_time(validity.read()?)?,
+        alt_names: alt_names(tbs.rest).unwrap_or_default(),
+    })
+}
+
+/// Format a distinguished name like "C=US, O=Example, CN=example.com".
+fn format_name(name: Tlv) -> Option<String> {
+    let mut parts = Vec::new();
+    let mut rdns = Der::new(name.value);
+    while !rdns.is_empty() {
+        let mut attributes = Der::new(rdns.expect(SET)?.value);
+        while !attributes.is_empty() {
+            let mut attribute = Der::new(attributes.expect(SEQUENCE)?.value);
+            let oid = attribute.expect(OID)?.value;
+            let value = String::from_utf8_lossy(attribute.read()?.value);
+            let key = match oid {
+                b"\x55\x04\x03" => "CN",
+                b"\x55\x04\x06" => "C",
+                b"\x55\x04\x07" => "L",
+                b"\x55\x04\x08" => "ST",
+                b"\x55\x04\x0a" => "O",
+                b"\x55\x04\x0b" => "OU",
+                _ => continue,
+            };
+            parts.push(format!("{}={}", key, value));
+        }
+    }
+    Some(parts.join(", "))
+}
+
+/// Format a UTCTime or GeneralizedTime like "2024-01-31 12:00:00 UTC".
+fn format_time(time: Tlv) -> Option<String> {
+    let text = std::str::from_utf8(time.value).ok()?.strip_suffix('Z')?;
+    let text = match time.tag {
+        // Two-digit years are 1950 to 2049
+        UTC_TIME if text.get(..2)? < "50" => format!("20{}", text),
+        UTC_TIME => format!("19{}", text),
+        GENERALIZED_TIME => text.to_string(),
+        _ => return None,
+    };
+    if text.len() != 14 || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!(
+        "{}-{}-{} {}:{}:{} UTC",
+        &text[0..4],
+        &text[4..6],
+        &text[6..8],
+        &text[8..10],
+        &text[10..12],
+        &text[12..14]
+    ))
+}
+
+/// The DNS names and IP addresses from the subject alternative name extension.
+fn alt_names(mut rest: Der) -> Option<Vec<String>> {
+    let extensions = loop {
+        let field = rest.read()?;
+        if field.tag == EXTENSIONS {
+            break field;
+        }
+    };
+    let mut extensions = Der::new(Der::new(extensions.value).expect(SEQUENCE)?.value);
+    while !extensions.is_empty() {
+        let mut extension = Der::new(extensions.expect(SEQUENCE)?.value);
+        if extension.expect(OID)?.value != SUBJECT_ALT_NAME {
+            continue;
+        }
+        // Skip the optional critical flag
+        let value = loop {
+            let field = extension.read()?;
+            if field.tag == OCTET_STRING {
+                break field;
+            }
+        };
+        let mut names = Der::new(Der::new(value.value).expect(SEQUENCE)?.value);
+        let mut alt_names = Vec::new();
+        while let Some(name) = names.read() {
+            match name.tag {
+                DNS_NAME => alt_names.push(String::from_utf8_lossy(name.value).into_owned()),
+                IP_ADDRESS => {
+                    let ip = match name.value.len() {
+                        4 => IpAddr::from(<[u8; 4]>::try_from(name.value).ok()?),
+                        16 => IpAddr::from(<[u8; 16]>::try_from(name.value).ok()?),
+                        _ => continue,
+                    };
+                    alt_names.push(ip.to_string());
+                }
+                _ => {}
+            }
+        }
+        return Some(alt_names);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_der() {
+        let mut der = Der::new(b"\x02\x01\x05\x04\x81\x03abc\x30\x00");
+        assert_eq!(der.read().unwrap().value, b"\x05");
+        let tlv = der.read().unwrap();
+        assert_eq!(
+            (tlv.tag, tlv.value, tlv.raw),
+            (0x04, &b"abc"[..], &b"\x04\x81\x03abc"[..])
+        );
+        assert_eq!(der.expect(SEQUENCE).unwrap().value, b"");
+        assert_eq!(der.read(), None);
+
+        assert_eq!(Der::new(b"\x04\x05abc").read(), None);
+        assert_eq!(Der::new(b"\x04\x80").read(), None);
+    }
+
+    #[test]
+    fn parse_cert() {
+        let pem = pem::parse(include_str!(
+            "../tests/fixtures/certs/wildcard-self-signed.pem"
+        ))
+        .unwrap();
+        let tbs = parse_certificate(pem.contents()).unwrap();
+        let mut spki = Der::new(tbs.subject_public_key_info.value);
+        // The algorithm, followed by the key as a bit string
+        assert_eq!(spki.read().unwrap().tag, SEQUENCE);
+        assert_eq!(spki.read().unwrap().tag, 0x03);
+        assert_eq!(spki.read(), None);
+        assert!(parse_certificate(&pem.contents()[..100]).is_none());
+
+        let name = "C=US, ST=California, L=San Francisco, O=BadSSL, CN=*.badssl.com";
+        assert_eq!(
+            summarize_certificate(pem.contents()).unwrap(),
+            CertificateSummary {
+                subject: name.to_string(),
+                issuer: name.to_string(),
+                not_before: "2016-08-08 21:17:05 UTC".to_string(),
+                not_after: "2018-08-08 21:17:05 UTC".to_string(),
+                alt_names: vec!["*.badssl.com".to_string(), "badssl.com".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn times() {
+        let time = |tag, text: &[u8]| {
+            format_time(Tlv {
+                tag,
+                value: text,
+                raw: text,
+            })
+        };
+        assert_eq!(
+            time(UTC_TIME, b"491231235959Z").unwrap(),
+            "2049-12-31 23:59:59 UTC"
+        );
+        assert_eq!(
+            time(UTC_TIME, b"500101000000Z").unwrap(),
+            "1950-01-01 00:00:00 UTC"
+        );
+        assert_eq!(
+            time(GENERALIZED_TIME, b"20510101000000Z").unwrap(),
+            "2051-01-01 00:00:00 UTC"
+        );
+        assert_eq!(time(UTC_TIME, b"5001010000Z"), None);
+        assert_eq!(time(OCTET_STRING, b"20510101000000Z"), None);
+    }
+}
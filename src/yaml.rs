@@ -0,0 +1,107 @@
+//! Converting YAML request bodies to JSON, for --yaml.
+
+use std::fs;
+use std::io::{self, Read};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{Map, Number, Value};
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::request_items::Body;
+
+/// Parse a YAML document and convert it to the equivalent JSON.
+pub fn yaml_to_json(text: &str) -> Result<Value> {
+    let mut docs = YamlLoader::load_from_str(text).context("Invalid YAML")?;
+    match docs.len() {
+        0 => Ok(Value::Null),
+        1 => convert(docs.remove(0)),
+        _ => Err(anyhow!(
+            "Expected a single YAML document, found {}",
+            docs.len()
+        )),
+    }
+}
+
+/// Replace a raw, stdin or file body with its YAML contents converted to JSON.
+///
+/// Bodies built from request items are already JSON and are left alone.
+pub fn convert_body(body: Body) -> Result<Body> {
+    let text = match body {
+        Body::Raw(raw) => String::from_utf8(raw).context("YAML body is not valid UTF-8")?,
+        Body::Stdin => {
+            let mut raw = Vec::new();
+            io::stdin().read_to_end(&mut raw)?;
+            String::from_utf8(raw).context("YAML body is not valid UTF-8")?
+        }
+        Body::File { file_name, .. } => fs::read_to_string(&file_name)
+            .with_context(|| format!("Failed to read {:?}", file_name))?,
+        body => return Ok(body),
+    };
+    let json = yaml_to_json(&text)?;
+    Ok(Body::Raw(serde_json::to_vec(&json)?))
+}
+
+fn convert(yaml: Yaml) -> Result<Value> {
+    Ok(match yaml {
+        Yaml::Null => Value::Null,
+        Yaml::Boolean(value) => Value::Bool(value),
+        Yaml::Integer(value) => Value::from(value),
+        Yaml::Real(text) => match text.parse::<f64>().ok().and_then(Number::from_f64) {
+            Some(number) => Value::Number(number),
+            // .inf and .nan can't be represented in JSON
+            None => Value::String(text),
+        },
+        Yaml::String(text) => Value::String(text),
+        Yaml::Array(items) => Value::Array(items.into_iter().map(convert).collect::<Result<_>>()?),
+        Yaml::Hash(hash) => {
+            let mut map = Map::new();
+            for (key, value) in hash {
+                let key = match key {
+                    Yaml::String(key) | Yaml::Real(key) => key,
+                    Yaml::Integer(key) => key.to_string(),
+                    Yaml::Boolean(key) => key.to_string(),
+                    Yaml::Null => "null".to_string(),
+                    _ => return Err(anyhow!("YAML keys must be scalars")),
+                };
+                map.insert(key, convert(value)?);
+            }
+            Value::Object(map)
+        }
+        Yaml::Alias(_) => return Err(anyhow!("YAML aliases are not supported")),
+        Yaml::BadValue => return Err(anyhow!("Invalid YAML value")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn convert_yaml() {
+        let text = "\
+name: xh
+version: 1
+ratio: 0.5
+tags: [cli, http]
+nested:
+  enabled: true
+  empty: ~
+  1: one
+";
+        assert_eq!(
+            yaml_to_json(text).unwrap(),
+            json!({
+                "name": "xh",
+                "version": 1,
+                "ratio": 0.5,
+                "tags": ["cli", "http"],
+                "nested": {"enabled": true, "empty": null, "1": "one"}
+            })
+        );
+        assert_eq!(yaml_to_json("").unwrap(), Value::Null);
+        assert!(yaml_to_json("a: [").is_err());
+        assert!(yaml_to_json("a: 1\n---\nb: 2\n").is_err());
+    }
+}